@@ -1,6 +1,5 @@
 use bulk_examples_generator::config::*;
 use bulk_examples_generator::generate_examples;
-use criterion::*;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use std::time::Instant;
 
@@ -24,7 +23,8 @@ fn basic_benchmark(c: &mut Criterion) {
                 black_box("sentence".to_string()),
                 black_box(&gen_config),
                 black_box(&exe_config),
-            );
+            )
+            .unwrap();
         })
     });
 }
@@ -33,10 +33,12 @@ fn basic_benchmark(c: &mut Criterion) {
 /// Add Rc to processing stack 30 - 40 KiB/sec
 fn throughput_sequential_benchmark_readme_example(c: &mut Criterion) {
     // let config: GeneratorConfig = GeneratorConfig::benchmark();
-    let mut exe_config: ExecutorConfig = Default::default();
-    exe_config.parallel_mode = false;
-    exe_config.print_stdout = false;
-    exe_config.return_vec = true;
+    let exe_config = ExecutorConfig {
+        parallel_mode: false,
+        print_stdout: false,
+        return_vec: true,
+        ..Default::default()
+    };
 
     let mut gen_config: GeneratorConfig = Default::default();
     gen_config.terminals_limit = Some(500);
@@ -71,18 +73,19 @@ fn throughput_sequential_benchmark_readme_example(c: &mut Criterion) {
                 black_box("start".to_string()),
                 black_box(&gen_config),
                 black_box(&exe_config),
-            );
+            )
+            .unwrap();
             let elapsed = (Instant::now() - it).as_secs_f64();
             let sum_len = s
                 .iter()
                 .map(|x| x.as_ref().unwrap())
-                .fold(String::new(), |acc, s| acc + &s)
+                .fold(String::new(), |acc, s| acc + s)
                 .len();
             let bytes_per_sec = sum_len as f64 / elapsed;
             // println!("{:?}", s);
             // println!("{}", sum_len);
             // print!("MiB/sec: {:12.4}\n", bytes_per_sec / 1024. / 1024.);
-            print!("KiB/sec: {:12.8}\n", bytes_per_sec / 1024.);
+            println!("KiB/sec: {:12.8}", bytes_per_sec / 1024.);
         })
     });
     group.finish();
@@ -1,20 +1,13 @@
-use config::ConfigError;
 use serde_derive::{Deserialize, Serialize};
-use std::path::PathBuf;
-
-enum OutputType {
-    Debug,
-    Stdout,
-    File,
-    Folder,
-}
+use std::path::{Path, PathBuf};
 
 /// Struct for define the config of the execution
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ExecutorConfig {
     /// This parameter is just for avoid print elements or create a vec with them, because we only want to benchmark the generation
     pub benchmark_mode: bool,
-    /// Enable or disable parallel creation, default: true
+    /// Enable or disable parallel creation, default: true. With `coverage_guided` and a seed in the config of the
+    /// generator the examples are always generated in order, so the same seed returns the same examples
     pub parallel_mode: bool,
     /// Print progress bar
     pub print_progress_bar: bool,
@@ -22,16 +15,106 @@ pub struct ExecutorConfig {
     pub print_progress_text: bool,
     /// Print additional info
     pub print_debug: bool,
-    /// Print every example in stdout
+    /// Print every example in stdout, followed by `separator`
     pub print_stdout: bool,
-    /// Create one file for all examples
+    /// Write all the examples in one file, every example followed by `separator`
     pub print_file: Option<PathBuf>,
+    /// Text written after every example in stdout and in `print_file`, e.g. "\n" (default), "\0" or "\n---\n". Use a
+    /// separator that the examples can't contain, e.g. "\0" when the examples contain newlines
+    pub separator: String,
+    /// Write the examples in stdout and in `print_file` in order (example #1, #2, ...) in parallel mode, the examples
+    /// that finish before the previous ones are kept in memory until they can be written. To bound that memory the
+    /// examples are generated in windows of 4096, every window waits for the slowest example of the previous one
+    pub ordered_output: bool,
+    /// Format of the examples written in stdout and in `print_file`
+    pub output_format: OutputFormat,
+    /// Add to the JSON Lines and CSV records how many times every rule was expanded in the example, with the text
+    /// format they're only counted for the sinks of `generate_examples_with_sinks`
+    pub rule_hits: bool,
+    /// Split `print_file` in several files (shards), see `Sharding`
+    pub sharding: Option<Sharding>,
+    /// Compress `print_file` (every shard) and the files of `print_folder`, see `Compression`. When it's `None` the
+    /// compression is inferred from the extension of every file (`.gz` or `.zst`)
+    pub compression: Option<Compression>,
     /// Create a file for every example
     /// (name_format, path)
     /// Name of the files, e.g. html-test-{}.html, {} will be used for enumerating the example
     pub print_folder: Option<(String, PathBuf)>,
+    /// Write the files of `print_folder` in one tar archive instead of the folder, the path of `print_folder` is the
+    /// path of the archive (`.tar` is added if it's missing). A path ending in `.tar`, `.tar.gz`, `.tgz` or
+    /// `.tar.zst` is always an archive
+    pub tar_folder: bool,
     /// Return all examples generated in a vec
     pub return_vec: bool,
+    /// Stop the generation when all the alternatives of the grammar have been generated, only with `coverage_guided`
+    /// in the config of the generator
+    pub stop_on_full_coverage: bool,
+    /// Print the final coverage in stderr, only with `coverage_guided` in the config of the generator
+    pub print_coverage: bool,
+}
+
+/// Format of the examples written in stdout and in the file of `ExecutorConfig`
+///
+/// The JSON Lines and CSV formats write one record per example with the fields `index`, `start_rule`, `example`,
+/// `length` (bytes), `ok`, `error` and `seed`, plus the hits of every rule with `rule_hits` (`rule_hits` object in
+/// JSON Lines, one `hits_<rule>` column per rule of the grammar in CSV). The examples that couldn't be generated
+/// have `ok` false and the `error`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// The example followed by `separator`
+    Text,
+    /// One JSON object per line
+    Jsonl,
+    /// CSV with a header
+    Csv,
+}
+
+/// Split of the output file in shards, for corpora too big for one file (or for one file per example)
+///
+/// `print_file` is the template of the shards, `{shard}` is replaced with the number of the shard starting in 1, e.g.
+/// `corpus-{shard}.jsonl`. Without `{shard}` the number is added before the extension (`corpus.jsonl` is
+/// `corpus-1.jsonl`, `corpus-2.jsonl`, ...). A new shard begins when the current one has `max_examples` examples or
+/// when the next example would exceed `max_bytes` (a shard has at least one example). Every CSV shard has the header
+///
+/// A JSON manifest with the file name, the examples and the bytes of every shard is written in `manifest`, by
+/// default in the folder of the shards and named after the template, e.g. `corpus-manifest.json` for
+/// `corpus-{shard}.jsonl` or `corpus.jsonl`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sharding {
+    pub max_examples: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub manifest: Option<PathBuf>,
+}
+
+/// Compression of the files written by `ExecutorConfig`
+///
+/// With an explicit compression the extension (`.gz` or `.zst`) is added to the files that don't have it, e.g.
+/// `corpus.jsonl` is `corpus.jsonl.gz`. The sizes of `Sharding` are measured before the compression
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Extension of the compressed files, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// Compression of a file with the extension `.gz` (or `.tgz`) or `.zst`, `None` for the others
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" | "tgz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
 }
 
 impl Default for ExecutorConfig {
@@ -44,8 +127,17 @@ impl Default for ExecutorConfig {
             print_debug: false,
             print_stdout: true,
             print_file: None,
+            separator: "\n".to_string(),
+            ordered_output: false,
+            output_format: OutputFormat::Text,
+            rule_hits: false,
+            sharding: None,
+            compression: None,
             print_folder: None,
+            tar_folder: false,
             return_vec: false,
+            stop_on_full_coverage: false,
+            print_coverage: false,
         }
     }
 }
@@ -60,8 +152,9 @@ impl ExecutorConfig {
     /// `let default: ExecutorConfig = Default::default();`
     ///
     pub fn benchmark() -> Self {
-        let mut settings: Self = Default::default();
-        settings.benchmark_mode = true;
-        settings
+        ExecutorConfig {
+            benchmark_mode: true,
+            ..Default::default()
+        }
     }
 }
@@ -1,5 +1,6 @@
 use config::ConfigError;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Struct for define the config of the generator
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -79,9 +80,68 @@ pub struct GeneratorConfig {
     /// When generator finds an expression !A ~ B
     /// It has to generate B and then probe that is not A
     /// here you can limit the times that B is generated and compared with A
+    /// the same limit is used for &A ~ B, where B has to begin with A
     /// for more details please refer to README
     /// _default value:_ 100
     pub max_attempts_negation: u32,
+
+    /// Max attempts to generate an invalid example, in every attempt an expression of the grammar is mutated and an
+    /// example is generated, the example is used only if the grammar rejects it
+    /// _default value:_ 100
+    pub max_attempts_invalid: u32,
+
+    /// Number of subtrees replaced in every variant of a seed (mutate mode)
+    /// _default value:_ 1
+    pub mutations_per_example: u32,
+
+    /// Max attempts to replace a subtree of a seed, the variant is used only if the grammar can parse it
+    /// _default value:_ 100
+    pub max_attempts_mutation: u32,
+
+    /// When a limit (hard_limit, limit_depth_level or rule_expand_limit) is reached the example is returned
+    /// incomplete, with this parameter the example returns an error instead
+    /// _default value:_ false
+    pub error_on_limit: bool,
+
+    /// Seed used for generate the examples, every example gets its own random generator derived from the seed and
+    /// the index of the example, so the example #n is always the same in parallel and sequential mode
+    /// _default value:_ None (Random seed for every example)
+    pub seed: Option<u64>,
+
+    /// pest accepts WHITESPACE and COMMENT between the elements of a sequence or a repetition in non atomic rules,
+    /// if the grammar has these rules they are generated with this probability, every extra WHITESPACE or COMMENT
+    /// has the same probability (at most upper_bound_zero_or_more_repetition elements). Nothing is generated inside
+    /// atomic rules `@{ ... }` or `${ ... }`. It's clamped to [0, 1], NaN and the infinities are taken as 0
+    /// _default value:_ 0.5
+    pub implicit_whitespace_probability: f64,
+
+    /// Weights of the alternatives of a rule `a | b | c`, e.g. `keyword = [10, 5, 1]` generates the first
+    /// alternative 10 times more often than the last one, the alternatives without weight have weight 1.
+    /// It's the same of writing `"|WEIGHT|n|"` at the beginning of every alternative
+    /// _default value:_ empty
+    #[serde(default)]
+    pub weights: HashMap<String, Vec<u32>>,
+
+    /// Coverage-guided generation, the alternatives of the choices and the bounds of the repetitions not generated
+    /// yet are preferred, so the rare alternatives are generated sooner
+    /// _default value:_ false
+    #[serde(default)]
+    pub coverage_guided: bool,
+
+    /// Max repetitions of `e*`, `e+`, `e{n,}`, `e{,n}` and `e{m, n}` in the exhaustive enumeration, the minimum of the
+    /// repetition is always generated
+    /// _default value:_ 2
+    pub max_enumeration_repetitions: u32,
+
+    /// Max characters taken from every range `'a'..'z'` and Unicode rule in the exhaustive enumeration
+    /// _default value:_ 128
+    pub max_enumeration_range_chars: u32,
+
+    /// When a limit is reached (hard_limit, limit_depth_level or rule_expand_limit) the rules still open are closed
+    /// with their shortest text, so the truncated examples can be parsed. If it's false or a rule doesn't have a
+    /// finite derivation, text_expand_limit is used
+    /// _default value:_ true
+    pub shortest_completion_on_limit: bool,
 }
 
 impl GeneratorConfig {
@@ -96,17 +156,30 @@ impl GeneratorConfig {
     pub fn new(config_file: &str) -> Result<Self, ConfigError> {
         let mut settings = config::Config::default();
         settings
-            .merge(config::File::with_name("src/config/default.toml"))
-            .unwrap()
-            .merge(config::File::with_name(config_file))
-            .unwrap()
+            .merge(config::File::with_name("src/config/default.toml"))?
+            .merge(config::File::with_name(config_file))?
             // Add in settings from the environment (with a prefix of APP)
             // Eg.. `APP_DEBUG=1 ./target/app` would set the `debug` key
-            .merge(config::Environment::with_prefix("APP"))
-            .unwrap();
+            .merge(config::Environment::with_prefix("APP"))?;
 
         settings.try_into()
     }
+
+    /// Load the weights of the alternatives from a TOML file, every rule is a list of weights
+    ///
+    /// ```toml
+    /// keyword = [10, 5, 1]
+    /// ```
+    ///
+    /// The weights of the file replace the weights of the same rules in the config
+    pub fn load_weights(&mut self, weights_file: &str) -> Result<(), ConfigError> {
+        let mut settings = config::Config::default();
+        settings.merge(config::File::with_name(weights_file))?;
+
+        let weights: HashMap<String, Vec<u32>> = settings.try_into()?;
+        self.weights.extend(weights);
+        Ok(())
+    }
 }
 
 impl Default for GeneratorConfig {
@@ -125,6 +198,17 @@ impl Default for GeneratorConfig {
             upper_bound_one_or_more_repetition: 5,
             upper_bound_at_least_repetition: 10,
             max_attempts_negation: 100,
+            max_attempts_invalid: 100,
+            mutations_per_example: 1,
+            max_attempts_mutation: 100,
+            error_on_limit: false,
+            seed: None,
+            implicit_whitespace_probability: 0.5,
+            weights: HashMap::new(),
+            coverage_guided: false,
+            max_enumeration_repetitions: 2,
+            max_enumeration_range_chars: 128,
+            shortest_completion_on_limit: true,
         }
     }
 }
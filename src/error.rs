@@ -0,0 +1,171 @@
+use config::ConfigError;
+use std::fmt;
+use std::io;
+
+/// Errors returned by the functions of the crate
+#[derive(Debug)]
+pub enum Error {
+    /// The grammar doesn't follow the pest syntax
+    GrammarSyntax(GrammarError),
+    /// The grammar follows the pest syntax but it isn't valid, e.g. undefined rules or left recursion
+    GrammarValidation(Vec<GrammarError>),
+    /// The start rule doesn't exist in the grammar
+    UnknownStartRule(String),
+    /// The input can't be parsed with the grammar
+    Parse(ParseError),
+    /// An example couldn't be generated
+    Generation(GenerationError),
+    /// The example to shrink doesn't satisfy the predicate
+    ExampleNotFailing,
+    /// Error reading or writing files
+    Io(io::Error),
+    /// A config or weights file couldn't be loaded
+    Config(ConfigError),
+}
+
+/// Error found in a grammar, `from` and `to` are the (line, column) where the error begins and ends, starting in 0
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrammarError {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub message: String,
+}
+
+/// Error parsing an input with a grammar, `from` and `to` are the (line, column) where the error begins and ends,
+/// starting in 0
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Rule used to begin the parsing
+    pub rule: String,
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    /// Error message of pest
+    pub message: String,
+}
+
+/// Errors that can happen while an example is being generated
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GenerationError {
+    /// The random selection between the alternatives of a choice didn't return an element
+    RandomChoose,
+    /// The blacklist doesn't allow to open any rule, the generation can't continue
+    LoopDetected,
+    /// A rule used in the grammar doesn't exist
+    RuleNotFound(String),
+    /// A limit of the generation was reached (only with `error_on_limit`), it contains the name of the parameter
+    LimitReached(String),
+    /// All the attempts to generate `a` in `!b ~ a` were rejected, it contains the expression `!b ~ a`
+    NegationAttemptsExhausted(String),
+    /// All the attempts to generate `a` in `&b ~ a` were rejected, it contains the expression `&b ~ a`
+    LookaheadAttemptsExhausted(String),
+    /// None of the mutated examples was rejected by the grammar, it contains the start rule
+    InvalidAttemptsExhausted(String),
+    /// None of the mutations of a seed could be parsed with the grammar, it contains the start rule
+    MutationAttemptsExhausted(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::GrammarSyntax(error) => write!(f, "Grammar syntax error {}", error),
+            Error::GrammarValidation(errors) => {
+                write!(f, "Invalid grammar:")?;
+                for error in errors {
+                    write!(f, "\n{}", error)?;
+                }
+                Ok(())
+            }
+            Error::UnknownStartRule(rule) => {
+                write!(f, "The start rule doesn't exist in the grammar: {}", rule)
+            }
+            Error::Parse(error) => write!(f, "{}", error),
+            Error::Generation(error) => write!(f, "{}", error),
+            Error::ExampleNotFailing => write!(f, "The example to shrink doesn't fail"),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Config(error) => write!(f, "Invalid config: {}", error),
+        }
+    }
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}, {}) - ({}, {}): {}",
+            self.from.0, self.from.1, self.to.0, self.to.1, self.message
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unable to parse with rule {}\n{}",
+            self.rule, self.message
+        )
+    }
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::RandomChoose => write!(f, "Unable to choose an alternative"),
+            GenerationError::LoopDetected => write!(
+                f,
+                "Loop detected in grammar, the execution has been stopped"
+            ),
+            GenerationError::RuleNotFound(name) => {
+                write!(f, "The rule hasn't been found: {}", name)
+            }
+            GenerationError::LimitReached(limit) => {
+                write!(f, "The limit {} has been reached", limit)
+            }
+            GenerationError::NegationAttemptsExhausted(expr)
+            | GenerationError::LookaheadAttemptsExhausted(expr) => {
+                write!(f, "Too many attempts to generate {}", expr)
+            }
+            GenerationError::InvalidAttemptsExhausted(rule) => {
+                write!(
+                    f,
+                    "Too many attempts to generate an invalid example of {}",
+                    rule
+                )
+            }
+            GenerationError::MutationAttemptsExhausted(rule) => {
+                write!(f, "Too many attempts to mutate a seed of {}", rule)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Generation(error) => Some(error),
+            Error::Io(error) => Some(error),
+            Error::Config(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+impl From<GenerationError> for Error {
+    fn from(error: GenerationError) -> Self {
+        Error::Generation(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(error: ConfigError) -> Self {
+        Error::Config(error)
+    }
+}
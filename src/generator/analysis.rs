@@ -0,0 +1,629 @@
+use super::shortest::minimal_derivations;
+use super::{branch_weight, is_marker, rule_atomicity, starts_with_predicate, InputData};
+use crate::config::GeneratorConfig;
+
+use pest_meta::ast::Expr;
+use serde_derive::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+/// Static analysis of a grammar from a start rule, it finds the rules that make the generation hang or spin until
+/// the `hard_limit`
+///
+/// - `unreachable_rules`: rules never used from the start rule (WHITESPACE and COMMENT are used implicitly by the
+///   non atomic rules)
+/// - `non_productive_rules`: rules that can't derive a finite string, e.g. `a = { "x" ~ a }`
+/// - `unbounded_rules` and `unbounded_alternatives`: rules and alternatives whose expected expansion size is
+///   unbounded with the repetition bounds and weights of the config, e.g. with the default config
+///   `list = { "[" ~ (list ~ ",")* ~ "]" }` expands 2 lists on average in every list
+/// - `unsupported_predicates`: predicates (`!a` and `&a`) the generator can't honor
+///
+/// The last three only include the rules reachable from the start rule.
+///
+/// ```
+/// use bulk_examples_generator::{analyze_grammar, compile_grammar};
+///
+/// let grammar = r#"
+///         list = { "[" ~ (list ~ ",")* ~ "]" }
+///         loop = { "x" ~ loop }
+///         start = { list ~ !"y" }
+///     "#;
+///
+/// let analysis = analyze_grammar(&compile_grammar(grammar.to_string()).unwrap(), "start").unwrap();
+/// assert_eq!(vec!["loop"], analysis.unreachable_rules);
+/// assert_eq!(vec!["list", "start"], analysis.unbounded_rules);
+/// assert_eq!(1, analysis.unsupported_predicates.len());
+/// println!("{}", analysis);
+/// ```
+#[derive(Clone, Debug, Serialize)]
+pub struct GrammarAnalysis {
+    /// Rule used as start of the analysis
+    pub start_rule: String,
+    /// Rules never used from the start rule, sorted
+    pub unreachable_rules: Vec<String>,
+    /// Rules that can't derive a finite string, sorted
+    pub non_productive_rules: Vec<String>,
+    /// Rules with unbounded expected expansion size, sorted
+    pub unbounded_rules: Vec<String>,
+    /// Alternatives with unbounded expected expansion size, sorted by rule
+    pub unbounded_alternatives: Vec<UnboundedAlternative>,
+    /// Predicates the generator can't honor, sorted by rule
+    pub unsupported_predicates: Vec<UnsupportedPredicate>,
+}
+
+/// Alternative of a choice whose expected expansion size is unbounded
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UnboundedAlternative {
+    /// Rule that contains the choice
+    pub rule: String,
+    /// Position of the alternative in the rule, numbered like in `AlternativeHits`
+    pub index: usize,
+    /// Expression of the alternative
+    pub alternative: String,
+}
+
+/// Predicate the generator can't honor
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UnsupportedPredicate {
+    /// Rule that contains the predicate
+    pub rule: String,
+    /// Expression of the predicate
+    pub predicate: String,
+    pub reason: PredicateIssue,
+}
+
+/// Why a predicate can't be honored by the generator
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PredicateIssue {
+    /// The predicate isn't at the beginning of a sequence followed by more expressions, e.g. `a ~ !b` or `a ~ &b ~ c`,
+    /// the generator ignores it
+    NotLeadingSequence,
+    /// The predicate uses the stack (`PEEK`, `POP`, ...), it's checked with an empty stack
+    UsesStack,
+}
+
+impl fmt::Display for PredicateIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            PredicateIssue::NotLeadingSequence => {
+                "ignored, it isn't at the beginning of a sequence followed by more expressions"
+            }
+            PredicateIssue::UsesStack => "checked with an empty stack",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+impl GrammarAnalysis {
+    /// Analiza la gramática desde `start_rule`, la regla debe existir
+    pub(crate) fn new(input_data: &InputData, config: &GeneratorConfig, start_rule: &str) -> Self {
+        let grammar = &input_data.clean_grammar;
+        let reachable = reachable_rules(input_data, start_rule);
+
+        let mut unreachable_rules: Vec<String> = grammar
+            .rules
+            .keys()
+            .filter(|name| !reachable.contains(*name))
+            .cloned()
+            .collect();
+        unreachable_rules.sort();
+
+        let derivations = minimal_derivations(grammar, &input_data.unicode_ranges);
+        let non_productive_rules = reachable
+            .iter()
+            .filter(|name| !derivations.contains_key(*name))
+            .cloned()
+            .collect();
+
+        // Los tamaños esperados usan la gramática con los pesos
+        let expected = ExpectedSizes::new(input_data, config);
+        let unbounded_rules = reachable
+            .iter()
+            .filter(|name| expected.sizes[*name].is_infinite())
+            .cloned()
+            .collect();
+
+        let mut unbounded_alternatives = vec![];
+        let mut unsupported_predicates = vec![];
+        for name in &reachable {
+            let expr = &input_data.grammar.rules[name].expr;
+            let mut index = 0;
+            expected.unbounded_alternatives(name, expr, &mut index, &mut unbounded_alternatives);
+            predicates(name, &grammar.rules[name].expr, &mut unsupported_predicates);
+        }
+
+        GrammarAnalysis {
+            start_rule: start_rule.to_string(),
+            unreachable_rules,
+            non_productive_rules,
+            unbounded_rules,
+            unbounded_alternatives,
+            unsupported_predicates,
+        }
+    }
+
+    /// Returns true if the analysis didn't find any problem
+    pub fn is_clean(&self) -> bool {
+        self.unreachable_rules.is_empty()
+            && self.non_productive_rules.is_empty()
+            && self.unbounded_rules.is_empty()
+            && self.unbounded_alternatives.is_empty()
+            && self.unsupported_predicates.is_empty()
+    }
+
+    /// Analysis in JSON format
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// One section for every kind of problem found
+impl fmt::Display for GrammarAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Analysis from the rule {}", self.start_rule)?;
+        if self.is_clean() {
+            return writeln!(f, "\nNo problems found");
+        }
+
+        let sections = [
+            ("Unreachable rules:", &self.unreachable_rules),
+            (
+                "Rules that can't derive a finite string:",
+                &self.non_productive_rules,
+            ),
+            ("Rules with unbounded expected size:", &self.unbounded_rules),
+        ];
+        for (title, rules) in sections.iter() {
+            if !rules.is_empty() {
+                write!(f, "\n{}", title)?;
+                for rule in rules.iter() {
+                    write!(f, "\n  {}", rule)?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        if !self.unbounded_alternatives.is_empty() {
+            write!(f, "\nAlternatives with unbounded expected size:")?;
+            for alternative in &self.unbounded_alternatives {
+                write!(
+                    f,
+                    "\n  {} #{}: {}",
+                    alternative.rule, alternative.index, alternative.alternative
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        if !self.unsupported_predicates.is_empty() {
+            write!(f, "\nPredicates the generator can't honor:")?;
+            for predicate in &self.unsupported_predicates {
+                write!(
+                    f,
+                    "\n  {}: {} ({})",
+                    predicate.rule, predicate.predicate, predicate.reason
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reglas alcanzables desde `start_rule`, ordenadas
+///
+/// WHITESPACE y COMMENT se alcanzan si alguna regla alcanzable no es atómica, igual que en la generación
+pub(super) fn reachable_rules(input_data: &InputData, start_rule: &str) -> BTreeSet<String> {
+    let grammar = &input_data.clean_grammar;
+    let mut reachable = BTreeSet::new();
+    // Regla y atomicidad heredada
+    let mut visited = HashSet::new();
+    let mut pending = vec![(start_rule.to_string(), false)];
+    while let Some((name, inherited)) = pending.pop() {
+        if !visited.insert((name.clone(), inherited)) {
+            continue;
+        }
+        let rule = &grammar.rules[&name];
+        let atomic = rule_atomicity(rule, inherited);
+        reachable.insert(name);
+
+        rule.expr.clone().map_bottom_up(|expr| {
+            if let Expr::Ident(ident) = &expr {
+                if grammar.rules.contains_key(ident) {
+                    pending.push((ident.clone(), atomic));
+                }
+            }
+            expr
+        });
+
+        if !atomic {
+            for trivia in &["WHITESPACE", "COMMENT"] {
+                if grammar.rules.contains_key(*trivia) {
+                    pending.push((trivia.to_string(), true));
+                }
+            }
+        }
+    }
+    reachable
+}
+
+/// Adiciona los predicados que el generador no puede cumplir, recorriendo las secuencias igual que el generador:
+/// en `!A ~ B` y `&A ~ B` se comprueba A con el texto de B, los demás predicados se ignoran
+fn predicates(rule: &str, expr: &Expr, unsupported: &mut Vec<UnsupportedPredicate>) {
+    let mut add = |predicate: &Expr, reason: PredicateIssue| {
+        unsupported.push(UnsupportedPredicate {
+            rule: rule.to_string(),
+            predicate: predicate.to_string(),
+            reason,
+        })
+    };
+
+    match expr {
+        Expr::Seq(lhs, rhs) => match &**lhs {
+            Expr::NegPred(inner) | Expr::PosPred(inner) => {
+                if uses_stack(inner) {
+                    add(lhs, PredicateIssue::UsesStack);
+                }
+                predicates(rule, rhs, unsupported);
+            }
+            Expr::Seq(inner_lhs, inner_rhs) if starts_with_predicate(inner_lhs) => {
+                let regrouped = Expr::Seq(
+                    inner_lhs.clone(),
+                    Box::new(Expr::Seq(inner_rhs.clone(), rhs.clone())),
+                );
+                predicates(rule, &regrouped, unsupported);
+            }
+            _ => {
+                predicates(rule, lhs, unsupported);
+                predicates(rule, rhs, unsupported);
+            }
+        },
+        Expr::NegPred(_) | Expr::PosPred(_) => add(expr, PredicateIssue::NotLeadingSequence),
+        Expr::Choice(lhs, rhs) => {
+            predicates(rule, lhs, unsupported);
+            predicates(rule, rhs, unsupported);
+        }
+        Expr::Opt(inner)
+        | Expr::Rep(inner)
+        | Expr::RepOnce(inner)
+        | Expr::RepExact(inner, _)
+        | Expr::RepMin(inner, _)
+        | Expr::RepMax(inner, _)
+        | Expr::RepMinMax(inner, _, _)
+        | Expr::Push(inner) => predicates(rule, inner, unsupported),
+        _ => {}
+    }
+}
+
+/// Retorna true si la expresión usa el stack de valores
+fn uses_stack(expr: &Expr) -> bool {
+    let mut stack = false;
+    expr.clone().map_bottom_up(|expr| {
+        match &expr {
+            Expr::Ident(name)
+                if ["PEEK", "POP", "DROP", "PEEK_ALL", "POP_ALL"].contains(&name.as_str()) =>
+            {
+                stack = true
+            }
+            Expr::PeekSlice(_, _) => stack = true,
+            _ => {}
+        }
+        expr
+    });
+    stack
+}
+
+/// Tamaño esperado de una expresión: una constante más la cantidad esperada de expansiones de cada regla
+#[derive(Default)]
+struct Linear {
+    constant: f64,
+    rules: HashMap<String, f64>,
+}
+
+/// Tamaño esperado (cantidad de terminales y reglas expandidas) de cada regla con la configuración del generador
+///
+/// El tamaño de cada regla es `1 + constante + Σ cantidad esperada de s * tamaño de s`, un sistema lineal que se
+/// resuelve por componentes fuertemente conexas. Una componente tiene tamaño finito solo si el sistema tiene una
+/// solución positiva (el radio espectral de la matriz de cantidades es menor a 1), si no su tamaño es infinito
+struct ExpectedSizes<'a> {
+    input_data: &'a InputData,
+    config: &'a GeneratorConfig,
+    sizes: HashMap<String, f64>,
+}
+
+impl<'a> ExpectedSizes<'a> {
+    fn new(input_data: &'a InputData, config: &'a GeneratorConfig) -> Self {
+        let mut expected = ExpectedSizes {
+            input_data,
+            config,
+            sizes: HashMap::new(),
+        };
+
+        let mut names: Vec<&String> = input_data.grammar.rules.keys().collect();
+        names.sort();
+        let bodies: HashMap<&String, Linear> = names
+            .iter()
+            .map(|name| {
+                let mut linear = Linear::default();
+                expected.linear(&input_data.grammar.rules[*name].expr, 1.0, &mut linear);
+                (*name, linear)
+            })
+            .collect();
+
+        // Las componentes se obtienen en orden topológico inverso, las reglas usadas se resuelven primero
+        for component in components(&names, &bodies) {
+            let sizes = solve(&component, &bodies, &expected.sizes);
+            for (name, size) in component.into_iter().zip(sizes) {
+                expected.sizes.insert(name.to_string(), size);
+            }
+        }
+        expected
+    }
+
+    /// Acumula en `linear` el tamaño esperado de `expr` multiplicado por `factor`
+    fn linear(&self, expr: &Expr, factor: f64, linear: &mut Linear) {
+        let config = self.config;
+        let mut repetitions = |inner: &Expr, mean: f64| self.linear(inner, factor * mean, linear);
+
+        match expr {
+            Expr::Str(string) if is_marker(string) => {}
+            Expr::Ident(name) if self.input_data.grammar.rules.contains_key(name) => {
+                *linear.rules.entry(name.to_string()).or_default() += factor;
+            }
+            Expr::Str(_)
+            | Expr::Insens(_)
+            | Expr::Range(_, _)
+            | Expr::Ident(_)
+            | Expr::PeekSlice(_, _) => linear.constant += factor,
+            Expr::Seq(lhs, rhs) => {
+                self.linear(lhs, factor, linear);
+                self.linear(rhs, factor, linear);
+            }
+            Expr::Choice(_, _) => {
+                for (alternative, probability) in probabilities(expr) {
+                    self.linear(alternative, factor * probability, linear);
+                }
+            }
+            // Promedio de la cantidad de repeticiones que elige el generador
+            Expr::Opt(inner) => repetitions(inner, 0.5),
+            Expr::Rep(inner) => repetitions(
+                inner,
+                f64::from(config.upper_bound_zero_or_more_repetition.saturating_sub(1)) / 2.0,
+            ),
+            Expr::RepOnce(inner) => repetitions(
+                inner,
+                f64::from(config.upper_bound_one_or_more_repetition.max(2)) / 2.0,
+            ),
+            Expr::RepExact(inner, num_reps) => repetitions(inner, f64::from(*num_reps)),
+            Expr::RepMin(inner, min_reps) => repetitions(
+                inner,
+                f64::from(*min_reps) + f64::from(config.upper_bound_at_least_repetition) / 2.0,
+            ),
+            Expr::RepMax(inner, max_reps) => repetitions(inner, f64::from(*max_reps) / 2.0),
+            Expr::RepMinMax(inner, min_reps, max_reps) => {
+                repetitions(inner, (f64::from(*min_reps) + f64::from(*max_reps)) / 2.0)
+            }
+            Expr::Push(inner) => self.linear(inner, factor, linear),
+            Expr::Skip(_) | Expr::PosPred(_) | Expr::NegPred(_) => {}
+        }
+    }
+
+    /// Adiciona las alternativas de `expr` con tamaño esperado infinito, numeradas como en `AlternativeHits`
+    fn unbounded_alternatives(
+        &self,
+        rule: &str,
+        expr: &Expr,
+        index: &mut usize,
+        unbounded: &mut Vec<UnboundedAlternative>,
+    ) {
+        match expr {
+            Expr::Choice(_, _) => {
+                for (alternative, _) in probabilities(expr) {
+                    let position = *index;
+                    *index += 1;
+
+                    let mut linear = Linear::default();
+                    self.linear(alternative, 1.0, &mut linear);
+                    if linear
+                        .rules
+                        .iter()
+                        .any(|(name, count)| *count > 0.0 && self.sizes[name].is_infinite())
+                    {
+                        unbounded.push(UnboundedAlternative {
+                            rule: rule.to_string(),
+                            index: position,
+                            alternative: clean_expr(alternative).to_string(),
+                        });
+                    }
+                    self.unbounded_alternatives(rule, alternative, index, unbounded);
+                }
+            }
+            Expr::Seq(lhs, rhs) => {
+                self.unbounded_alternatives(rule, lhs, index, unbounded);
+                self.unbounded_alternatives(rule, rhs, index, unbounded);
+            }
+            Expr::PosPred(inner)
+            | Expr::NegPred(inner)
+            | Expr::Opt(inner)
+            | Expr::Rep(inner)
+            | Expr::RepOnce(inner)
+            | Expr::RepExact(inner, _)
+            | Expr::RepMin(inner, _)
+            | Expr::RepMax(inner, _)
+            | Expr::RepMinMax(inner, _, _)
+            | Expr::Push(inner) => self.unbounded_alternatives(rule, inner, index, unbounded),
+            _ => {}
+        }
+    }
+}
+
+/// Alternativas de un choice de izquierda a derecha con la probabilidad de que el generador las elija
+fn probabilities(expr: &Expr) -> Vec<(&Expr, f64)> {
+    let mut list = vec![];
+    let mut aux_expr = expr;
+    while let Expr::Choice(lhs, rhs) = aux_expr {
+        list.push(&**rhs);
+        aux_expr = lhs;
+    }
+    list.push(aux_expr);
+    list.reverse();
+
+    let weights: Vec<f64> = list
+        .iter()
+        .map(|alternative| f64::from(branch_weight(alternative)))
+        .collect();
+    let total: f64 = weights.iter().sum();
+    list.into_iter()
+        .zip(weights)
+        .map(|(alternative, weight)| {
+            // Con todos los pesos en 0 el generador elige cualquier alternativa
+            let probability = if total > 0.0 { weight / total } else { 1.0 };
+            (alternative, probability)
+        })
+        .collect()
+}
+
+/// Reemplaza los marcadores por `""` como en la gramática limpia
+fn clean_expr(expr: &Expr) -> Expr {
+    expr.clone().map_bottom_up(|expr| match expr {
+        Expr::Str(string) if is_marker(&string) => Expr::Str(String::new()),
+        expr => expr,
+    })
+}
+
+/// Componentes fuertemente conexas del grafo de reglas (algoritmo de Tarjan), en orden topológico inverso
+fn components<'a>(names: &[&'a String], bodies: &HashMap<&String, Linear>) -> Vec<Vec<&'a String>> {
+    struct Tarjan<'a, 'b> {
+        bodies: &'b HashMap<&'b String, Linear>,
+        names: HashMap<&'b String, &'a String>,
+        index: HashMap<&'a String, usize>,
+        low_link: HashMap<&'a String, usize>,
+        stack: Vec<&'a String>,
+        on_stack: HashSet<&'a String>,
+        components: Vec<Vec<&'a String>>,
+    }
+
+    impl<'a, 'b> Tarjan<'a, 'b> {
+        fn visit(&mut self, name: &'a String) {
+            let index = self.index.len();
+            self.index.insert(name, index);
+            self.low_link.insert(name, index);
+            self.stack.push(name);
+            self.on_stack.insert(name);
+
+            let mut successors: Vec<&'a String> = self.bodies[name]
+                .rules
+                .iter()
+                .filter(|(_, count)| **count > 0.0)
+                .map(|(successor, _)| self.names[successor])
+                .collect();
+            successors.sort();
+            for successor in successors {
+                if !self.index.contains_key(successor) {
+                    self.visit(successor);
+                    let low_link = self.low_link[name].min(self.low_link[successor]);
+                    self.low_link.insert(name, low_link);
+                } else if self.on_stack.contains(successor) {
+                    let low_link = self.low_link[name].min(self.index[successor]);
+                    self.low_link.insert(name, low_link);
+                }
+            }
+
+            if self.low_link[name] == self.index[name] {
+                let mut component = vec![];
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack.remove(member);
+                    component.push(member);
+                    if member == name {
+                        break;
+                    }
+                }
+                component.sort();
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        bodies,
+        names: names.iter().map(|name| (*name, *name)).collect(),
+        index: HashMap::new(),
+        low_link: HashMap::new(),
+        stack: vec![],
+        on_stack: HashSet::new(),
+        components: vec![],
+    };
+    for name in names {
+        if !tarjan.index.contains_key(*name) {
+            tarjan.visit(name);
+        }
+    }
+    tarjan.components
+}
+
+/// Resuelve `x = b + C x` para las reglas de una componente, `sizes` contiene los tamaños de las reglas usadas
+/// fuera de la componente. Retorna infinito para toda la componente si no hay una solución positiva
+fn solve(
+    component: &[&String],
+    bodies: &HashMap<&String, Linear>,
+    sizes: &HashMap<String, f64>,
+) -> Vec<f64> {
+    let n = component.len();
+    let position: HashMap<&String, usize> = component
+        .iter()
+        .enumerate()
+        .map(|(position, name)| (*name, position))
+        .collect();
+
+    // Matriz aumentada de (I - C) x = b
+    let mut matrix = vec![vec![0.0; n + 1]; n];
+    for (row, name) in component.iter().enumerate() {
+        let body = &bodies[*name];
+        matrix[row][row] = 1.0;
+        matrix[row][n] = 1.0 + body.constant;
+        for (rule, count) in &body.rules {
+            match position.get(rule) {
+                Some(column) => matrix[row][*column] -= count,
+                None => matrix[row][n] += count * sizes[rule],
+            }
+        }
+    }
+    let infinite = vec![f64::INFINITY; n];
+    if matrix.iter().any(|row| row[n].is_infinite()) {
+        return infinite;
+    }
+
+    // Eliminación de Gauss con pivote parcial
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|a, b| {
+                matrix[*a][column]
+                    .abs()
+                    .total_cmp(&matrix[*b][column].abs())
+            })
+            .unwrap();
+        if matrix[pivot][column].abs() < 1e-12 {
+            return infinite;
+        }
+        matrix.swap(column, pivot);
+        let pivot_row = matrix[column].clone();
+        for (row, values) in matrix.iter_mut().enumerate() {
+            if row != column {
+                let ratio = values[column] / pivot_row[column];
+                for (value, pivot_value) in values.iter_mut().zip(&pivot_row).skip(column) {
+                    *value -= ratio * pivot_value;
+                }
+            }
+        }
+    }
+
+    let solution: Vec<f64> = (0..n)
+        .map(|row| matrix[row][n] / matrix[row][row])
+        .collect();
+    if solution.iter().all(|size| size.is_finite() && *size >= 1.0) {
+        solution
+    } else {
+        infinite
+    }
+}
@@ -0,0 +1,375 @@
+use super::mutate::mutate_seed;
+use super::negative::Mutations;
+use super::shrink::shrink_example;
+use super::{
+    example_rng, traverse, traverse_derivation, Coverage, DerivedExample, Enumeration, Grammar,
+    GrammarAnalysis, InputData, InvalidExample, MinimalDerivation, MutatedExample, RandomSource,
+};
+
+use crate::config::GeneratorConfig;
+use crate::error::{Error, GenerationError};
+use crate::report::CoverageReport;
+use crate::stream::{ExampleStream, ParallelExampleStream};
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A grammar compiled once and ready to generate examples
+///
+/// The grammar and its clean version (without blacklist) are shared behind `Arc`, so generating examples doesn't
+/// compile or copy the grammar again. `Generator` is `Sync`, it can be shared between threads.
+///
+/// ```
+/// use bulk_examples_generator::config::*;
+/// use bulk_examples_generator::Generator;
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go" | "Java" | "PHP" | "Haskell"}
+///         sentence = {"I like " ~ language}
+///     "#;
+///
+/// let generator = Generator::new(grammar.to_string())
+///     .unwrap()
+///     .with_config(Default::default());
+///
+/// let example = generator.generate("sentence").unwrap();
+/// assert!(generator.parse("sentence", &example).is_ok());
+///
+/// let examples = generator.generate_n("sentence", 10);
+/// assert_eq!(10, examples.len());
+/// ```
+#[derive(Debug)]
+pub struct Generator {
+    input_data: InputData,
+    config: GeneratorConfig,
+    /// Number of the next example returned by `generate`
+    next_index: AtomicU64,
+}
+
+impl Clone for Generator {
+    fn clone(&self) -> Self {
+        Generator {
+            input_data: self.input_data.clone(),
+            config: self.config.clone(),
+            next_index: AtomicU64::new(self.next_index.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Generator {
+    /// Compile a grammar string with the default config
+    pub fn new(grammar_string: String) -> Result<Self, Error> {
+        Ok(Generator::from_input_data(InputData::new(grammar_string)?))
+    }
+
+    /// Use a grammar compiled with `compile_grammar`
+    pub fn from_grammar(grammar: Grammar) -> Self {
+        Generator::from_input_data(InputData::from_grammar(grammar))
+    }
+
+    fn from_input_data(input_data: InputData) -> Self {
+        Generator {
+            input_data,
+            config: Default::default(),
+            next_index: AtomicU64::new(1),
+        }
+    }
+
+    /// En modo cobertura con semilla los ejemplos dependen del orden en que se generan (la cobertura cambia los pesos),
+    /// así que se generan uno a uno para que sean siempre los mismos
+    pub(crate) fn is_sequential(&self) -> bool {
+        self.input_data.coverage.is_some() && self.config.seed.is_some()
+    }
+
+    /// Aplica `f` a los índices de 1 a `quantity` en paralelo (o en orden si `is_sequential`), el resultado queda en
+    /// orden
+    fn map_indexes<T: Send>(&self, quantity: u64, f: impl Fn(u64) -> T + Sync + Send) -> Vec<T> {
+        if self.is_sequential() {
+            (1..quantity + 1).map(f).collect()
+        } else {
+            (1..quantity + 1).into_par_iter().map(f).collect()
+        }
+    }
+
+    /// Change the config used for generate the examples
+    ///
+    /// The `weights` of the config are added to the grammar as `"|WEIGHT|n|"` markers, replacing the weights of the
+    /// previous config (without weights the original grammar is used), with `coverage_guided` a new coverage is started
+    pub fn with_config(mut self, config: GeneratorConfig) -> Self {
+        self.input_data = self
+            .input_data
+            .with_weights(&config.weights)
+            .with_coverage(&config);
+        self.config = config;
+        self
+    }
+
+    /// Config used for generate the examples
+    pub fn config(&self) -> &GeneratorConfig {
+        &self.config
+    }
+
+    /// Coverage of the grammar reached by the examples generated, only in coverage-guided mode
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.input_data.coverage.as_deref()
+    }
+
+    /// Minimal derivation of every rule (the rules without a finite derivation aren't included), see
+    /// `MinimalDerivation`
+    pub fn minimal_derivations(&self) -> &HashMap<String, MinimalDerivation> {
+        &self.input_data.minimal_derivations
+    }
+
+    /// Grammar used for generate the examples
+    pub fn grammar(&self) -> &Grammar {
+        &self.input_data.grammar
+    }
+
+    /// Generate one example, beginning in the rule `rule`
+    ///
+    /// Every call generates the next example, with a seed the first call returns the example #1, the second call
+    /// the example #2 and so on
+    pub fn generate(&self, rule: &str) -> Result<String, GenerationError> {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        self.generate_nth(rule, index)
+    }
+
+    /// Generate the example number `index`, with a seed it's always the same example
+    pub fn generate_nth(&self, rule: &str, index: u64) -> Result<String, GenerationError> {
+        let mut rng = example_rng(self.config.seed, index);
+        traverse(&self.input_data, rule, &mut rng, &self.config)
+    }
+
+    /// Generate `quantity` examples in parallel, the examples are returned in order
+    ///
+    /// The examples are enumerated from 1 like in `generate_examples`. With `coverage_guided` and a seed the examples
+    /// depend on the ones generated before, so they are generated one at a time and every run returns the same examples
+    pub fn generate_n(&self, rule: &str, quantity: u64) -> Vec<Result<String, GenerationError>> {
+        self.map_indexes(quantity, |i| self.generate_nth(rule, i))
+    }
+
+    /// Generate one example taking the random decisions from `source` instead of the seed of the config, e.g. a
+    /// `ByteSource` with the input of a fuzzer
+    pub fn generate_with(
+        &self,
+        rule: &str,
+        source: &mut dyn RandomSource,
+    ) -> Result<String, GenerationError> {
+        traverse(&self.input_data, rule, source, &self.config)
+    }
+
+    /// Generate one example together with the derivation tree that produced it, see `DerivedExample`
+    ///
+    /// Like `generate`, every call generates the next example. With a seed the text is the same as the example
+    /// returned by `generate_nth` with the same index
+    pub fn generate_derived(&self, rule: &str) -> Result<DerivedExample, GenerationError> {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        self.generate_derived_nth(rule, index)
+    }
+
+    /// Generate the example number `index` together with its derivation tree
+    pub fn generate_derived_nth(
+        &self,
+        rule: &str,
+        index: u64,
+    ) -> Result<DerivedExample, GenerationError> {
+        let mut rng = example_rng(self.config.seed, index);
+        traverse_derivation(&self.input_data, rule, &mut rng, &self.config)
+    }
+
+    /// Generate `quantity` examples with their derivation trees in parallel, the examples are returned in order
+    pub fn generate_derived_n(
+        &self,
+        rule: &str,
+        quantity: u64,
+    ) -> Vec<Result<DerivedExample, GenerationError>> {
+        self.map_indexes(quantity, |i| self.generate_derived_nth(rule, i))
+    }
+
+    /// Generate one example that the grammar rejects, see `InvalidExample`
+    ///
+    /// Like `generate`, every call generates the next invalid example
+    pub fn generate_invalid(&self, rule: &str) -> Result<InvalidExample, GenerationError> {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        self.generate_invalid_nth(rule, index)
+    }
+
+    /// Generate the invalid example number `index`, with a seed it's always the same example
+    pub fn generate_invalid_nth(
+        &self,
+        rule: &str,
+        index: u64,
+    ) -> Result<InvalidExample, GenerationError> {
+        Mutations::new(&self.input_data, rule)?.generate(&self.input_data, &self.config, index)
+    }
+
+    /// Generate `quantity` invalid examples in parallel, the examples are returned in order
+    pub fn generate_invalid_n(
+        &self,
+        rule: &str,
+        quantity: u64,
+    ) -> Vec<Result<InvalidExample, GenerationError>> {
+        // Las expresiones que se pueden mutar se buscan una sola vez para todos los ejemplos
+        match Mutations::new(&self.input_data, rule) {
+            Ok(mutations) => self.map_indexes(quantity, |i| {
+                mutations.generate(&self.input_data, &self.config, i)
+            }),
+            Err(error) => (1..quantity + 1).map(|_| Err(error.clone())).collect(),
+        }
+    }
+
+    /// Create a variant of `seed` by generating again random subtrees, see `MutatedExample`
+    ///
+    /// Like `generate`, every call creates the next variant. `seed` must be parsed by `rule`
+    pub fn mutate(&self, rule: &str, seed: &str) -> Result<MutatedExample, Error> {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        self.mutate_nth(rule, seed, index)
+    }
+
+    /// Create the variant number `index` of `seed`, with a seed in the config it's always the same variant
+    pub fn mutate_nth(&self, rule: &str, seed: &str, index: u64) -> Result<MutatedExample, Error> {
+        mutate_seed(&self.input_data, &self.config, rule, seed, None, index)
+    }
+
+    /// Create `quantity` variants in parallel, the variant #n is created from the seed `(n - 1) % seeds.len()`
+    pub fn mutate_n(
+        &self,
+        rule: &str,
+        seeds: &[String],
+        quantity: u64,
+    ) -> Vec<Result<MutatedExample, Error>> {
+        if seeds.is_empty() {
+            return vec![];
+        }
+        let len = seeds.len() as u64;
+        self.map_indexes(quantity, |i| {
+            self.mutate_nth(rule, &seeds[((i - 1) % len) as usize], i)
+        })
+    }
+
+    /// Create a variant of `seed` by replacing random subtrees with subtrees of the same rule taken from `donor`
+    ///
+    /// Like `generate`, every call creates the next variant. Both seeds must be parsed by `rule`
+    pub fn splice(&self, rule: &str, seed: &str, donor: &str) -> Result<MutatedExample, Error> {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        self.splice_nth(rule, seed, donor, index)
+    }
+
+    /// Create the variant number `index` of `seed` with subtrees of `donor`
+    pub fn splice_nth(
+        &self,
+        rule: &str,
+        seed: &str,
+        donor: &str,
+        index: u64,
+    ) -> Result<MutatedExample, Error> {
+        mutate_seed(
+            &self.input_data,
+            &self.config,
+            rule,
+            seed,
+            Some(donor),
+            index,
+        )
+    }
+
+    /// Create `quantity` variants in parallel, the variant #n is created from the seed `(n - 1) % seeds.len()` with
+    /// subtrees of the next seed `n % seeds.len()`
+    pub fn splice_n(
+        &self,
+        rule: &str,
+        seeds: &[String],
+        quantity: u64,
+    ) -> Vec<Result<MutatedExample, Error>> {
+        if seeds.is_empty() {
+            return vec![];
+        }
+        let len = seeds.len() as u64;
+        self.map_indexes(quantity, |i| {
+            let seed = &seeds[((i - 1) % len) as usize];
+            let donor = &seeds[(i % len) as usize];
+            self.splice_nth(rule, seed, donor, i)
+        })
+    }
+
+    /// Shrink `example` toward the smallest input that `rule` can parse and `is_failing` still returns true for
+    ///
+    /// The example is parsed and its subtrees are replaced with the shortest derivation of their rule or with an inner
+    /// subtree of the same rule, and the optional and repeated items are dropped, until no candidate fails. Every
+    /// candidate must be parsed completely before calling `is_failing`. Returns `Error::ExampleNotFailing` if
+    /// `is_failing(example)` is false
+    ///
+    /// ```
+    /// use bulk_examples_generator::Generator;
+    ///
+    /// let grammar = r#"
+    ///         number = @{ ('0'..'9')+ }
+    ///         list = { "[" ~ number ~ ("," ~ number)* ~ "]" }
+    ///     "#;
+    ///
+    /// let generator = Generator::new(grammar.to_string()).unwrap();
+    /// // Un parser que falla con los números de 3 cifras
+    /// let shrunk = generator
+    ///     .shrink("list", "[12,5,843,77]", |example| example.contains("843"))
+    ///     .unwrap();
+    /// assert_eq!("[843]", shrunk);
+    /// ```
+    pub fn shrink<F>(&self, rule: &str, example: &str, is_failing: F) -> Result<String, Error>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        shrink_example(&self.input_data, rule, example, is_failing)
+    }
+
+    /// Parse `input` beginning in the rule `rule`, returns `Ok` if the parse is successful, `Err` otherwise
+    ///
+    /// The grammar without blacklist is used, like in the validation of the examples generated
+    pub fn parse(&self, rule: &str, input: &str) -> Result<(), Error> {
+        self.input_data.parser.parse(rule, input)
+    }
+
+    /// Every distinct example of `rule` within `max_depth` rule expansions, in order of increasing size, see
+    /// `Enumeration`
+    pub fn enumerate(&self, rule: &str, max_depth: usize) -> Result<Enumeration, Error> {
+        if !self.input_data.clean_grammar.rules.contains_key(rule) {
+            return Err(Error::UnknownStartRule(rule.to_string()));
+        }
+        Ok(Enumeration::new(
+            &self.input_data,
+            &self.config,
+            rule,
+            max_depth,
+        ))
+    }
+
+    /// Count the hits of every rule and alternative in the examples, see `CoverageReport`
+    ///
+    /// The grammar without blacklist is used, like in `parse`
+    pub fn coverage_report(
+        &self,
+        rule: &str,
+        examples: &[String],
+    ) -> Result<CoverageReport, Error> {
+        CoverageReport::new(&self.input_data.clean_grammar, rule, examples)
+    }
+
+    /// Static analysis of the grammar from `rule` with the repetition bounds and weights of the config, see
+    /// `GrammarAnalysis`
+    pub fn analyze(&self, rule: &str) -> Result<GrammarAnalysis, Error> {
+        if !self.input_data.clean_grammar.rules.contains_key(rule) {
+            return Err(Error::UnknownStartRule(rule.to_string()));
+        }
+        Ok(GrammarAnalysis::new(&self.input_data, &self.config, rule))
+    }
+
+    /// Lazy and unbounded sequence of examples, see `ExampleStream`
+    pub fn stream(&self, rule: &str) -> ExampleStream {
+        ExampleStream::from_generator(self.clone(), rule.to_string())
+    }
+
+    /// Lazy and unbounded sequence of examples generated in parallel, see `ExampleStream::parallel`
+    pub fn parallel_stream(&self, rule: &str, buffer_size: usize) -> ParallelExampleStream {
+        ParallelExampleStream::from_generator(self.clone(), rule.to_string(), buffer_size)
+    }
+}
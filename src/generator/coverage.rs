@@ -0,0 +1,274 @@
+use super::{branch_weight, Grammar};
+use crate::config::GeneratorConfig;
+
+use pest_meta::ast::Expr;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Factor usado para multiplicar el peso de las alternativas que aún no han sido generadas
+pub(crate) const COVERAGE_BOOST: u32 = 100;
+
+/// Coverage of the grammar reached by the examples generated in coverage-guided mode
+///
+/// The coverage is measured with the (rule, alternative) pairs of every choice `a | b` and the bounds of every
+/// repetition (`e?`, `e*`, `e+`, `e{n,}`, `e{,n}` and `e{m, n}`), e.g. in `e{2, 5}` the bounds are 2 and 5.
+/// The alternatives with weight 0 aren't taken into account.
+///
+/// The coverage is shared between the threads and the clones of a `Generator`, cloning it takes a snapshot
+#[derive(Debug)]
+pub struct Coverage {
+    /// (regla, alternativa) de todos los choices de la gramática
+    alternatives: HashSet<(String, String)>,
+    /// (regla, repetición, límite) de todas las repeticiones de la gramática
+    repetitions: HashSet<(String, String, u32)>,
+    covered_alternatives: Mutex<HashSet<(String, String)>>,
+    covered_repetitions: Mutex<HashSet<(String, String, u32)>>,
+}
+
+impl Coverage {
+    /// Busca todas las alternativas y límites de repetición de la gramática, los límites de `e*`, `e+` y `e{n,}`
+    /// dependen de la configuración
+    pub(crate) fn new(grammar: &Grammar, config: &GeneratorConfig) -> Self {
+        let mut alternatives = HashSet::new();
+        let mut repetitions = HashSet::new();
+        for (name, rule) in &grammar.rules {
+            find_targets(
+                name,
+                &rule.expr,
+                config,
+                &mut alternatives,
+                &mut repetitions,
+            );
+        }
+
+        Coverage {
+            alternatives,
+            repetitions,
+            covered_alternatives: Mutex::new(HashSet::new()),
+            covered_repetitions: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Number of alternatives generated at least once
+    pub fn alternatives_covered(&self) -> usize {
+        self.covered_alternatives.lock().unwrap().len()
+    }
+
+    /// Number of alternatives in the grammar
+    pub fn alternatives_total(&self) -> usize {
+        self.alternatives.len()
+    }
+
+    /// Number of repetition bounds generated at least once
+    pub fn repetitions_covered(&self) -> usize {
+        self.covered_repetitions.lock().unwrap().len()
+    }
+
+    /// Number of repetition bounds in the grammar
+    pub fn repetitions_total(&self) -> usize {
+        self.repetitions.len()
+    }
+
+    /// Percentage of alternatives generated, 100 if the grammar doesn't have choices
+    pub fn alternatives_percentage(&self) -> f64 {
+        percentage(self.alternatives_covered(), self.alternatives_total())
+    }
+
+    /// Percentage of repetition bounds generated, 100 if the grammar doesn't have repetitions
+    pub fn repetitions_percentage(&self) -> f64 {
+        percentage(self.repetitions_covered(), self.repetitions_total())
+    }
+
+    /// Returns true when all the alternatives of the grammar have been generated
+    pub fn is_complete(&self) -> bool {
+        self.alternatives_covered() == self.alternatives_total()
+    }
+
+    /// (rule, alternative) pairs not generated yet, sorted
+    pub fn uncovered_alternatives(&self) -> Vec<(String, String)> {
+        let covered = self.covered_alternatives.lock().unwrap();
+        let mut uncovered: Vec<_> = self.alternatives.difference(&covered).cloned().collect();
+        uncovered.sort();
+        uncovered
+    }
+
+    /// (rule, repetition, bound) not generated yet, sorted
+    pub fn uncovered_repetitions(&self) -> Vec<(String, String, u32)> {
+        let covered = self.covered_repetitions.lock().unwrap();
+        let mut uncovered: Vec<_> = self.repetitions.difference(&covered).cloned().collect();
+        uncovered.sort();
+        uncovered
+    }
+
+    /// Peso de una alternativa, las alternativas no generadas tienen más peso
+    pub(crate) fn alternative_weight(&self, rule: &str, alternative: &Expr) -> u32 {
+        let weight = branch_weight(alternative);
+        let key = (rule.to_string(), alternative.to_string());
+        if self.alternatives.contains(&key)
+            && !self.covered_alternatives.lock().unwrap().contains(&key)
+        {
+            weight.saturating_mul(COVERAGE_BOOST)
+        } else {
+            weight
+        }
+    }
+
+    /// Registra la alternativa elegida en un choice
+    pub(crate) fn cover_alternative(&self, rule: &str, alternative: &Expr) {
+        let key = (rule.to_string(), alternative.to_string());
+        if self.alternatives.contains(&key) {
+            self.covered_alternatives.lock().unwrap().insert(key);
+        }
+    }
+
+    /// Elige el número de repeticiones de `repetition`, si alguno de sus límites dentro de [min, max] no ha sido
+    /// generado se elige ese límite, si no se conserva `num_reps`
+    pub(crate) fn repetitions(
+        &self,
+        rule: &str,
+        repetition: &Expr,
+        (min, max): (u32, u32),
+        num_reps: u32,
+    ) -> u32 {
+        let key = |bound| (rule.to_string(), repetition.to_string(), bound);
+        let mut covered = self.covered_repetitions.lock().unwrap();
+
+        let num_reps = [min, max]
+            .iter()
+            .copied()
+            .find(|bound| {
+                self.repetitions.contains(&key(*bound)) && !covered.contains(&key(*bound))
+            })
+            .unwrap_or(num_reps);
+
+        if self.repetitions.contains(&key(num_reps)) {
+            covered.insert(key(num_reps));
+        }
+        num_reps
+    }
+}
+
+impl Clone for Coverage {
+    fn clone(&self) -> Self {
+        Coverage {
+            alternatives: self.alternatives.clone(),
+            repetitions: self.repetitions.clone(),
+            covered_alternatives: Mutex::new(self.covered_alternatives.lock().unwrap().clone()),
+            covered_repetitions: Mutex::new(self.covered_repetitions.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Alternatives covered: {}/{} ({:.2}%)",
+            self.alternatives_covered(),
+            self.alternatives_total(),
+            self.alternatives_percentage()
+        )?;
+        write!(
+            f,
+            "Repetition bounds covered: {}/{} ({:.2}%)",
+            self.repetitions_covered(),
+            self.repetitions_total(),
+            self.repetitions_percentage()
+        )?;
+
+        let uncovered_alternatives = self.uncovered_alternatives();
+        if !uncovered_alternatives.is_empty() {
+            write!(f, "\nUncovered alternatives:")?;
+            for (rule, alternative) in uncovered_alternatives {
+                write!(f, "\n  {}: {}", rule, alternative)?;
+            }
+        }
+
+        let uncovered_repetitions = self.uncovered_repetitions();
+        if !uncovered_repetitions.is_empty() {
+            write!(f, "\nUncovered repetition bounds:")?;
+            for (rule, repetition, bound) in uncovered_repetitions {
+                write!(f, "\n  {}: {} with {} repetitions", rule, repetition, bound)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn percentage(covered: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        covered as f64 * 100.0 / total as f64
+    }
+}
+
+/// Recorre la expresión de la regla `rule` buscando los choices y las repeticiones
+fn find_targets(
+    rule: &str,
+    expr: &Expr,
+    config: &GeneratorConfig,
+    alternatives: &mut HashSet<(String, String)>,
+    repetitions: &mut HashSet<(String, String, u32)>,
+) {
+    let mut add_bounds = |min: u32, max: u32| {
+        repetitions.insert((rule.to_string(), expr.to_string(), min));
+        repetitions.insert((rule.to_string(), expr.to_string(), max));
+    };
+
+    match expr {
+        Expr::Choice(_, _) => {
+            // Las alternativas se recorren igual que en la generación, `a | b | c` es `(a | b) | c`
+            let mut list = vec![];
+            let mut aux_expr = expr;
+            while let Expr::Choice(lhs, rhs) = aux_expr {
+                list.push(&**rhs);
+                aux_expr = lhs;
+            }
+            list.push(aux_expr);
+
+            for alternative in list {
+                if branch_weight(alternative) > 0 {
+                    alternatives.insert((rule.to_string(), alternative.to_string()));
+                }
+                find_targets(rule, alternative, config, alternatives, repetitions);
+            }
+            return;
+        }
+        Expr::Opt(_) => add_bounds(0, 1),
+        Expr::Rep(_) => add_bounds(
+            0,
+            config.upper_bound_zero_or_more_repetition.saturating_sub(1),
+        ),
+        Expr::RepOnce(_) => add_bounds(
+            1,
+            config
+                .upper_bound_one_or_more_repetition
+                .saturating_sub(1)
+                .max(1),
+        ),
+        Expr::RepMin(_, min) => add_bounds(*min, min + config.upper_bound_at_least_repetition),
+        Expr::RepMax(_, max) => add_bounds(0, *max),
+        Expr::RepMinMax(_, min, max) => add_bounds(*min, *max),
+        _ => {}
+    }
+
+    match expr {
+        Expr::Opt(inner)
+        | Expr::Rep(inner)
+        | Expr::RepOnce(inner)
+        | Expr::RepExact(inner, _)
+        | Expr::RepMin(inner, _)
+        | Expr::RepMax(inner, _)
+        | Expr::RepMinMax(inner, _, _)
+        | Expr::PosPred(inner)
+        | Expr::NegPred(inner)
+        | Expr::Push(inner) => find_targets(rule, inner, config, alternatives, repetitions),
+        Expr::Seq(lhs, rhs) => {
+            find_targets(rule, lhs, config, alternatives, repetitions);
+            find_targets(rule, rhs, config, alternatives, repetitions);
+        }
+        _ => {}
+    }
+}
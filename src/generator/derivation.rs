@@ -0,0 +1,328 @@
+use pest_meta::ast::{Expr, Rule as AstRule, RuleType};
+use serde_derive::Serialize;
+
+/// Example generated together with the derivation tree that produced it
+///
+/// The tree is recorded during the generation, the example isn't parsed again. It can be serialized to JSON with
+/// `to_json` or converted to the pairs that pest produces when parsing the example with `to_pairs_json` and
+/// `to_pairs_string`
+///
+/// ```
+/// use bulk_examples_generator::Generator;
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go"}
+///         sentence = {"I like " ~ language ~ "."}
+///     "#;
+///
+/// let generator = Generator::new(grammar.to_string()).unwrap();
+/// let example = generator.generate_derived("sentence").unwrap();
+///
+/// let language = &example.tree.children[0];
+/// assert_eq!("language", language.rule);
+/// assert_eq!(&example.text[language.start..language.end], ["Rust", "Python", "Go"][language.alternatives[0]]);
+/// println!("{}", example.to_pairs_string());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DerivedExample {
+    pub text: String,
+    pub tree: DerivationNode,
+}
+
+/// Rule expanded in the derivation of an example
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DerivationNode {
+    pub rule: String,
+    /// Byte offset in the example where the text of the rule begins
+    pub start: usize,
+    /// Byte offset in the example where the text of the rule ends (exclusive)
+    pub end: usize,
+    /// Alternative chosen in each choice of the rule, in the order the choices were made, e.g. in
+    /// `a = { "x" | "y" | "z" }` the alternative `"y"` is 1
+    pub alternatives: Vec<usize>,
+    /// Rules expanded inside the rule, the rules completed with their shortest derivation when a limit is reached
+    /// don't have children
+    pub children: Vec<DerivationNode>,
+    /// Tipo de la regla, usado para generar los pares de pest
+    #[serde(skip)]
+    ty: RuleType,
+}
+
+impl DerivedExample {
+    /// Example and tree as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Pairs that pest produces when parsing the example, in the JSON format of pest's `Pairs`, e.g.
+    /// `{"pos":[0,8],"pairs":[{"pos":[0,8],"rule":"sentence","inner":[...]}]}`
+    ///
+    /// Like in pest, the silent rules, the rules inside atomic rules (`@`) and the implicit WHITESPACE and COMMENT
+    /// of silent rules don't produce pairs
+    pub fn to_pairs_json(&self) -> String {
+        let pairs = self.pairs();
+        let pos = match (pairs.first(), pairs.last()) {
+            (Some(first), Some(last)) => [first.start, last.end],
+            _ => [0, 0],
+        };
+        let pairs = PairsJson {
+            pos,
+            pairs: pairs.iter().map(|pair| pair.to_json(&self.text)).collect(),
+        };
+        serde_json::to_string(&pairs).unwrap()
+    }
+
+    /// Pairs that pest produces when parsing the example, in the format of `format!("{:#}", pairs)`, e.g.
+    /// `[sentence(0, 8, [language(2, 6)])]`
+    pub fn to_pairs_string(&self) -> String {
+        let pairs: Vec<String> = self.pairs().iter().map(Pair::to_string).collect();
+        format!("[{}]", pairs.join(", "))
+    }
+
+    fn pairs(&self) -> Vec<Pair> {
+        let mut pairs = vec![];
+        self.tree.pairs(false, &mut pairs);
+        pairs
+    }
+}
+
+impl DerivationNode {
+    fn new(rule: &AstRule, start: usize) -> Self {
+        DerivationNode {
+            rule: rule.name.clone(),
+            start,
+            end: start,
+            alternatives: vec![],
+            children: vec![],
+            ty: rule.ty,
+        }
+    }
+
+    /// Adiciona a `pairs` los pares de pest del nodo, `atomic` indica si se está dentro de una regla atómica
+    fn pairs(&self, atomic: bool, pairs: &mut Vec<Pair>) {
+        // WHITESPACE y COMMENT son atómicas de forma implícita
+        let ty = match self.ty {
+            RuleType::Normal if self.rule == "WHITESPACE" || self.rule == "COMMENT" => {
+                RuleType::Atomic
+            }
+            ty => ty,
+        };
+        // Dentro de `@` las reglas no producen pares, salvo las reglas `!` que vuelven a producir pares internos
+        let (produces_pair, inner_atomic) = match ty {
+            RuleType::Atomic => (!atomic, true),
+            RuleType::CompoundAtomic => (!atomic, false),
+            RuleType::NonAtomic => (!atomic, false),
+            RuleType::Normal => (!atomic, atomic),
+            RuleType::Silent => (false, atomic),
+        };
+
+        let mut inner = vec![];
+        for child in &self.children {
+            child.pairs(inner_atomic, &mut inner);
+        }
+
+        if produces_pair {
+            pairs.push(Pair {
+                rule: self.rule.clone(),
+                start: self.start,
+                end: self.end,
+                inner,
+            });
+        } else {
+            pairs.extend(inner);
+        }
+    }
+}
+
+/// Par de pest: regla, posición de inicio y fin, y pares internos
+struct Pair {
+    rule: String,
+    start: usize,
+    end: usize,
+    inner: Vec<Pair>,
+}
+
+impl Pair {
+    /// Igual que la serialización de `Pair` en pest, un par sin pares internos contiene su texto
+    fn to_json<'a>(&'a self, text: &'a str) -> PairJson<'a> {
+        let inner = if self.inner.is_empty() {
+            PairInner::Text(&text[self.start..self.end])
+        } else {
+            PairInner::Pairs(self.inner.iter().map(|pair| pair.to_json(text)).collect())
+        };
+        PairJson {
+            pos: [self.start, self.end],
+            rule: &self.rule,
+            inner,
+        }
+    }
+}
+
+/// Serialización de `Pairs` de pest, los campos conservan el orden de pest
+#[derive(Serialize)]
+struct PairsJson<'a> {
+    pos: [usize; 2],
+    pairs: Vec<PairJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct PairJson<'a> {
+    pos: [usize; 2],
+    rule: &'a str,
+    inner: PairInner<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum PairInner<'a> {
+    Text(&'a str),
+    Pairs(Vec<PairJson<'a>>),
+}
+
+impl std::fmt::Display for Pair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.inner.is_empty() {
+            return write!(f, "{}({}, {})", self.rule, self.start, self.end);
+        }
+        let inner: Vec<String> = self.inner.iter().map(Pair::to_string).collect();
+        write!(
+            f,
+            "{}({}, {}, [{}])",
+            self.rule,
+            self.start,
+            self.end,
+            inner.join(", ")
+        )
+    }
+}
+
+/// Construye el árbol de derivación durante la generación
+///
+/// Las posiciones son relativas al ejemplo completo, `base` es la posición donde comienza el texto de la llamada
+/// actual de `processing_stack_fn` (los predicados y PUSH generan su texto en llamadas anidadas)
+#[derive(Debug, Default)]
+pub(crate) struct DerivationBuilder {
+    /// Nodos abiertos, del más externo al más interno
+    open: Vec<DerivationNode>,
+    root: Option<DerivationNode>,
+    pub(crate) base: usize,
+}
+
+/// Estado del constructor antes de un intento que puede ser rechazado (predicados)
+pub(crate) struct Snapshot {
+    open: usize,
+    children: usize,
+    alternatives: usize,
+}
+
+impl DerivationBuilder {
+    /// Número de nodos abiertos
+    pub(crate) fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    /// Abre el nodo de la regla `rule` en la posición `start`
+    pub(crate) fn open(&mut self, rule: &AstRule, start: usize) {
+        self.open.push(DerivationNode::new(rule, start));
+    }
+
+    /// Cierra el nodo más interno en la posición `end`
+    pub(crate) fn close(&mut self, end: usize) {
+        if let Some(mut node) = self.open.pop() {
+            node.end = end;
+            self.attach(node);
+        }
+    }
+
+    /// Cierra los nodos abiertos hasta que queden `depth` nodos
+    pub(crate) fn close_until(&mut self, depth: usize, end: usize) {
+        while self.open.len() > depth {
+            self.close(end);
+        }
+    }
+
+    /// Adiciona un nodo sin hijos, usado para las reglas cerradas con su derivación más corta
+    pub(crate) fn leaf(&mut self, rule: &AstRule, start: usize, end: usize) {
+        let mut node = DerivationNode::new(rule, start);
+        node.end = end;
+        self.attach(node);
+    }
+
+    /// Registra en el nodo más interno la alternativa `selected` elegida en un choice de la regla `rule`
+    pub(crate) fn choose(&mut self, rule: &AstRule, selected: &Expr) {
+        if let (Some(node), Some(index)) = (
+            self.open.last_mut(),
+            alternative_index(&rule.expr, selected),
+        ) {
+            node.alternatives.push(index);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        let node = self.open.last();
+        Snapshot {
+            open: self.open.len(),
+            children: node.map_or(0, |node| node.children.len()),
+            alternatives: node.map_or(0, |node| node.alternatives.len()),
+        }
+    }
+
+    /// Descarta los nodos y alternativas registrados después de `snapshot`
+    pub(crate) fn restore(&mut self, snapshot: &Snapshot) {
+        self.open.truncate(snapshot.open);
+        if let Some(node) = self.open.last_mut() {
+            node.children.truncate(snapshot.children);
+            node.alternatives.truncate(snapshot.alternatives);
+        }
+    }
+
+    /// Cierra los nodos pendientes y retorna la raíz
+    pub(crate) fn finish(mut self, end: usize) -> Option<DerivationNode> {
+        self.close_until(0, end);
+        self.root
+    }
+
+    fn attach(&mut self, node: DerivationNode) {
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => self.root = Some(node),
+        }
+    }
+}
+
+/// Posición de `selected` entre las alternativas (de izquierda a derecha) del primer choice de `expr` que la contiene
+fn alternative_index(expr: &Expr, selected: &Expr) -> Option<usize> {
+    match expr {
+        Expr::Choice(_, _) => {
+            // `a | b | c` es `(a | b) | c`
+            let mut list = vec![];
+            let mut aux_expr = expr;
+            while let Expr::Choice(lhs, rhs) = aux_expr {
+                list.push(&**rhs);
+                aux_expr = lhs;
+            }
+            list.push(aux_expr);
+            list.reverse();
+
+            if let Some(index) = list.iter().position(|alternative| *alternative == selected) {
+                return Some(index);
+            }
+            list.into_iter()
+                .find_map(|alternative| alternative_index(alternative, selected))
+        }
+        Expr::Seq(lhs, rhs) => {
+            alternative_index(lhs, selected).or_else(|| alternative_index(rhs, selected))
+        }
+        Expr::Opt(inner)
+        | Expr::Rep(inner)
+        | Expr::RepOnce(inner)
+        | Expr::RepExact(inner, _)
+        | Expr::RepMin(inner, _)
+        | Expr::RepMax(inner, _)
+        | Expr::RepMinMax(inner, _, _)
+        | Expr::PosPred(inner)
+        | Expr::NegPred(inner)
+        | Expr::Push(inner) => alternative_index(inner, selected),
+        _ => None,
+    }
+}
@@ -0,0 +1,460 @@
+use super::{peek_slice, prefix_matches, process_stack_builtin, starts_with_predicate, InputData};
+use crate::config::GeneratorConfig;
+
+use pest_meta::ast::Expr;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+/// Textos derivados de una expresión junto con el stack de valores que queda después de derivarlos
+type Derivations = Vec<(String, Vec<String>)>;
+
+/// Every distinct example derivable from a rule within a maximum rule-expansion depth, in order of increasing size
+///
+/// The examples are sorted by their number of characters and then alphabetically, they are derived lazily one size
+/// at a time, so taking the first examples doesn't derive the longer ones. The depth counts the rules
+/// expanded from the start rule, with depth 1 only the start rule is expanded, e.g. with
+/// `expr = { "x" | "(" ~ expr ~ ")" }` and depth 3 the examples are `x`, `(x)` and `((x))`.
+///
+/// The enumeration is bounded by the config of the generator:
+/// - `max_enumeration_repetitions` caps the repetitions of `e*`, `e+`, `e{n,}`, `e{,n}` and `e{m, n}` (but never below
+///   the minimum of the repetition)
+/// - `max_enumeration_range_chars` caps the characters taken from every range and Unicode rule, e.g. with 128
+///   `ANY` is enumerated as the ASCII characters
+///
+/// Like in the random generation, `^"a"` is enumerated in lowercase and uppercase, the implicit WHITESPACE and
+/// COMMENT aren't added and the blacklist isn't used (the depth already avoids the infinite recursion)
+///
+/// ```
+/// use bulk_examples_generator::Generator;
+///
+/// let grammar = r#"
+///         expr = { "x" | "(" ~ expr ~ ")" }
+///     "#;
+///
+/// let generator = Generator::new(grammar.to_string()).unwrap();
+/// let examples: Vec<String> = generator.enumerate("expr", 3).unwrap().collect();
+/// assert_eq!(vec!["x", "(x)", "((x))"], examples);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Enumeration {
+    enumerator: Enumerator,
+    /// Regla inicial, en el heap para que su dirección no cambie (ver `Enumerator`)
+    rule: Arc<Expr>,
+    max_depth: usize,
+    /// Tamaño de la siguiente capa de ejemplos
+    size: usize,
+    /// Cota superior del tamaño de los ejemplos, después de esta capa no hay más ejemplos
+    max_size: usize,
+    /// Ejemplos de la capa actual que aún no se han retornado
+    layer: std::vec::IntoIter<String>,
+}
+
+impl Enumeration {
+    /// Los ejemplos se derivan por capas de un mismo tamaño, solo al pedir el primer ejemplo de cada capa
+    pub(crate) fn new(
+        input_data: &InputData,
+        config: &GeneratorConfig,
+        rule: &str,
+        max_depth: usize,
+    ) -> Self {
+        let mut enumerator = Enumerator {
+            input_data: input_data.clone(),
+            config: config.clone(),
+            memo: HashMap::new(),
+            regrouped: HashMap::new(),
+            max_sizes: HashMap::new(),
+        };
+        let rule = Arc::new(Expr::Ident(rule.to_string()));
+        let max_size = enumerator.max_size(&rule, max_depth).text.0;
+
+        Enumeration {
+            enumerator,
+            rule,
+            max_depth,
+            size: 0,
+            max_size,
+            layer: vec![].into_iter(),
+        }
+    }
+}
+
+impl Iterator for Enumeration {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            if let Some(example) = self.layer.next() {
+                return Some(example);
+            }
+            if self.size > self.max_size {
+                return None;
+            }
+
+            // Los ejemplos de una capa se ordenan alfabéticamente
+            self.layer = self
+                .enumerator
+                .expr(&self.rule, self.max_depth, &[], self.size)
+                .iter()
+                .map(|(text, _)| text.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>()
+                .into_iter();
+            self.size += 1;
+        }
+    }
+}
+
+/// Recorre el AST de la gramática limpia derivando todos los textos posibles de cada expresión con un tamaño dado
+///
+/// Las expresiones se identifican por su dirección, todas son parte de la gramática (o de `regrouped`) y no se mueven
+/// mientras exista el `Enumerator`
+#[derive(Clone, Debug)]
+struct Enumerator {
+    input_data: InputData,
+    config: GeneratorConfig,
+    /// Derivaciones de cada (expresión, profundidad, stack inicial, tamaño) ya calculadas
+    memo: HashMap<(usize, usize, Vec<String>, usize), Arc<Derivations>>,
+    /// `(!A ~ B) ~ C` reagrupado como `!A ~ (B ~ C)`, por la dirección de la secuencia original
+    regrouped: HashMap<usize, Arc<Expr>>,
+    /// Cota del tamaño de cada (regla, profundidad) ya calculada, ver `max_size`
+    max_sizes: HashMap<(String, usize), SizeBound>,
+}
+
+impl Enumerator {
+    /// Derivaciones de `expr` de exactamente `size` caracteres partiendo del stack de valores `stack`, `depth` es la
+    /// cantidad de reglas que aún se pueden expandir
+    fn expr(
+        &mut self,
+        expr: &Expr,
+        depth: usize,
+        stack: &[String],
+        size: usize,
+    ) -> Arc<Derivations> {
+        let key = (expr as *const Expr as usize, depth, stack.to_vec(), size);
+        if let Some(derivations) = self.memo.get(&key) {
+            return derivations.clone();
+        }
+        let derivations = Arc::new(self.derive(expr, depth, stack, size));
+        self.memo.insert(key, derivations.clone());
+        derivations
+    }
+
+    fn derive(&mut self, expr: &Expr, depth: usize, stack: &[String], size: usize) -> Derivations {
+        let text = |text: String| {
+            if text.chars().count() == size {
+                vec![(text, stack.to_vec())]
+            } else {
+                vec![]
+            }
+        };
+
+        match expr {
+            Expr::Str(string) => text(string.to_string()),
+            // Igual que en la generación aleatoria solo se usan minúsculas y mayúsculas
+            Expr::Insens(string) => {
+                let mut derivations = text(string.to_lowercase());
+                derivations.extend(text(string.to_uppercase()));
+                dedup(derivations)
+            }
+            Expr::Range(initial_char, end_char) => {
+                let from = initial_char.chars().next().unwrap();
+                let to = end_char.chars().next().unwrap();
+                self.chars(from..=to, stack, size)
+            }
+            Expr::Ident(name) => {
+                let mut value_stack = stack.to_vec();
+                if let Some(builtin) = process_stack_builtin(name, &mut value_stack) {
+                    return match text(builtin).pop() {
+                        Some((builtin, _)) => vec![(builtin, value_stack)],
+                        None => vec![],
+                    };
+                }
+                if let Some(ranges) = self.input_data.unicode_ranges.get(name) {
+                    let chars = ranges.chars();
+                    return self.chars(chars, stack, size);
+                }
+
+                let grammar = self.input_data.clean_grammar.clone();
+                match grammar.rules.get(name) {
+                    Some(rule) if depth > 0 => {
+                        self.expr(&rule.expr, depth - 1, stack, size).to_vec()
+                    }
+                    Some(_) => vec![],
+                    // SOI y EOI no producen texto
+                    None => text(String::new()),
+                }
+            }
+            Expr::PeekSlice(start, end) => text(peek_slice(stack, *start, *end)),
+            // `!A ~ B` y `&A ~ B` se derivan como B y se filtran según A, igual que en la generación aleatoria
+            Expr::Seq(lhs, rhs) => match &**lhs {
+                Expr::NegPred(predicate) | Expr::PosPred(predicate) => {
+                    let positive_predicate = matches!(**lhs, Expr::PosPred(_));
+                    self.expr(rhs, depth, stack, size)
+                        .iter()
+                        .filter(|(text, _)| {
+                            prefix_matches(&self.input_data, predicate, text) == positive_predicate
+                        })
+                        .cloned()
+                        .collect()
+                }
+                Expr::Seq(inner_lhs, inner_rhs) if starts_with_predicate(inner_lhs) => {
+                    let regrouped = self
+                        .regrouped
+                        .entry(expr as *const Expr as usize)
+                        .or_insert_with(|| {
+                            Arc::new(Expr::Seq(
+                                inner_lhs.clone(),
+                                Box::new(Expr::Seq(inner_rhs.clone(), rhs.clone())),
+                            ))
+                        })
+                        .clone();
+                    self.expr(&regrouped, depth, stack, size).to_vec()
+                }
+                _ => {
+                    let mut result = vec![];
+                    for lhs_size in 0..=size {
+                        let derivations = self.expr(lhs, depth, stack, lhs_size);
+                        result.extend(self.concat(&derivations, rhs, depth, size - lhs_size));
+                    }
+                    dedup(result)
+                }
+            },
+            Expr::Choice(lhs, rhs) => {
+                let mut derivations = self.expr(lhs, depth, stack, size).to_vec();
+                derivations.extend(self.expr(rhs, depth, stack, size).iter().cloned());
+                dedup(derivations)
+            }
+            Expr::Push(inner) => self
+                .expr(inner, depth, stack, size)
+                .iter()
+                .map(|(text, value_stack)| {
+                    let mut value_stack = value_stack.clone();
+                    value_stack.push(text.clone());
+                    (text.clone(), value_stack)
+                })
+                .collect(),
+            Expr::Skip(_) | Expr::PosPred(_) | Expr::NegPred(_) => text(String::new()),
+            _ => match self.repetition_bounds(expr) {
+                Some((inner, bounds)) => self.repetitions(inner, bounds, depth, stack, size),
+                None => vec![],
+            },
+        }
+    }
+
+    /// Expresión repetida y (mínimo, máximo) de repeticiones de `e?`, `e*`, `e+`, `e{n}`, `e{n,}`, `e{,n}` y `e{m, n}`,
+    /// limitadas por `max_enumeration_repetitions` (pero nunca por debajo del mínimo)
+    fn repetition_bounds<'e>(&self, expr: &'e Expr) -> Option<(&'e Expr, (u32, u32))> {
+        let max = self.config.max_enumeration_repetitions;
+        match expr {
+            Expr::Opt(inner) => Some((inner, (0, 1))),
+            Expr::Rep(inner) => Some((inner, (0, max))),
+            Expr::RepOnce(inner) => Some((inner, (1, max.max(1)))),
+            Expr::RepExact(inner, num_reps) => Some((inner, (*num_reps, *num_reps))),
+            Expr::RepMin(inner, min_reps) => Some((inner, (*min_reps, max.max(*min_reps)))),
+            Expr::RepMax(inner, max_reps) => Some((inner, (0, max.min(*max_reps)))),
+            Expr::RepMinMax(inner, min_reps, max_reps) => {
+                Some((inner, (*min_reps, max.min(*max_reps).max(*min_reps))))
+            }
+            _ => None,
+        }
+    }
+
+    /// Concatena cada derivación con las derivaciones de `expr` de tamaño `size` que parten de su stack
+    fn concat(
+        &mut self,
+        derivations: &Derivations,
+        expr: &Expr,
+        depth: usize,
+        size: usize,
+    ) -> Derivations {
+        let mut result = vec![];
+        for (prefix, value_stack) in derivations {
+            for (text, value_stack) in self.expr(expr, depth, value_stack, size).iter() {
+                result.push((format!("{}{}", prefix, text), value_stack.clone()));
+            }
+        }
+        result
+    }
+
+    /// Derivaciones de tamaño `size` de `expr` repetida entre `min` y `max` veces
+    fn repetitions(
+        &mut self,
+        expr: &Expr,
+        (min, max): (u32, u32),
+        depth: usize,
+        stack: &[String],
+        size: usize,
+    ) -> Derivations {
+        let mut result = vec![];
+        if min == 0 && size == 0 {
+            result.push((String::new(), stack.to_vec()));
+        }
+        if max > 0 {
+            for first_size in 0..=size {
+                for (prefix, value_stack) in self.expr(expr, depth, stack, first_size).iter() {
+                    let rest = self.repetitions(
+                        expr,
+                        (min.saturating_sub(1), max - 1),
+                        depth,
+                        value_stack,
+                        size - first_size,
+                    );
+                    for (text, value_stack) in rest {
+                        result.push((format!("{}{}", prefix, text), value_stack));
+                    }
+                }
+            }
+        }
+        dedup(result)
+    }
+
+    /// Un texto por cada caracter si `size` es 1, limitado por `max_enumeration_range_chars`
+    fn chars(
+        &self,
+        chars: impl Iterator<Item = char>,
+        stack: &[String],
+        size: usize,
+    ) -> Derivations {
+        if size != 1 {
+            return vec![];
+        }
+        chars
+            .take(self.config.max_enumeration_range_chars as usize)
+            .map(|c| (c.to_string(), stack.to_vec()))
+            .collect()
+    }
+
+    /// Cota del tamaño de las derivaciones de `expr` y del stack que dejan, ver `SizeBound`
+    fn max_size(&mut self, expr: &Expr, depth: usize) -> SizeBound {
+        match expr {
+            Expr::Str(string) | Expr::Insens(string) => SizeBound::text(string.chars().count()),
+            Expr::Range(..) => SizeBound::text(1),
+            Expr::Ident(name) => {
+                match name.as_str() {
+                    "PEEK" | "POP" | "PEEK_ALL" | "POP_ALL" => return SizeBound::STACK,
+                    "DROP" => return SizeBound::EMPTY,
+                    _ => {}
+                }
+                if self.input_data.unicode_ranges.contains_key(name) {
+                    return SizeBound::text(1);
+                }
+                let grammar = self.input_data.clean_grammar.clone();
+                let rule = match grammar.rules.get(name) {
+                    Some(rule) if depth > 0 => rule,
+                    _ => return SizeBound::EMPTY,
+                };
+
+                let key = (name.to_string(), depth);
+                if let Some(max_size) = self.max_sizes.get(&key) {
+                    return *max_size;
+                }
+                let max_size = self.max_size(&rule.expr, depth - 1);
+                self.max_sizes.insert(key, max_size);
+                max_size
+            }
+            Expr::PeekSlice(..) => SizeBound::STACK,
+            Expr::Seq(lhs, rhs) => {
+                let lhs = self.max_size(lhs, depth);
+                let rhs = self.max_size(rhs, depth);
+                lhs.then(rhs)
+            }
+            Expr::Choice(lhs, rhs) => {
+                let lhs = self.max_size(lhs, depth);
+                let rhs = self.max_size(rhs, depth);
+                lhs.max(rhs)
+            }
+            Expr::Push(inner) => self.max_size(inner, depth).pushed(),
+            Expr::Skip(_) | Expr::PosPred(_) | Expr::NegPred(_) => SizeBound::EMPTY,
+            _ => match self.repetition_bounds(expr) {
+                Some((inner, (_, max))) => {
+                    let once = self.max_size(inner, depth);
+                    (0..max).fold(SizeBound::EMPTY, |total, _| total.then(once))
+                }
+                None => SizeBound::EMPTY,
+            },
+        }
+    }
+}
+
+/// Cota superior del tamaño de las derivaciones de una expresión en función del tamaño `s` del stack (la suma de sus
+/// textos) antes de derivarla, cada cota es `(a, b)` = `a + b * s`
+///
+/// El stack solo cambia con PUSH, así que su tamaño está acotado aunque PEEK_ALL lo repita. La enumeración se detiene
+/// al pasar la cota de la regla inicial (con el stack vacío)
+#[derive(Clone, Copy, Debug)]
+struct SizeBound {
+    /// Tamaño del texto derivado
+    text: (usize, usize),
+    /// Tamaño del stack después de derivar
+    stack: (usize, usize),
+}
+
+impl SizeBound {
+    /// Sin texto y sin cambios en el stack
+    const EMPTY: SizeBound = SizeBound {
+        text: (0, 0),
+        stack: (0, 1),
+    };
+
+    /// Texto tomado del stack (PEEK, POP, PEEK_ALL, ...)
+    const STACK: SizeBound = SizeBound {
+        text: (0, 1),
+        stack: (0, 1),
+    };
+
+    fn text(size: usize) -> Self {
+        SizeBound {
+            text: (size, 0),
+            ..SizeBound::EMPTY
+        }
+    }
+
+    /// Cota de derivar `self` y luego `next`, el stack de `next` es el que deja `self`
+    fn then(self, next: SizeBound) -> Self {
+        let after = |(a, b): (usize, usize)| {
+            (
+                a.saturating_add(b.saturating_mul(self.stack.0)),
+                b.saturating_mul(self.stack.1),
+            )
+        };
+        let (text_a, text_b) = after(next.text);
+        SizeBound {
+            text: (
+                self.text.0.saturating_add(text_a),
+                self.text.1.saturating_add(text_b),
+            ),
+            stack: after(next.stack),
+        }
+    }
+
+    fn max(self, other: SizeBound) -> Self {
+        SizeBound {
+            text: (self.text.0.max(other.text.0), self.text.1.max(other.text.1)),
+            stack: (
+                self.stack.0.max(other.stack.0),
+                self.stack.1.max(other.stack.1),
+            ),
+        }
+    }
+
+    /// PUSH agrega el texto derivado al stack
+    fn pushed(self) -> Self {
+        SizeBound {
+            stack: (
+                self.stack.0.saturating_add(self.text.0),
+                self.stack.1.saturating_add(self.text.1),
+            ),
+            ..self
+        }
+    }
+}
+
+/// Elimina las derivaciones repetidas conservando el orden
+fn dedup(derivations: Derivations) -> Derivations {
+    let mut seen = HashSet::new();
+    derivations
+        .into_iter()
+        .filter(|derivation| seen.insert(derivation.clone()))
+        .collect()
+}
@@ -1,12 +1,51 @@
 use crate::compile_grammar;
 use crate::config::GeneratorConfig;
-use crate::parse_input;
+use crate::error::{Error, GenerationError};
+use crate::parse_input_with_vm;
 
-use pest_meta::ast::{Expr, Rule as AstRule};
+use pest_meta::ast::{Expr, Rule as AstRule, RuleType};
+use pest_meta::optimizer;
+use pest_vm::Vm;
 use rand::prelude::*;
-use regex::Regex;
+use rand::rngs::StdRng;
+use std::fmt;
+use std::sync::Arc;
 use std::{collections::HashMap, rc::Rc};
 
+mod analysis;
+mod compiled;
+mod coverage;
+mod derivation;
+mod enumerate;
+mod mutate;
+mod negative;
+mod random;
+mod shortest;
+mod shrink;
+mod unicode;
+
+pub use self::analysis::{
+    GrammarAnalysis, PredicateIssue, UnboundedAlternative, UnsupportedPredicate,
+};
+pub use self::compiled::Generator;
+pub use self::coverage::Coverage;
+use self::derivation::DerivationBuilder;
+pub use self::derivation::{DerivationNode, DerivedExample};
+pub use self::enumerate::Enumeration;
+pub use self::mutate::{MutatedExample, SubtreeMutation, SubtreeMutationKind};
+pub use self::negative::{InvalidExample, Mutation, MutationKind};
+pub use self::random::{ByteSource, RandomSource};
+pub use self::shortest::MinimalDerivation;
+use self::shortest::{minimal_derivations, shortest_text};
+use self::unicode::UnicodeRanges;
+
+/// Marcador que cierra el nodo del árbol de derivación de una regla, solo se usa al generar el árbol
+const TREE_END: &str = "|TREE|END|";
+
+/// Constante usada para dispersar el índice de cada ejemplo antes de combinarlo con la semilla
+const SEED_MIXER: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Compiled grammar, the rules found are the keys and their components (AST) the entries
 #[derive(Clone, Debug)]
 pub struct Grammar {
     pub rules: HashMap<String, AstRule>,
@@ -14,23 +53,159 @@ pub struct Grammar {
 
 /// Estructura interna usada para almacenar los datos que se van a procesar por el algoritmo
 /// gramática original, y gramática preprocesada para evitar conflictos con pest
+///
+/// Las gramáticas se comparten con `Arc`, clonar `InputData` no copia las reglas
 #[derive(Clone, Debug)]
 pub struct InputData {
     /// Gramática original ingresada por el usuario
-    grammar: Grammar,
+    grammar: Arc<Grammar>,
+    /// Gramática sin pesos, los pesos se aplican siempre sobre esta para no anidar los marcadores
+    base_grammar: Arc<Grammar>,
     /// Gramática limpia (sin blacklist), para otras operaciones con pest
-    clean_grammar: Grammar,
+    clean_grammar: Arc<Grammar>,
+    /// Parser de la gramática limpia, usado para parsear los ejemplos y revisar los predicados
+    parser: Parser,
+    /// Codepoints de las reglas Unicode predefinidas usadas en la gramática (LETTER, XID_START, ...)
+    unicode_ranges: Arc<HashMap<String, UnicodeRanges>>,
+    /// Derivación mínima de cada regla, usada para cerrar las reglas abiertas al alcanzar un límite
+    minimal_derivations: Arc<HashMap<String, MinimalDerivation>>,
+    /// Derivaciones mínimas de la gramática sin pesos
+    base_minimal_derivations: Arc<HashMap<String, MinimalDerivation>>,
+    /// Cobertura de la gramática, solo en modo cobertura (`coverage_guided`)
+    coverage: Option<Arc<Coverage>>,
 }
 
 impl InputData {
-    pub fn new(grammar: String) -> Self {
-        let (grammar, clean_grammar) = init_grammar(grammar);
+    pub fn new(grammar: String) -> Result<Self, Error> {
+        Ok(InputData::from_grammar(compile_grammar(grammar)?))
+    }
+
+    /// Adiciona los pesos `weights` (regla -> peso de cada alternativa) como marcadores `"|WEIGHT|n|"` al inicio
+    /// de las alternativas de cada regla, reemplazando los pesos anteriores (sin pesos vuelve a la gramática original)
+    pub fn with_weights(&self, weights: &HashMap<String, Vec<u32>>) -> Self {
+        if weights.is_empty() {
+            return InputData {
+                grammar: self.base_grammar.clone(),
+                minimal_derivations: self.base_minimal_derivations.clone(),
+                ..self.clone()
+            };
+        }
+
+        let mut grammar = (*self.base_grammar).clone();
+        for (name, rule_weights) in weights {
+            if let Some(rule) = grammar.rules.get_mut(name) {
+                rule.expr = add_weights(rule.expr.clone(), rule_weights);
+            }
+        }
+
+        // Las alternativas con peso 0 no se usan en las derivaciones mínimas
+        let minimal_derivations = minimal_derivations(&grammar, &self.unicode_ranges);
         InputData {
-            grammar: grammar.unwrap(),
-            clean_grammar: clean_grammar.unwrap(),
+            grammar: Arc::new(grammar),
+            minimal_derivations: Arc::new(minimal_derivations),
+            ..self.clone()
+        }
+    }
+
+    /// Inicia una cobertura nueva si la configuración activa el modo cobertura
+    pub fn with_coverage(&self, config: &GeneratorConfig) -> Self {
+        let coverage = if config.coverage_guided {
+            Some(Arc::new(Coverage::new(&self.grammar, config)))
+        } else {
+            None
+        };
+
+        InputData {
+            coverage,
+            ..self.clone()
+        }
+    }
+
+    pub fn from_grammar(grammar: Grammar) -> Self {
+        let clean_grammar = init_clean_grammar(&grammar);
+        let unicode_ranges = init_unicode_ranges(&grammar);
+        let minimal_derivations = Arc::new(minimal_derivations(&grammar, &unicode_ranges));
+        let grammar = Arc::new(grammar);
+        InputData {
+            base_grammar: grammar.clone(),
+            grammar,
+            parser: Parser::new(&clean_grammar),
+            clean_grammar: Arc::new(clean_grammar),
+            unicode_ranges: Arc::new(unicode_ranges),
+            base_minimal_derivations: minimal_derivations.clone(),
+            minimal_derivations,
+            coverage: None,
+        }
+    }
+}
+
+/// Parser de pest de una gramática, se compila una sola vez y lo comparten todos los ejemplos (y los hilos)
+///
+/// Cada predicado (`&A`, `!A`) que no es un identificador se compila como una regla temporal, así revisar un
+/// predicado no requiere copiar la gramática
+#[derive(Clone)]
+pub(crate) struct Parser {
+    vm: Arc<Vm>,
+    /// Expresión de cada predicado y nombre de su regla temporal
+    predicates: Arc<Vec<(Expr, String)>>,
+}
+
+impl Parser {
+    fn new(grammar: &Grammar) -> Self {
+        let mut predicates: Vec<(Expr, String)> = vec![];
+        for rule in grammar.rules.values() {
+            rule.expr.clone().map_bottom_up(|expr| {
+                if let Expr::PosPred(inner) | Expr::NegPred(inner) = &expr {
+                    let known = predicates
+                        .iter()
+                        .any(|(predicate, _)| predicate == &**inner);
+                    if !matches!(**inner, Expr::Ident(_)) && !known {
+                        let name = format!("{}_{}", PREFIX_RULE, predicates.len());
+                        predicates.push(((**inner).clone(), name));
+                    }
+                }
+                expr
+            });
+        }
+
+        let mut rules: Vec<AstRule> = grammar.rules.values().cloned().collect();
+        rules.extend(predicates.iter().map(|(expr, name)| AstRule {
+            name: name.clone(),
+            ty: RuleType::Normal,
+            expr: expr.clone(),
+        }));
+        Parser {
+            vm: Arc::new(Vm::new(optimizer::optimize(rules))),
+            predicates: Arc::new(predicates),
         }
     }
+
+    /// Máquina de pest de la gramática limpia, para recorrer los pares del parseo
+    pub(crate) fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    /// Parsea `input` desde la regla `rule`, igual que `parse_input`
+    pub(crate) fn parse(&self, rule: &str, input: &str) -> Result<(), Error> {
+        parse_input_with_vm(&self.vm, rule, input)
+    }
+
+    /// Parsea `input` con la regla temporal del predicado `expr`, None si `expr` no es un predicado de la gramática
+    fn parse_predicate(&self, expr: &Expr, input: &str) -> Option<bool> {
+        let (_, name) = self
+            .predicates
+            .iter()
+            .find(|(predicate, _)| predicate == expr)?;
+        Some(self.parse(name, input).is_ok())
+    }
 }
+
+impl fmt::Debug for Parser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Parser")
+    }
+}
+
 /// Enum used to decide if to continue processing a choice expression
 enum ProcessingChoices {
     /// Expr
@@ -45,6 +220,7 @@ enum ProcessingChoices {
 ///  Some((weights, choices_count, previous_rule, actual_rule, actual_expr))
 ///  Vec<(Vec<f32>, HashMap<String, u32>, Option<AstRule>, AstRule, Expr)> = Vec::new();
 #[derive(Clone, Debug, Default)]
+#[allow(dead_code)]
 struct Context {
     total_count: usize,
     /// Contador de profundidad actual como si fuera Breadth-first search
@@ -57,100 +233,193 @@ struct Context {
     /// Parametro diseñado para mantener un conteo de las reglas que han aparecido hasta el momento
     /// no esta siendo usado actualmente
     choices_count: HashMap<String, u32>,
+    /// Indica si se está dentro de una regla atómica, donde pest no acepta WHITESPACE ni COMMENT implícitos
+    atomic: bool,
 }
 
-fn init_grammar(
-    grammar_string: String,
-) -> (
-    Result<Grammar, Vec<HashMap<String, String>>>,
-    Result<Grammar, Vec<HashMap<String, String>>>,
-) {
-    // Compilar gramática normal
-    let grammar = compile_grammar(grammar_string.clone());
+/// Elemento del stack de procesamiento
+/// Contexto, definicion anterior, definición actual, termino actual a procesar
+type StackItem = (Context, Option<Rc<AstRule>>, Rc<AstRule>, Rc<Expr>);
+
+/// Crea la gramática limpia (sin blacklist) reemplazando los textos `"|BLACKLIST|I|...|"`, `"|BLACKLIST|R|...|"` y
+/// `"|WEIGHT|...|"` por `""`, de esta forma pest puede parsear los ejemplos generados
+fn init_clean_grammar(grammar: &Grammar) -> Grammar {
+    let rules = grammar
+        .rules
+        .iter()
+        .map(|(name, rule)| {
+            let mut clean_rule = rule.clone();
+            clean_rule.expr = clean_expr(rule.expr.clone());
+            (name.clone(), clean_rule)
+        })
+        .collect();
+
+    Grammar { rules }
+}
+
+/// Reemplaza los marcadores (blacklist y pesos) de la expresión por cadenas vacías
+fn clean_expr(expr: Expr) -> Expr {
+    expr.map_bottom_up(|expr| match expr {
+        Expr::Str(string) if is_marker(&string) => Expr::Str(String::new()),
+        expr => expr,
+    })
+}
 
-    // Compilar gramática limpia (Sin blacklist)
-    let re = Regex::new(r#""\|BLACKLIST\|[I\|R]\|.+\|""#).unwrap();
-    let clean_grammar_string = re.replace_all(&grammar_string, "\"\"");
-    let clean_grammar = compile_grammar(clean_grammar_string.to_string());
+/// Adiciona el marcador `"|WEIGHT|n|"` al inicio de cada alternativa del choice principal de una regla
+///
+/// Las alternativas sin peso en `weights` conservan el peso 1
+fn add_weights(expr: Expr, weights: &[u32]) -> Expr {
+    // pest agrupa `a | b | c` como `(a | b) | c`
+    fn alternatives(expr: Expr, list: &mut Vec<Expr>) {
+        match expr {
+            Expr::Choice(lhs, rhs) => {
+                alternatives(*lhs, list);
+                alternatives(*rhs, list);
+            }
+            expr => list.push(expr),
+        }
+    }
 
-    (grammar, clean_grammar)
+    let mut list = Vec::new();
+    alternatives(expr, &mut list);
+
+    list.into_iter()
+        .enumerate()
+        .map(|(index, alternative)| match weights.get(index) {
+            Some(weight) => Expr::Seq(
+                Box::new(Expr::Str(format!("|WEIGHT|{}|", weight))),
+                Box::new(alternative),
+            ),
+            None => alternative,
+        })
+        .reduce(|lhs, rhs| Expr::Choice(Box::new(lhs), Box::new(rhs)))
+        .unwrap()
 }
 
-pub fn generate_example(
-    input_data: InputData,
-    start_rule: String,
-    config: &GeneratorConfig,
-) -> Result<String, String> {
-    let mut rng = thread_rng();
-
-    // let config = GeneratorConfig {
-    //     expand_limit: expand_limit,
-    //     soft_limit: 10000,
-    //     hard_limit: 25000,
-    //     ..Default::default()
-    // };
-
-    let result = traverse(input_data, &start_rule, &mut rng, config);
-    result
+/// Calcula los rangos de codepoints de las reglas Unicode predefinidas que usa la gramática
+///
+/// Los rangos se calculan una sola vez al compilar la gramática, las reglas definidas por el usuario tienen prioridad
+fn init_unicode_ranges(grammar: &Grammar) -> HashMap<String, UnicodeRanges> {
+    let mut unicode_ranges = HashMap::new();
+    for rule in grammar.rules.values() {
+        rule.expr.clone().map_bottom_up(|expr| {
+            if let Expr::Ident(name) = &expr {
+                if !grammar.rules.contains_key(name) && !unicode_ranges.contains_key(name) {
+                    if let Some(ranges) = UnicodeRanges::from_name(name) {
+                        unicode_ranges.insert(name.clone(), ranges);
+                    }
+                }
+            }
+            expr
+        });
+    }
+    unicode_ranges
+}
+
+/// Crea el generador aleatorio para el ejemplo número `index`
+///
+/// Si la configuración tiene una semilla, el generador aleatorio del ejemplo se deriva de la semilla y del índice,
+/// de modo que el ejemplo `index` es siempre el mismo sin importar el orden (o el hilo) en que se genere.
+/// Sin semilla se usa un generador inicializado desde `thread_rng()`
+pub fn example_rng(seed: Option<u64>, index: u64) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed ^ index.wrapping_mul(SEED_MIXER)),
+        None => StdRng::from_rng(thread_rng()).expect("Unable to initialize the random generator"),
+    }
 }
 
 pub fn traverse(
-    input_data: InputData,
-    start_rule: &String,
-    rng: &mut ThreadRng,
+    input_data: &InputData,
+    start_rule: &str,
+    rng: &mut dyn RandomSource,
     config: &GeneratorConfig,
-) -> Result<String, String> {
+) -> Result<String, GenerationError> {
+    traverse_with_tree(input_data, start_rule, rng, config, &mut None)
+}
+
+/// Igual que `traverse`, retorna también el árbol de derivación del ejemplo
+pub(crate) fn traverse_derivation(
+    input_data: &InputData,
+    start_rule: &str,
+    rng: &mut dyn RandomSource,
+    config: &GeneratorConfig,
+) -> Result<DerivedExample, GenerationError> {
+    let mut tree = Some(DerivationBuilder::default());
+    let text = traverse_with_tree(input_data, start_rule, rng, config, &mut tree)?;
+    let tree = tree
+        .and_then(|tree| tree.finish(text.len()))
+        .ok_or_else(|| GenerationError::RuleNotFound(start_rule.to_string()))?;
+    Ok(DerivedExample { text, tree })
+}
+
+/// Genera un ejemplo de `start_rule`, si `tree` existe se construye el árbol de derivación con la regla inicial
+/// como raíz
+fn traverse_with_tree(
+    input_data: &InputData,
+    start_rule: &str,
+    rng: &mut dyn RandomSource,
+    config: &GeneratorConfig,
+    tree: &mut Option<DerivationBuilder>,
+) -> Result<String, GenerationError> {
     // Factor de convergencia
     // let c_factor: f32 = 0.01;
     // let c_factor: f32 = 0.5;
 
-    // Regla Inicial
-    let rule;
     // Extrae la regla inicial
-    let find_lhs = input_data.grammar.rules.get(start_rule);
-    match find_lhs {
-        Some(r) => rule = r.clone(),
-        None => return Ok(start_rule.to_string()),
-    }
+    let rule = match input_data.grammar.rules.get(start_rule) {
+        Some(r) => r.clone(),
+        None => return Err(GenerationError::RuleNotFound(start_rule.to_string())),
+    };
 
     // Lista negra dinámica, usada para evitar la expansión de ciertos elementos de forma dinámica
     // Por ejemplo para evitar <a><p><a>TEXT</a></p></a>
     let mut dynamic_blacklist: Vec<String> = Vec::new();
 
+    // Stack de valores al estilo pest, usado por PUSH, POP, PEEK, PEEK_ALL, POP_ALL, DROP y PEEK[..]
+    let mut value_stack: Vec<String> = Vec::new();
+
     // Stack usado para almacenar todos los términos sintácticos
     // weights, definitions_count, actual_definition, actual_term
     // Contexto, definicion anterior, definición actual, termino actual a procesar
-    let mut processing_stack: Vec<(Context, Option<Rc<AstRule>>, Rc<AstRule>, Rc<Expr>)> =
-        Vec::new();
+    let mut processing_stack: Vec<StackItem> = Vec::new();
 
     // Add first term
-    let context: Context = Default::default();
+    let context = Context {
+        atomic: rule_atomicity(&rule, false),
+        ..Default::default()
+    };
+    if let Some(tree) = tree {
+        tree.open(&rule, 0);
+    }
     let rc_rule = Rc::new(rule.clone());
     let rc_expr = Rc::new(rule.expr);
     processing_stack.push((context, None, rc_rule, rc_expr));
 
     // Variable que contiene la cadena generada
-    let result = processing_terms(
-        &input_data,
+    processing_terms(
+        input_data,
         rng,
         config,
         0,
         processing_stack,
         &mut dynamic_blacklist,
-    );
-
-    result
+        &mut value_stack,
+        tree,
+    )
 }
 
 // depth level es una variable auxiliar para apoyar y detener la profundidad de la recursión
+#[allow(clippy::too_many_arguments)]
 fn processing_terms(
     input_data: &InputData,
-    rng: &mut ThreadRng,
+    rng: &mut dyn RandomSource,
     config: &GeneratorConfig,
     depth_level: usize,
-    processing_stack: Vec<(Context, Option<Rc<AstRule>>, Rc<AstRule>, Rc<Expr>)>,
+    processing_stack: Vec<StackItem>,
     dynamic_blacklist: &mut Vec<String>,
-) -> Result<String, String> {
+    value_stack: &mut Vec<String>,
+    tree: &mut Option<DerivationBuilder>,
+) -> Result<String, GenerationError> {
     // Call to processing_stack
     let result = processing_stack_fn(
         input_data,
@@ -159,34 +428,59 @@ fn processing_terms(
         depth_level,
         processing_stack,
         dynamic_blacklist,
-    );
+        value_stack,
+        tree,
+    )?;
 
-    Ok(result.unwrap().0)
+    Ok(result.0)
 }
 
 /// Retorna (result, count_output, count_nodes_processed, count_expand_idents)
 /// el resultado en String, los nodos procesados por la función, y los identificadores expandidos (reglas)
+///
+/// `value_stack` contiene los valores guardados con PUSH, se comparte entre las llamadas recursivas
+///
+/// `tree` es el árbol de derivación en construcción (si se pidió), los nodos abiertos en esta llamada se cierran
+/// al terminar
+#[allow(clippy::too_many_arguments)]
 fn processing_stack_fn(
     input_data: &InputData,
-    mut rng: &mut ThreadRng,
+    rng: &mut dyn RandomSource,
     config: &GeneratorConfig,
     depth_level: usize,
-    mut processing_stack: Vec<(Context, Option<Rc<AstRule>>, Rc<AstRule>, Rc<Expr>)>,
+    mut processing_stack: Vec<StackItem>,
     dynamic_blacklist: &mut Vec<String>,
-) -> Result<(String, usize, usize, usize), String> {
+    value_stack: &mut Vec<String>,
+    tree: &mut Option<DerivationBuilder>,
+) -> Result<(String, usize, usize, usize), GenerationError> {
     // Variable que contiene la cadena generada
     let mut result = String::new();
 
+    // Posición del texto de esta llamada en el ejemplo y nodos del árbol abiertos antes de esta llamada
+    let base = tree.as_ref().map_or(0, |tree| tree.base);
+    let open_nodes = tree.as_ref().map_or(0, |tree| tree.depth());
+
     // println!("Profundidad: {}", depth_level);
 
     // 32KB
     let stack_red_zone: usize = 32 * 1024;
     // println!("Stack restante: {}", stacker::remaining_stack().unwrap());
     // println!("Hard Limit: {}", hard_limit);
+    // Al alcanzar un límite las expresiones pendientes se cierran con su texto más corto
+    let mut closing = false;
     if depth_level > config.limit_depth_level
         || config.hard_limit < 1
         || stacker::remaining_stack().unwrap() < stack_red_zone
     {
+        if config.error_on_limit {
+            let limit = if config.hard_limit < 1 {
+                "hard_limit"
+            } else {
+                "limit_depth_level"
+            };
+            return Err(GenerationError::LimitReached(limit.to_string()));
+        }
+
         // Si el stack esta lleno, forzar un string vacio
         // En realidad debería retorna un error de generación
         // return Err(Error::RecursionLimit(format!(
@@ -195,7 +489,10 @@ fn processing_stack_fn(
         // )));
 
         // return Ok((result, 0, 0));
-        return Ok((config.text_expand_limit.to_owned(), 0, 0, 0));
+        if !config.shortest_completion_on_limit {
+            return Ok((config.text_expand_limit.to_owned(), 0, 0, 0));
+        }
+        closing = true;
     }
 
     // Counter of the number of 'strings' or output generated
@@ -216,9 +513,6 @@ fn processing_stack_fn(
     let mut upper_bound_repeated_sequence = config.upper_bound_zero_or_more_repetition;
     let mut upper_bound_repeated_one_sequence = config.upper_bound_one_or_more_repetition;
 
-    // Limite agresivo para detener la expansión interna de elementos y forzar la convergencia
-    let mut bool_hard_limit = false;
-
     // Variable de control usada para decidir si se debe seguir procesando una expr choice recursivamente
     let mut continue_processing_choice = false;
 
@@ -234,12 +528,30 @@ fn processing_stack_fn(
         // println!("TERM: {:?}", actual_expr);
         // result.push_str(" ' ");
 
+        // El marcador de fin de regla no cuenta como nodo procesado, así el ejemplo es el mismo con o sin árbol
+        if let Some(tree) = tree {
+            if matches!(&*actual_expr, Expr::Str(string) if string == TREE_END) {
+                tree.close(base + result.len());
+                continue;
+            }
+        }
+
+        // Las alternativas de un choice que se está eligiendo se siguen procesando, la elegida se cierra después
+        if closing && !continue_processing_choice {
+            count_output += 1;
+            let start = base + result.len();
+            result.push_str(&close_expr(input_data, config, &actual_expr, value_stack));
+            close_leaf(input_data, tree, &actual_expr, start, base + result.len());
+            continue;
+        }
+
         match &*actual_expr {
             // match actual_expr {
             // Matches an exact string, e.g. `"a"`
             Expr::Str(string) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -250,9 +562,12 @@ fn processing_stack_fn(
                         Rc::clone(&actual_rule),
                         previous_rule.clone(),
                         &mut processing_stack,
+                        tree,
                     )
                 } else {
-                    if string.starts_with("|BLACKLIST|I|") {
+                    if string.starts_with("|WEIGHT|") {
+                        // El peso solo se usa al elegir la alternativa de un choice
+                    } else if string.starts_with("|BLACKLIST|I|") {
                         add_blacklist_items(dynamic_blacklist, string, &input_data.clean_grammar);
                     } else if string.starts_with("|BLACKLIST|R|") {
                         remove_blacklist_items(
@@ -262,7 +577,7 @@ fn processing_stack_fn(
                         );
                     } else {
                         count_output += 1;
-                        result.push_str(&string);
+                        result.push_str(string);
                     }
                 }
             }
@@ -270,6 +585,7 @@ fn processing_stack_fn(
             Expr::Insens(string) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -280,6 +596,7 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
                 } else {
                     // FIXME: esto tal vez debería generar algo como hOla
@@ -298,6 +615,7 @@ fn processing_stack_fn(
             Expr::Range(initial_char, end_char) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -308,12 +626,13 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
                 } else {
                     // let mut rng = rand::thread_rng();
                     let from = initial_char.chars().next().unwrap();
                     let to = end_char.chars().next().unwrap();
-                    let random_char = rng.gen_range(from..=to);
+                    let random_char = rng.gen_char(from..=to);
 
                     count_output += 1;
                     result.push(random_char);
@@ -323,6 +642,7 @@ fn processing_stack_fn(
             Expr::Ident(name) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -333,7 +653,18 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
+                } else if let Some(text) = process_stack_builtin(name, value_stack) {
+                    // POP, PEEK, PEEK_ALL, POP_ALL y DROP reproducen o consumen los valores guardados con PUSH
+                    count_output += 1;
+                    result.push_str(&text);
+                } else if let Some(ranges) = input_data.unicode_ranges.get(name) {
+                    // Reglas Unicode predefinidas, se genera un codepoint de la propiedad
+                    if let Some(random_char) = ranges.random_char(rng) {
+                        count_output += 1;
+                        result.push(random_char);
+                    }
                 } else {
                     if config.rule_expand_limit.is_none()
                         || config.rule_expand_limit.unwrap() > count_expand_idents
@@ -344,6 +675,17 @@ fn processing_stack_fn(
                                     let mut new_context = context.clone();
                                     new_context.depth_count += 1;
                                     new_context.breadth_count = 0;
+                                    new_context.atomic = rule_atomicity(new_rule, context.atomic);
+                                    if let Some(tree) = tree {
+                                        // El nodo se cierra cuando se procesa el marcador, después del cuerpo
+                                        tree.open(new_rule, base + result.len());
+                                        processing_stack.push((
+                                            context.clone(),
+                                            previous_rule.clone(),
+                                            actual_rule.clone(),
+                                            Rc::new(Expr::Str(TREE_END.to_string())),
+                                        ));
+                                    }
                                     processing_stack.push((
                                         new_context,
                                         Some(actual_rule.clone()),
@@ -353,7 +695,7 @@ fn processing_stack_fn(
                                     count_expand_idents += 1;
                                 }
                                 None => {
-                                    unimplemented!("The rule hasn't been found: {}", &name);
+                                    return Err(GenerationError::RuleNotFound(name.to_string()));
                                 }
                             }
                         } else {
@@ -398,7 +740,7 @@ fn processing_stack_fn(
                                     None => {
                                         // println!("Loop detected in grammar");
                                         // return Err("Existe un ciclo en la gramática, se ha detenido la ejecución".to_string());
-                                        return Err("Loop detected in grammar, the execution has been stopped".to_string());
+                                        return Err(GenerationError::LoopDetected);
                                     }
                                 }
                             }
@@ -406,7 +748,23 @@ fn processing_stack_fn(
                     // }
                     } else {
                         // FIXME No se expande el identificador pero se adiciona un texto temporalmente, para que no salgan tantos tags vacios
-                        result.push_str(&config.text_expand_limit);
+                        if config.error_on_limit {
+                            return Err(GenerationError::LimitReached(
+                                "rule_expand_limit".to_string(),
+                            ));
+                        }
+                        if config.shortest_completion_on_limit {
+                            let start = base + result.len();
+                            result.push_str(&close_expr(
+                                input_data,
+                                config,
+                                &actual_expr,
+                                value_stack,
+                            ));
+                            close_leaf(input_data, tree, &actual_expr, start, base + result.len());
+                        } else {
+                            result.push_str(&config.text_expand_limit);
+                        }
                     }
                 }
             }
@@ -420,6 +778,7 @@ fn processing_stack_fn(
             Expr::Seq(lhs, rhs) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -430,17 +789,15 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
                 } else {
                     match &**lhs {
-                        // Si es una negación seguida de algo más el procesamiento implica un parseo
-                        Expr::NegPred(neg_expr) => {
-                            let mut new_processing_stack: Vec<(
-                                Context,
-                                Option<Rc<AstRule>>,
-                                Rc<AstRule>,
-                                Rc<Expr>,
-                            )> = Vec::new();
+                        // Si es una negación o un predicado positivo seguido de algo más el procesamiento implica un parseo
+                        // En `!A ~ B` se rechaza el ejemplo de B si A coincide, en `&A ~ B` si A no coincide
+                        Expr::NegPred(neg_expr) | Expr::PosPred(neg_expr) => {
+                            let positive_predicate = matches!(**lhs, Expr::PosPred(_));
+                            let mut new_processing_stack: Vec<StackItem> = Vec::new();
 
                             // Add first term
                             let mut new_context = context.clone();
@@ -452,9 +809,18 @@ fn processing_stack_fn(
                                 Rc::new(*rhs.clone()),
                             ));
 
-                            let mut invalid_neg_generation = false;
                             let mut count_remaining_attempts = config.max_attempts_negation;
+                            // Los intentos rechazados no deben modificar el stack de valores
+                            let value_stack_snapshot = value_stack.clone();
+                            let tree_snapshot = tree.as_ref().map(|tree| tree.snapshot());
                             loop {
+                                value_stack.clone_from(&value_stack_snapshot);
+                                if let (Some(tree), Some(snapshot)) =
+                                    (tree.as_mut(), &tree_snapshot)
+                                {
+                                    tree.restore(snapshot);
+                                    tree.base = base + result.len();
+                                }
                                 // Se usa un valor más pequeño de soft limit y hard limit para reducir posibilidad de OVERFLOW STACK
                                 let mut new_config = config.clone();
                                 if let Some(exp_lim) = config.rule_expand_limit {
@@ -465,13 +831,15 @@ fn processing_stack_fn(
                                 new_config.hard_limit =
                                     config.hard_limit.saturating_sub(count_nodes_processed);
 
-                                match processing_stack_fn(
-                                    &input_data,
+                                let invalid_neg_generation = match processing_stack_fn(
+                                    input_data,
                                     rng,
                                     &new_config,
                                     depth_level + 1,
                                     new_processing_stack.clone(),
                                     dynamic_blacklist,
+                                    value_stack,
+                                    tree,
                                 ) {
                                     Ok((
                                         result_neg,
@@ -480,35 +848,11 @@ fn processing_stack_fn(
                                         neg_count_expand_idents,
                                     )) => {
                                         // println!("Resultado negación: {}", &result_neg);
-                                        // Se hace un recorrido en toda la expresión de negación (B) unicamente revisando los Ident
-                                        let _item = neg_expr.clone().map_bottom_up(|inner_expr| {
-                                            match inner_expr.clone() {
-                                                Expr::Ident(name) => {
-                                                    let parsing = parse_input(
-                                                        input_data.clean_grammar.clone(),
-                                                        name.clone(),
-                                                        result_neg.clone(),
-                                                    );
-                                                    match parsing {
-                                                        // Si el parseo fue exitoso quiere decir que la secuencia generada es invalidad
-                                                        Ok(_) => {
-                                                            invalid_neg_generation = true;
-                                                        }
-                                                        Err(_a) => {
-                                                            //     println!(
-                                                            //     "Error de parseo en negación: {:?}, regla {}",
-                                                            //     _a,
-                                                            //     name
-                                                            // ),
-                                                            // Si el parseo no es exitoso se cumple la premisa A - B
-                                                            invalid_neg_generation = false
-                                                        }
-                                                    }
-                                                }
-                                                _ => {}
-                                            };
-                                            inner_expr
-                                        });
+                                        // Con `&A` A debe coincidir con el inicio de la secuencia generada, con `!A`
+                                        // no debe coincidir
+                                        let invalid_neg_generation =
+                                            prefix_matches(input_data, neg_expr, &result_neg)
+                                                != positive_predicate;
 
                                         // Si la secuencia generada es valida, es decir no hace parte de la negación se debe adicionar al string
                                         if !invalid_neg_generation {
@@ -518,29 +862,50 @@ fn processing_stack_fn(
                                             count_expand_idents += neg_count_expand_idents;
                                             result.push_str(&result_neg);
                                         }
+                                        invalid_neg_generation
                                     }
                                     error => {
                                         return error;
                                     }
-                                }
+                                };
 
+                                if let Some(tree) = tree {
+                                    tree.base = base;
+                                }
                                 if !invalid_neg_generation {
                                     break;
                                 }
 
-                                if count_remaining_attempts <= 0 {
-                                    println!(
-                                        "Exceso de intentos para A - B en: !{:?} ~ {:?}",
-                                        neg_expr, *rhs
-                                    );
-                                    break;
+                                if count_remaining_attempts == 0 {
+                                    // Exceso de intentos para A - B
+                                    if positive_predicate {
+                                        return Err(GenerationError::LookaheadAttemptsExhausted(
+                                            format!("&{} ~ {}", neg_expr, rhs),
+                                        ));
+                                    }
+                                    return Err(GenerationError::NegationAttemptsExhausted(
+                                        format!("!{} ~ {}", neg_expr, rhs),
+                                    ));
                                 }
 
                                 count_remaining_attempts -= 1;
                                 // println!("{}", count_remaining_attempts);
                             }
                         }
-                        // Si no es Una negación, la secuencia se procesa normalmente
+                        // pest agrupa `!A ~ B ~ C` como `(!A ~ B) ~ C`, se reagrupa como `!A ~ (B ~ C)` para que
+                        // el predicado se compruebe con toda la secuencia que le sigue
+                        Expr::Seq(inner_lhs, inner_rhs) if starts_with_predicate(inner_lhs) => {
+                            processing_stack.push((
+                                context,
+                                previous_rule,
+                                actual_rule,
+                                Rc::new(Expr::Seq(
+                                    inner_lhs.clone(),
+                                    Box::new(Expr::Seq(inner_rhs.clone(), rhs.clone())),
+                                )),
+                            ));
+                        }
+                        // Si no es una negación o un predicado positivo, la secuencia se procesa normalmente
                         _ => {
                             let mut new_context = context.clone();
                             new_context.breadth_count += 1;
@@ -552,6 +917,17 @@ fn processing_stack_fn(
                                 Rc::new(*rhs.clone()),
                             ));
 
+                            // Los marcadores (|BLACKLIST| y |WEIGHT|) no hacen parte del ejemplo
+                            if !matches!(&**lhs, Expr::Str(string) if is_marker(string)) {
+                                push_implicit_trivia(
+                                    input_data,
+                                    rng,
+                                    config,
+                                    &mut processing_stack,
+                                    (&new_context, &previous_rule, &actual_rule),
+                                );
+                            }
+
                             new_context.breadth_count += 1;
                             processing_stack.push((
                                 new_context,
@@ -607,11 +983,15 @@ fn processing_stack_fn(
                             continue_processing_choice = false;
                             choice_count = 0;
 
-                            let selected = random_definition(
-                                &vec![(**lhs).clone(), (**rhs).clone()],
-                                &mut rng,
-                            )
-                            .unwrap();
+                            let definitions = [(**lhs).clone(), (**rhs).clone()];
+                            let weights: Vec<u32> = definitions
+                                .iter()
+                                .map(|definition| {
+                                    alternative_weight(input_data, &actual_rule.name, definition)
+                                })
+                                .collect();
+                            let selected = random_definition(&definitions, &weights, rng).unwrap();
+                            choose_alternative(input_data, tree, &actual_rule, &selected);
                             // println!("SELECCTED: {:?}", &selected);
                             // processing_stack((Vec::new(), HashMap::new(), rule, rule.expr))
                             let mut new_context = context.clone();
@@ -632,6 +1012,7 @@ fn processing_stack_fn(
             Expr::Opt(expr) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -642,10 +1023,18 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
-                } else if !bool_hard_limit {
-                    let option = rng.gen_bool(0.5);
-                    if option {
+                } else {
+                    let option = rng.gen_bool(0.5) as u32;
+                    let option = coverage_repetitions(
+                        input_data,
+                        &actual_rule,
+                        &actual_expr,
+                        (0, 1),
+                        option,
+                    );
+                    if option == 1 {
                         let mut new_context = context.clone();
                         new_context.breadth_count += 1;
                         processing_stack.push((
@@ -660,47 +1049,60 @@ fn processing_stack_fn(
             // Matches an expression zero or more times, e.g. `e*`
             Expr::Rep(expr) => {
                 // HARD LIMIT
-                if processing_stack.len() > config.soft_limit {
+                if pending_items(&processing_stack, tree, open_nodes) > config.soft_limit {
                     upper_bound_repeated_sequence = 1;
                     upper_bound_repeated_one_sequence = 2;
-                } else if !bool_hard_limit {
-                    let num_reps = rng.gen_range(0..upper_bound_repeated_sequence);
-                    (1..num_reps + 1).for_each(|rep| {
-                        let mut new_context = context.clone();
-                        new_context.breadth_count += rep as usize;
-                        processing_stack.push((
-                            new_context,
-                            previous_rule.clone(),
-                            actual_rule.clone(),
-                            Rc::new(*expr.clone()),
-                        ))
-                    });
+                } else {
+                    let num_reps = rng.gen_range(0..=upper_bound_repeated_sequence - 1);
+                    let num_reps = coverage_repetitions(
+                        input_data,
+                        &actual_rule,
+                        &actual_expr,
+                        (0, upper_bound_repeated_sequence - 1),
+                        num_reps,
+                    );
+                    push_repetitions(
+                        input_data,
+                        rng,
+                        config,
+                        &mut processing_stack,
+                        (&context, &previous_rule, &actual_rule),
+                        expr,
+                        num_reps,
+                    );
                 }
             }
             // Matches an expression one or more times, e.g. `e+`
             Expr::RepOnce(expr) => {
                 // HARD LIMIT
-                if processing_stack.len() > config.soft_limit {
+                if pending_items(&processing_stack, tree, open_nodes) > config.soft_limit {
                     upper_bound_repeated_sequence = 1;
                     upper_bound_repeated_one_sequence = 2;
-                } else if !bool_hard_limit {
-                    let num_reps = rng.gen_range(1..upper_bound_repeated_one_sequence);
-                    (1..num_reps + 1).for_each(|rep| {
-                        let mut new_context = context.clone();
-                        new_context.breadth_count += rep as usize;
-                        processing_stack.push((
-                            new_context,
-                            previous_rule.clone(),
-                            actual_rule.clone(),
-                            Rc::new(*expr.clone()),
-                        ))
-                    });
+                } else {
+                    let num_reps = rng.gen_range(1..=upper_bound_repeated_one_sequence - 1);
+                    let num_reps = coverage_repetitions(
+                        input_data,
+                        &actual_rule,
+                        &actual_expr,
+                        (1, upper_bound_repeated_one_sequence - 1),
+                        num_reps,
+                    );
+                    push_repetitions(
+                        input_data,
+                        rng,
+                        config,
+                        &mut processing_stack,
+                        (&context, &previous_rule, &actual_rule),
+                        expr,
+                        num_reps,
+                    );
                 }
             }
             // Matches an expression an exact number of times, e.g. `e{n}`
             Expr::RepExact(expr, num_reps) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -711,24 +1113,25 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
-                } else if !bool_hard_limit {
-                    (1..num_reps + 1).for_each(|rep| {
-                        let mut new_context = context.clone();
-                        new_context.breadth_count += rep as usize;
-                        processing_stack.push((
-                            new_context,
-                            previous_rule.clone(),
-                            actual_rule.clone(),
-                            Rc::new(*expr.clone()),
-                        ))
-                    });
+                } else {
+                    push_repetitions(
+                        input_data,
+                        rng,
+                        config,
+                        &mut processing_stack,
+                        (&context, &previous_rule, &actual_rule),
+                        expr,
+                        *num_reps,
+                    );
                 }
             }
             // Matches an expression at least a number of times, e.g. `e{n,}`
             Expr::RepMin(expr, min_reps) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -739,26 +1142,34 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
-                } else if !bool_hard_limit {
+                } else {
                     let max_reps = min_reps + config.upper_bound_at_least_repetition;
                     let num_reps = rng.gen_range(*min_reps..=max_reps);
-                    (1..num_reps + 1).for_each(|rep| {
-                        let mut new_context = context.clone();
-                        new_context.breadth_count += rep as usize;
-                        processing_stack.push((
-                            new_context,
-                            previous_rule.clone(),
-                            actual_rule.clone(),
-                            Rc::new(*expr.clone()),
-                        ))
-                    });
+                    let num_reps = coverage_repetitions(
+                        input_data,
+                        &actual_rule,
+                        &actual_expr,
+                        (*min_reps, max_reps),
+                        num_reps,
+                    );
+                    push_repetitions(
+                        input_data,
+                        rng,
+                        config,
+                        &mut processing_stack,
+                        (&context, &previous_rule, &actual_rule),
+                        expr,
+                        num_reps,
+                    );
                 }
             }
             // Matches an expression at most a number of times, e.g. `e{,n}`
             Expr::RepMax(expr, max_reps) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -769,25 +1180,33 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
-                } else if !bool_hard_limit {
+                } else {
                     let num_reps = rng.gen_range(0..=*max_reps);
-                    (1..num_reps + 1).for_each(|rep| {
-                        let mut new_context = context.clone();
-                        new_context.breadth_count += rep as usize;
-                        processing_stack.push((
-                            new_context,
-                            previous_rule.clone(),
-                            actual_rule.clone(),
-                            Rc::new(*expr.clone()),
-                        ))
-                    });
+                    let num_reps = coverage_repetitions(
+                        input_data,
+                        &actual_rule,
+                        &actual_expr,
+                        (0, *max_reps),
+                        num_reps,
+                    );
+                    push_repetitions(
+                        input_data,
+                        rng,
+                        config,
+                        &mut processing_stack,
+                        (&context, &previous_rule, &actual_rule),
+                        expr,
+                        num_reps,
+                    );
                 }
             }
             // Matches an expression a number of times within a range, e.g. `e{m, n}`
             Expr::RepMinMax(expr, min_reps, max_reps) => {
                 if continue_processing_choice {
                     auxiliar_function(
+                        input_data,
                         rng,
                         &mut choice_count,
                         &mut selected_choice,
@@ -798,40 +1217,134 @@ fn processing_stack_fn(
                         actual_rule,
                         previous_rule,
                         &mut processing_stack,
+                        tree,
                     )
-                } else if !bool_hard_limit {
+                } else {
                     let num_reps = rng.gen_range(*min_reps..=*max_reps);
-                    (1..num_reps + 1).for_each(|rep| {
-                        let mut new_context = context.clone();
-                        new_context.breadth_count += rep as usize;
-                        processing_stack.push((
-                            new_context,
-                            previous_rule.clone(),
-                            actual_rule.clone(),
-                            Rc::new(*expr.clone()),
-                        ))
-                    });
+                    let num_reps = coverage_repetitions(
+                        input_data,
+                        &actual_rule,
+                        &actual_expr,
+                        (*min_reps, *max_reps),
+                        num_reps,
+                    );
+                    push_repetitions(
+                        input_data,
+                        rng,
+                        config,
+                        &mut processing_stack,
+                        (&context, &previous_rule, &actual_rule),
+                        expr,
+                        num_reps,
+                    );
+                }
+            }
+            // Matches a custom part of the stack, e.g. `PEEK[..]`
+            Expr::PeekSlice(start, end) => {
+                if continue_processing_choice {
+                    auxiliar_function(
+                        input_data,
+                        rng,
+                        &mut choice_count,
+                        &mut selected_choice,
+                        &mut continue_processing_choice,
+                        &mut last_processing_choice,
+                        context,
+                        actual_expr,
+                        actual_rule,
+                        previous_rule,
+                        &mut processing_stack,
+                        tree,
+                    )
+                } else {
+                    count_output += 1;
+                    result.push_str(&peek_slice(value_stack, *start, *end));
                 }
-            } //     /// Continues to match expressions until one of the strings in the `Vec` is found
-            //     Expr::Skip(Vec<String>),
-            //     /// Matches an expression and pushes it to the stack, e.g. `push(e)`
-            // Expr::Push(Box<Expr>),
-            Expr::PeekSlice(_, _) => {}
+            }
+            // Matches an expression and pushes it to the stack, e.g. `push(e)`
+            Expr::Push(expr) => {
+                if continue_processing_choice {
+                    auxiliar_function(
+                        input_data,
+                        rng,
+                        &mut choice_count,
+                        &mut selected_choice,
+                        &mut continue_processing_choice,
+                        &mut last_processing_choice,
+                        context,
+                        actual_expr,
+                        actual_rule,
+                        previous_rule,
+                        &mut processing_stack,
+                        tree,
+                    )
+                } else {
+                    // La expresión se genera por separado para conocer el texto que se debe guardar en el stack
+                    let mut new_context = context.clone();
+                    new_context.breadth_count += 1;
+                    let new_processing_stack: Vec<StackItem> = vec![(
+                        new_context,
+                        previous_rule,
+                        actual_rule,
+                        Rc::new(*expr.clone()),
+                    )];
+
+                    let mut new_config = config.clone();
+                    if let Some(exp_lim) = config.rule_expand_limit {
+                        new_config.rule_expand_limit =
+                            Some(exp_lim.saturating_sub(count_expand_idents));
+                    }
+                    new_config.hard_limit = config.hard_limit.saturating_sub(count_nodes_processed);
+
+                    if let Some(tree) = tree {
+                        tree.base = base + result.len();
+                    }
+                    let (
+                        pushed,
+                        push_count_output,
+                        push_count_nodes_processed,
+                        push_count_expand_idents,
+                    ) = processing_stack_fn(
+                        input_data,
+                        rng,
+                        &new_config,
+                        depth_level + 1,
+                        new_processing_stack,
+                        dynamic_blacklist,
+                        value_stack,
+                        tree,
+                    )?;
+                    if let Some(tree) = tree {
+                        tree.base = base;
+                    }
+
+                    count_output += push_count_output;
+                    count_nodes_processed += push_count_nodes_processed;
+                    count_expand_idents += push_count_expand_idents;
+                    result.push_str(&pushed);
+                    value_stack.push(pushed);
+                }
+            }
+            // Continues to match expressions until one of the strings in the `Vec` is found
+            Expr::Skip(_) => {}
             Expr::PosPred(_) => {}
             Expr::NegPred(_) => {}
-            Expr::Skip(_) => {}
-            Expr::Push(_) => {}
         }
         // println!("{:?}", result);
         // println!("Len: {}", processing_stack.len());
         // println!("Nodes processed: {}", count_nodes_processed);
         count_nodes_processed += 1;
 
-        if count_nodes_processed > config.hard_limit {
-            // Activar HARD LIMIT
-            bool_hard_limit = true;
+        if !closing && count_nodes_processed > config.hard_limit {
+            // HARD LIMIT alcanzado
             // println!("HARD LIMIT REACHED: {}", config.hard_limit);
-            break;
+            if config.error_on_limit && !processing_stack.is_empty() {
+                return Err(GenerationError::LimitReached("hard_limit".to_string()));
+            }
+            if !config.shortest_completion_on_limit {
+                break;
+            }
+            closing = true;
         }
 
         if let Some(config_count) = config.terminals_limit {
@@ -843,6 +1356,9 @@ fn processing_stack_fn(
     }
 
     // println!("Nodes processed: {}", count_nodes_processed);
+    if let Some(tree) = tree {
+        tree.close_until(open_nodes, base + result.len());
+    }
     Ok((
         result,
         count_output,
@@ -851,21 +1367,322 @@ fn processing_stack_fn(
     ))
 }
 
+/// Texto más corto de una expresión pendiente cuando se alcanza un límite, `text_expand_limit` si la expresión
+/// usa una regla sin derivación finita
+fn close_expr(
+    input_data: &InputData,
+    config: &GeneratorConfig,
+    expr: &Expr,
+    value_stack: &mut Vec<String>,
+) -> String {
+    shortest_text(
+        expr,
+        &input_data.grammar,
+        &input_data.unicode_ranges,
+        &input_data.minimal_derivations,
+        value_stack,
+    )
+    .unwrap_or_else(|| config.text_expand_limit.to_owned())
+}
+
+/// Adiciona al árbol de derivación un nodo sin hijos si `expr` es una regla de la gramática cerrada con su texto
+/// más corto entre `start` y `end`
+fn close_leaf(
+    input_data: &InputData,
+    tree: &mut Option<DerivationBuilder>,
+    expr: &Expr,
+    start: usize,
+    end: usize,
+) {
+    if let (Some(tree), Expr::Ident(name)) = (tree, expr) {
+        if let Some(rule) = input_data.grammar.rules.get(name) {
+            tree.leaf(rule, start, end);
+        }
+    }
+}
+
+/// Elementos pendientes del stack sin contar los marcadores de fin de regla del árbol de derivación (uno por cada
+/// nodo abierto en la llamada actual)
+fn pending_items(
+    processing_stack: &[StackItem],
+    tree: &Option<DerivationBuilder>,
+    open_nodes: usize,
+) -> usize {
+    let markers = tree.as_ref().map_or(0, |tree| tree.depth() - open_nodes);
+    processing_stack.len() - markers
+}
+
+/// Procesa las reglas predefinidas que operan sobre el stack de valores (POP, PEEK, PEEK_ALL, POP_ALL y DROP)
+///
+/// Retorna el texto que se debe generar o None si `name` no es una de esas reglas.
+/// Con el stack vacío pest no podría parsear el ejemplo, en ese caso no se genera nada
+fn process_stack_builtin(name: &str, value_stack: &mut Vec<String>) -> Option<String> {
+    match name {
+        "PEEK" => Some(value_stack.last().cloned().unwrap_or_default()),
+        "POP" => Some(value_stack.pop().unwrap_or_default()),
+        "DROP" => {
+            value_stack.pop();
+            Some(String::new())
+        }
+        // Se reproduce el stack completo desde el tope hasta la base
+        "PEEK_ALL" => Some(value_stack.iter().rev().map(String::as_str).collect()),
+        "POP_ALL" => {
+            let text = value_stack.iter().rev().map(String::as_str).collect();
+            value_stack.clear();
+            Some(text)
+        }
+        _ => None,
+    }
+}
+
+/// Texto de `PEEK[start..end]`, los índices negativos se cuentan desde el tope del stack como en pest
+///
+/// pest recorre el rango desde la base hasta el tope, si el rango no es válido no se genera nada
+fn peek_slice(value_stack: &[String], start: i32, end: Option<i32>) -> String {
+    let normalize = |index: i32| {
+        let len = value_stack.len() as i32;
+        match index {
+            index if index > len => None,
+            index if index >= 0 => Some(index as usize),
+            index if len + index >= 0 => Some((len + index) as usize),
+            _ => None,
+        }
+    };
+
+    let start = normalize(start);
+    let end = end.map_or(Some(value_stack.len()), normalize);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => value_stack[start..end].concat(),
+        _ => String::new(),
+    }
+}
+
+/// Retorna true si la expresión es una secuencia que comienza con un predicado (`!A` o `&A`)
+fn starts_with_predicate(expr: &Expr) -> bool {
+    match expr {
+        Expr::NegPred(_) | Expr::PosPred(_) => true,
+        Expr::Seq(lhs, _) => starts_with_predicate(lhs),
+        _ => false,
+    }
+}
+
+/// Nombre de la regla temporal usada para parsear expresiones que no son un identificador
+const PREFIX_RULE: &str = "__bulk_examples_generator_prefix";
+
+/// Retorna true si la expresión `expr` coincide con el inicio de `input` según la gramática
+///
+/// pest no exige que la regla consuma toda la entrada, por eso parsear equivale a buscar un prefijo
+fn prefix_matches(input_data: &InputData, expr: &Expr, input: &str) -> bool {
+    if let Expr::Ident(name) = expr {
+        return input_data.parser.parse(name, input).is_ok();
+    }
+    // Los predicados de la gramática ya tienen su regla en el parser, se comparan sin los marcadores
+    match input_data
+        .parser
+        .parse_predicate(&clean_expr(expr.clone()), input)
+    {
+        Some(matches) => matches,
+        None => {
+            // Se adiciona una regla temporal con la expresión para que pest la pueda parsear
+            let mut prefix_grammar = (*input_data.clean_grammar).clone();
+            prefix_grammar.rules.insert(
+                PREFIX_RULE.to_string(),
+                AstRule {
+                    name: PREFIX_RULE.to_string(),
+                    ty: RuleType::Normal,
+                    expr: expr.clone(),
+                },
+            );
+            crate::parse_with_grammar(&prefix_grammar, PREFIX_RULE, input).is_ok()
+        }
+    }
+}
+
+/// Atomicidad de una regla como en pest: `@` y `$` la activan, `!` la desactiva y las demás reglas la heredan,
+/// WHITESPACE y COMMENT siempre son atómicas
+fn rule_atomicity(rule: &AstRule, inherited: bool) -> bool {
+    if rule.name == "WHITESPACE" || rule.name == "COMMENT" {
+        return true;
+    }
+    match rule.ty {
+        RuleType::Atomic | RuleType::CompoundAtomic => true,
+        RuleType::NonAtomic => false,
+        RuleType::Normal | RuleType::Silent => inherited,
+    }
+}
+
+/// Adiciona al stack los WHITESPACE y COMMENT implícitos que pest acepta entre los elementos de una secuencia o
+/// repetición, solo si existen en la gramática y no se está dentro de una regla atómica
+fn push_implicit_trivia(
+    input_data: &InputData,
+    rng: &mut dyn RandomSource,
+    config: &GeneratorConfig,
+    processing_stack: &mut Vec<StackItem>,
+    (context, previous_rule, actual_rule): (&Context, &Option<Rc<AstRule>>, &Rc<AstRule>),
+) {
+    if context.atomic {
+        return;
+    }
+
+    let trivia: Vec<&str> = ["WHITESPACE", "COMMENT"]
+        .iter()
+        .copied()
+        .filter(|name| input_data.grammar.rules.contains_key(*name))
+        .collect();
+    if trivia.is_empty() {
+        return;
+    }
+
+    // NaN pasaría sin cambios por clamp y gen_bool falla con probabilidades fuera de [0, 1]
+    let probability = if config.implicit_whitespace_probability.is_finite() {
+        config.implicit_whitespace_probability.clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let mut count = 0;
+    while count < config.upper_bound_zero_or_more_repetition && rng.gen_bool(probability) {
+        let name = trivia[rng.choose(trivia.len()).unwrap()];
+        processing_stack.push((
+            context.clone(),
+            previous_rule.clone(),
+            actual_rule.clone(),
+            Rc::new(Expr::Ident(name.to_string())),
+        ));
+        count += 1;
+    }
+}
+
+/// Adiciona al stack `num_reps` repeticiones de `expr`, con WHITESPACE y COMMENT implícitos entre ellas
+fn push_repetitions(
+    input_data: &InputData,
+    rng: &mut dyn RandomSource,
+    config: &GeneratorConfig,
+    processing_stack: &mut Vec<StackItem>,
+    (context, previous_rule, actual_rule): (&Context, &Option<Rc<AstRule>>, &Rc<AstRule>),
+    expr: &Expr,
+    num_reps: u32,
+) {
+    for rep in 1..num_reps + 1 {
+        if rep > 1 {
+            push_implicit_trivia(
+                input_data,
+                rng,
+                config,
+                processing_stack,
+                (context, previous_rule, actual_rule),
+            );
+        }
+
+        let mut new_context = context.clone();
+        new_context.breadth_count += rep as usize;
+        processing_stack.push((
+            new_context,
+            previous_rule.clone(),
+            actual_rule.clone(),
+            Rc::new(expr.clone()),
+        ));
+    }
+}
+
 /// Random entre Simbolos |
-pub fn random_definition(definitions: &Vec<Expr>, rng: &mut ThreadRng) -> Result<Expr, String> {
+///
+/// Cada alternativa se elige con una probabilidad proporcional a su peso, ver `alternative_weight`
+pub fn random_definition(
+    definitions: &[Expr],
+    weights: &[u32],
+    rng: &mut dyn RandomSource,
+) -> Result<Expr, GenerationError> {
     // println!("Selección aleatoria: {:?}", &definitions);
-    match definitions.choose(rng) {
-        Some(selected) => {
-            // println!("Gano: {:?}", &selected);
-            Ok(selected.to_owned())
+    let total = weights
+        .iter()
+        .fold(0u32, |total, weight| total.saturating_add(*weight));
+    if total == 0 {
+        // Todas las alternativas tienen peso 0 (o no hay alternativas), se elige cualquiera
+        return rng
+            .choose(definitions.len())
+            .map(|index| definitions[index].clone())
+            .ok_or(GenerationError::RandomChoose);
+    }
+
+    // Con todos los pesos en 1 se obtiene el mismo número aleatorio que con `choose`
+    let mut position = rng.gen_range(0..=total - 1);
+    for (definition, weight) in definitions.iter().zip(weights.iter().copied()) {
+        if position < weight {
+            // println!("Gano: {:?}", &definition);
+            return Ok(definition.to_owned());
         }
-        None => Err("crate::error::GenerationError::RandomChoose".to_string()),
+        position -= weight;
+    }
+    Err(GenerationError::RandomChoose)
+}
+
+/// Peso de una alternativa de un choice (`"|WEIGHT|n|"`, por defecto 1), en modo cobertura las alternativas que
+/// aún no han sido generadas tienen más peso
+fn alternative_weight(input_data: &InputData, rule: &str, alternative: &Expr) -> u32 {
+    match &input_data.coverage {
+        Some(coverage) => coverage.alternative_weight(rule, alternative),
+        None => branch_weight(alternative),
     }
 }
 
+/// Registra la alternativa elegida en un choice de `actual_rule` en la cobertura y en el árbol de derivación
+fn choose_alternative(
+    input_data: &InputData,
+    tree: &mut Option<DerivationBuilder>,
+    actual_rule: &AstRule,
+    selected: &Expr,
+) {
+    if let Some(coverage) = &input_data.coverage {
+        coverage.cover_alternative(&actual_rule.name, selected);
+    }
+    if let Some(tree) = tree {
+        tree.choose(actual_rule, selected);
+    }
+}
+
+/// En modo cobertura, si alguno de los límites de la repetición no ha sido generado se usa ese límite,
+/// si no se conserva `num_reps`
+fn coverage_repetitions(
+    input_data: &InputData,
+    actual_rule: &AstRule,
+    repetition: &Expr,
+    bounds: (u32, u32),
+    num_reps: u32,
+) -> u32 {
+    match &input_data.coverage {
+        Some(coverage) => coverage.repetitions(&actual_rule.name, repetition, bounds, num_reps),
+        None => num_reps,
+    }
+}
+
+/// Retorna true si el texto es un marcador del generador (`|BLACKLIST|` o `|WEIGHT|`) que no hace parte del ejemplo
+fn is_marker(string: &str) -> bool {
+    string.starts_with("|BLACKLIST|I|")
+        || string.starts_with("|BLACKLIST|R|")
+        || string.starts_with("|WEIGHT|")
+}
+
+/// Peso de una alternativa de un choice, indicado con `"|WEIGHT|n|"` como primer elemento de la alternativa
+///
+/// Si no hay peso o no es un número válido el peso es 1
+fn branch_weight(expr: &Expr) -> u32 {
+    match expr {
+        Expr::Str(string) if string.starts_with("|WEIGHT|") => string
+            .trim_start_matches("|WEIGHT|")
+            .trim_end_matches('|')
+            .trim()
+            .parse()
+            .unwrap_or(1),
+        Expr::Seq(lhs, _) => branch_weight(lhs),
+        _ => 1,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn auxiliar_function(
-    rng: &mut ThreadRng,
-    choice_count: &mut i32,
+    input_data: &InputData,
+    rng: &mut dyn RandomSource,
+    choice_count: &mut u32,
     selected_choice: &mut Rc<Expr>,
     continue_processing_choice: &mut bool,
     last_processing_choice: &mut ProcessingChoices,
@@ -873,49 +1690,53 @@ fn auxiliar_function(
     actual_expr: Rc<Expr>,
     actual_rule: Rc<AstRule>,
     previous_rule: Option<Rc<AstRule>>,
-    processing_stack: &mut Vec<(Context, Option<Rc<AstRule>>, Rc<AstRule>, Rc<Expr>)>,
+    processing_stack: &mut Vec<StackItem>,
+    tree: &mut Option<DerivationBuilder>,
 ) {
+    // Muestreo de reservorio ponderado, `choice_count` es la suma de los pesos de las alternativas procesadas
+    let weight = alternative_weight(input_data, &actual_rule.name, &actual_expr);
     if *choice_count == 0 {
+        // Primera alternativa (o todas las anteriores tienen peso 0)
         *selected_choice = Rc::clone(&actual_expr);
-        *choice_count += 1;
+        *choice_count = weight;
     } else {
         // println!(
         //     "Actual: {:?} vs Selected: {:?}",
         //     actual_expr, selected_choice
         // );
-        // println!("Range selection: [{}, {})", 0, *choice_count + 1);
-        let num = rng.gen_range(0..=*choice_count);
-        if num == *choice_count {
+        // println!("Range selection: [{}, {})", 0, *choice_count + weight);
+        let total = choice_count.saturating_add(weight);
+        let num = rng.gen_range(0..=total - 1);
+        if num >= *choice_count {
             *selected_choice = Rc::clone(&actual_expr);
         }
+        *choice_count = total;
         // println!("Choice count: {} - Num: {}", choice_count, num);
         // println!("WIN: {:?}", selected_choice);
         // println!("Last processing choice: {:?}", last_processing_choice);
+    }
 
-        match *last_processing_choice {
-            ProcessingChoices::LastOne => {
-                processing_stack.push((
-                    context.clone(),
-                    previous_rule.clone(),
-                    actual_rule.clone(),
-                    Rc::clone(&selected_choice),
-                ));
-
-                // Reinicio de variables
-                *last_processing_choice = ProcessingChoices::No;
-                *continue_processing_choice = false;
-                *selected_choice = Rc::new(Expr::Str("RESERVED".to_string()));
-                // -1 Para evitar que RESERVED entre en comparación
-                // choice_count al final de esta sección terminaria valiendo cero
-                *choice_count = -1;
-            }
-            // Si se estan procesando las últimas dos opciones posibles
-            ProcessingChoices::LastTwo => {
-                *last_processing_choice = ProcessingChoices::LastOne;
-            }
-            ProcessingChoices::No => {}
+    match *last_processing_choice {
+        ProcessingChoices::LastOne => {
+            choose_alternative(input_data, tree, &actual_rule, selected_choice);
+            processing_stack.push((
+                context,
+                previous_rule,
+                actual_rule,
+                Rc::clone(selected_choice),
+            ));
+
+            // Reinicio de variables
+            *last_processing_choice = ProcessingChoices::No;
+            *continue_processing_choice = false;
+            *selected_choice = Rc::new(Expr::Str("RESERVED".to_string()));
+            *choice_count = 0;
         }
-        *choice_count += 1;
+        // Si se estan procesando las últimas dos opciones posibles
+        ProcessingChoices::LastTwo => {
+            *last_processing_choice = ProcessingChoices::LastOne;
+        }
+        ProcessingChoices::No => {}
     }
 }
 
@@ -964,16 +1785,16 @@ fn rule_is_only_ident_choices(grammar: &Grammar, blacklisted_ident: &str) -> Opt
                 }
             }
         }
-        return Some(idents);
+        Some(idents)
     } else {
-        return None;
+        None
     }
 }
 
 /**
  * Retorna true si encuentra un ciclo infinito
  */
-fn verify_infinite_loop_blacklist(grammar: &Grammar, ident: &str, blacklist: &Vec<String>) -> bool {
+fn verify_infinite_loop_blacklist(grammar: &Grammar, ident: &str, blacklist: &[String]) -> bool {
     match rule_is_only_ident_choices(grammar, ident) {
         Some(idents) => {
             for ident_item in idents {
@@ -987,7 +1808,7 @@ fn verify_infinite_loop_blacklist(grammar: &Grammar, ident: &str, blacklist: &Ve
     }
 }
 
-fn add_blacklist_items(blacklist: &mut Vec<String>, string: &String, grammar: &Grammar) {
+fn add_blacklist_items(blacklist: &mut Vec<String>, string: &str, grammar: &Grammar) {
     // Adicionar un ident a la blacklist
     let blacklisted_idents = string
         .trim_start_matches("|BLACKLIST|I|")
@@ -995,7 +1816,7 @@ fn add_blacklist_items(blacklist: &mut Vec<String>, string: &String, grammar: &G
         .split(",");
 
     for ident in blacklisted_idents {
-        if let Some(mut rules) = rule_is_only_ident_choices(grammar, &ident) {
+        if let Some(mut rules) = rule_is_only_ident_choices(grammar, ident) {
             // Si la regla se compone unicamente de choices donde cada choice es un ident, adicionar todas las choices
             // println!("{:?}", rules);
             blacklist.append(&mut rules);
@@ -1006,7 +1827,7 @@ fn add_blacklist_items(blacklist: &mut Vec<String>, string: &String, grammar: &G
     }
 }
 
-fn remove_blacklist_items(blacklist: &mut Vec<String>, string: &String, grammar: &Grammar) {
+fn remove_blacklist_items(blacklist: &mut Vec<String>, string: &str, grammar: &Grammar) {
     // Remover un ident de la blacklist
     let blacklisted_idents = string
         .trim_start_matches("|BLACKLIST|R|")
@@ -1014,7 +1835,7 @@ fn remove_blacklist_items(blacklist: &mut Vec<String>, string: &String, grammar:
         .split(",");
 
     for ident in blacklisted_idents {
-        if let Some(rules) = rule_is_only_ident_choices(grammar, &ident) {
+        if let Some(rules) = rule_is_only_ident_choices(grammar, ident) {
             // Si la regla se compone unicamente de choices donde cada choice es un ident, remover todas las choices
             // dynamic_blacklist.retain(|r| !rules.contains(r));
 
@@ -1055,8 +1876,11 @@ fn test_only_ident_in_rules() {
     )
     .unwrap();
 
-    let mut results = rule_is_only_ident_choices(&g, &"Example").unwrap();
-    assert_eq!(vec!["Uno", "Dos", "Tres", "Cuatro"].sort(), results.sort());
+    let mut results = rule_is_only_ident_choices(&g, "Example").unwrap();
+    results.sort();
+    let mut expected = vec!["Uno", "Dos", "Tres", "Cuatro"];
+    expected.sort_unstable();
+    assert_eq!(expected, results);
 }
 
 #[test]
@@ -1072,7 +1896,7 @@ fn test_only_ident_in_rules_1() {
     )
     .unwrap();
 
-    let results = rule_is_only_ident_choices(&g, &"Example");
+    let results = rule_is_only_ident_choices(&g, "Example");
     assert_eq!(None, results);
 }
 
@@ -1089,6 +1913,32 @@ fn test_only_ident_in_rules_2() {
     )
     .unwrap();
 
-    let results = rule_is_only_ident_choices(&g, &"Example");
+    let results = rule_is_only_ident_choices(&g, "Example");
     assert_eq!(None, results);
 }
+
+#[test]
+fn test_parser_predicate_rules() {
+    let g = compile_grammar(
+        r#"
+        word = { ASCII_ALPHA+ }
+        keyword = { &("if" | "else") ~ word }
+        other = { !("if" | "else") ~ word }
+    "#
+        .to_string(),
+    )
+    .unwrap();
+    let input_data = InputData::from_grammar(g);
+
+    // Los dos predicados tienen la misma expresión, se compila una sola regla temporal
+    assert_eq!(1, input_data.parser.predicates.len());
+    let predicate = &input_data.parser.predicates[0].0;
+    assert!(prefix_matches(&input_data, predicate, "iffy"));
+    assert!(!prefix_matches(&input_data, predicate, "word"));
+    assert_eq!(
+        None,
+        input_data
+            .parser
+            .parse_predicate(&Expr::Str("x".to_string()), "x")
+    );
+}
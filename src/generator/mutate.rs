@@ -0,0 +1,191 @@
+use super::{example_rng, traverse, InputData};
+use crate::config::GeneratorConfig;
+use crate::error::{Error, GenerationError};
+
+use pest_vm::Vm;
+use rand::prelude::*;
+use serde_derive::Serialize;
+use std::fmt;
+
+/// Variant of a seed input created by replacing subtrees of the seed
+///
+/// The seed is parsed with the grammar and the text of a pair (a subtree of some rule) is replaced, the rest of the
+/// seed is kept. Only the variants that can be parsed with the grammar are returned
+///
+/// ```
+/// use bulk_examples_generator::Generator;
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go"}
+///         sentence = {"I like " ~ language ~ "."}
+///     "#;
+///
+/// let generator = Generator::new(grammar.to_string()).unwrap();
+/// let mutated = generator.mutate("sentence", "I like Go.").unwrap();
+/// assert!(generator.parse("sentence", &mutated.text).is_ok());
+/// for mutation in &mutated.mutations {
+///     println!("{}", mutation);
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MutatedExample {
+    pub text: String,
+    /// Mutations applied to the seed, in order
+    pub mutations: Vec<SubtreeMutation>,
+}
+
+/// Replacement of the text of a subtree
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SubtreeMutation {
+    pub kind: SubtreeMutationKind,
+    /// Rule of the subtree
+    pub rule: String,
+    /// Byte offset where the subtree begins, in the text before the mutation
+    pub start: usize,
+    /// Byte offset where the subtree ends (exclusive), in the text before the mutation
+    pub end: usize,
+    /// Text that replaces the subtree
+    pub replacement: String,
+}
+
+/// Kinds of mutations of a seed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtreeMutationKind {
+    /// The subtree is generated again beginning in its rule
+    Regenerate,
+    /// The subtree is replaced with a subtree of the same rule of another seed
+    Splice,
+}
+
+impl fmt::Display for SubtreeMutationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SubtreeMutationKind::Regenerate => "regenerate",
+            SubtreeMutationKind::Splice => "splice",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// e.g. `regenerate language (7, 9): "Rust"`
+impl fmt::Display for SubtreeMutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({}, {}): {:?}",
+            self.kind, self.rule, self.start, self.end, self.replacement
+        )
+    }
+}
+
+/// Subárbol de un texto parseado: regla, inicio y fin
+type Subtree = (String, usize, usize);
+
+/// Crea la variante número `index` de `seed`, `donor` es la semilla de la que se toman los subárboles en modo splice
+///
+/// Se aplican `mutations_per_example` mutaciones, en cada una se reemplaza un subárbol elegido al azar y se acepta
+/// el resultado si la gramática lo puede parsear, con a lo sumo `max_attempts_mutation` intentos
+pub(crate) fn mutate_seed(
+    input_data: &InputData,
+    config: &GeneratorConfig,
+    start_rule: &str,
+    seed: &str,
+    donor: Option<&str>,
+    index: u64,
+) -> Result<MutatedExample, Error> {
+    if !input_data.clean_grammar.rules.contains_key(start_rule) {
+        return Err(Error::UnknownStartRule(start_rule.to_string()));
+    }
+    input_data.parser.parse(start_rule, seed)?;
+
+    let vm = input_data.parser.vm();
+    // Subárboles de la semilla donante agrupados por regla
+    let donor_subtrees = match donor {
+        Some(donor) => {
+            input_data.parser.parse(start_rule, donor)?;
+            let subtrees = subtrees(input_data, vm, start_rule, donor).unwrap_or_default();
+            Some((donor, subtrees))
+        }
+        None => None,
+    };
+
+    let mut rng = example_rng(config.seed, index);
+    let mut text = seed.to_string();
+    let mut mutations = vec![];
+    for _ in 0..config.mutations_per_example {
+        let candidates = subtrees(input_data, vm, start_rule, &text).unwrap_or_default();
+        let mut mutation = None;
+
+        for _ in 0..config.max_attempts_mutation {
+            let (rule, start, end) = match candidates.choose(&mut rng) {
+                Some(subtree) => subtree,
+                None => break,
+            };
+            let (kind, replacement) = match &donor_subtrees {
+                Some((donor, donor_subtrees)) => {
+                    // Solo los subárboles de la misma regla
+                    let same_rule: Vec<&Subtree> = donor_subtrees
+                        .iter()
+                        .filter(|(donor_rule, _, _)| donor_rule == rule)
+                        .collect();
+                    match same_rule.choose(&mut rng) {
+                        Some((_, donor_start, donor_end)) => (
+                            SubtreeMutationKind::Splice,
+                            donor[*donor_start..*donor_end].to_string(),
+                        ),
+                        None => continue,
+                    }
+                }
+                None => match traverse(input_data, rule, &mut rng, config) {
+                    Ok(replacement) => (SubtreeMutationKind::Regenerate, replacement),
+                    Err(_) => continue,
+                },
+            };
+
+            let candidate = format!("{}{}{}", &text[..*start], replacement, &text[*end..]);
+            if vm.parse(start_rule, &candidate).is_ok() {
+                mutation = Some((
+                    candidate,
+                    SubtreeMutation {
+                        kind,
+                        rule: rule.clone(),
+                        start: *start,
+                        end: *end,
+                        replacement,
+                    },
+                ));
+                break;
+            }
+        }
+
+        match mutation {
+            Some((candidate, mutation)) => {
+                text = candidate;
+                mutations.push(mutation);
+            }
+            None => {
+                return Err(
+                    GenerationError::MutationAttemptsExhausted(start_rule.to_string()).into(),
+                )
+            }
+        }
+    }
+
+    Ok(MutatedExample { text, mutations })
+}
+
+/// Pares que produce el parseo de `text`, solo los de las reglas de la gramática (no EOI)
+fn subtrees(input_data: &InputData, vm: &Vm, start_rule: &str, text: &str) -> Option<Vec<Subtree>> {
+    let pairs = vm.parse(start_rule, text).ok()?;
+    Some(
+        pairs
+            .flatten()
+            .filter(|pair| input_data.grammar.rules.contains_key(pair.as_rule()))
+            .map(|pair| {
+                let span = pair.as_span();
+                (pair.as_rule().to_string(), span.start(), span.end())
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,360 @@
+use super::analysis::reachable_rules;
+use super::{example_rng, is_marker, traverse, Grammar, InputData};
+use crate::config::GeneratorConfig;
+use crate::error::GenerationError;
+
+use pest_meta::ast::Expr;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Example that the grammar must reject, with the mutation used to create it
+///
+/// The example isn't a valid example with its text changed: it's generated from a copy of the grammar where one
+/// expression of a rule reachable from the start rule was mutated, only the examples that can't be parsed with the
+/// original grammar are returned
+///
+/// ```
+/// use bulk_examples_generator::Generator;
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go"}
+///         sentence = {"I like " ~ language ~ "."}
+///     "#;
+///
+/// let generator = Generator::new(grammar.to_string()).unwrap();
+/// let invalid = generator.generate_invalid("sentence").unwrap();
+/// assert!(generator.parse("sentence", &invalid.text).is_err());
+/// println!("{}: {}", invalid.mutation, invalid.text);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InvalidExample {
+    pub text: String,
+    pub mutation: Mutation,
+}
+
+/// Mutation applied to an expression of the grammar
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Mutation {
+    pub kind: MutationKind,
+    /// Rule that contains the mutated expression
+    pub rule: String,
+    /// Expression before the mutation
+    pub original: String,
+    /// Expression after the mutation
+    pub replacement: String,
+}
+
+/// Kinds of mutations used to create invalid examples
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MutationKind {
+    /// A required token of a sequence is removed, e.g. `"(" ~ expr ~ ")"` to `"(" ~ expr ~ ""`
+    DropToken,
+    /// A terminal is replaced with a terminal of another rule
+    SwapTerminal,
+    /// A range is replaced with a char just outside of it, e.g. `'a'..'z'` to `"{"`
+    BreakRange,
+    /// A repetition is generated one time more than its maximum or one time less than its minimum, e.g. `a{3}` to
+    /// `a{4}`
+    BreakRepetition,
+}
+
+impl fmt::Display for MutationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MutationKind::DropToken => "drop_token",
+            MutationKind::SwapTerminal => "swap_terminal",
+            MutationKind::BreakRange => "break_range",
+            MutationKind::BreakRepetition => "break_repetition",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// e.g. `drop_token in sentence: "." -> ""`
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in {}: {} -> {}",
+            self.kind, self.rule, self.original, self.replacement
+        )
+    }
+}
+
+/// Expresión de una regla que se puede mutar, `position` es su posición en el recorrido en preorden de la regla
+struct Site {
+    rule: String,
+    position: usize,
+    kind: MutationKind,
+}
+
+/// Expresiones mutables de las reglas alcanzables desde una regla inicial, se buscan una sola vez para generar
+/// todos los ejemplos inválidos de esa regla
+pub(crate) struct Mutations {
+    start_rule: String,
+    sites: Vec<Site>,
+    /// Terminales de cada regla, usados por SwapTerminal
+    terminals: Vec<(String, Expr)>,
+    /// Gramáticas mutadas ya construidas por (regla, posición, reemplazo), compartidas entre los ejemplos
+    grammars: Mutex<HashMap<(String, usize, String), Arc<Grammar>>>,
+}
+
+impl Mutations {
+    pub(crate) fn new(input_data: &InputData, start_rule: &str) -> Result<Self, GenerationError> {
+        if !input_data.clean_grammar.rules.contains_key(start_rule) {
+            return Err(GenerationError::RuleNotFound(start_rule.to_string()));
+        }
+
+        let sites = mutation_sites(input_data, start_rule);
+        let terminals = sites
+            .iter()
+            .filter(|site| site.kind == MutationKind::SwapTerminal)
+            .map(|site| {
+                let expr = node(&input_data.grammar.rules[&site.rule].expr, site.position);
+                (site.rule.clone(), expr.clone())
+            })
+            .collect();
+        Ok(Mutations {
+            start_rule: start_rule.to_string(),
+            sites,
+            terminals,
+            grammars: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Genera el ejemplo inválido número `index`
+    ///
+    /// En cada intento se muta una expresión elegida al azar y se genera un ejemplo con la gramática mutada, el
+    /// ejemplo se acepta si la gramática original no lo puede parsear
+    pub(crate) fn generate(
+        &self,
+        input_data: &InputData,
+        config: &GeneratorConfig,
+        index: u64,
+    ) -> Result<InvalidExample, GenerationError> {
+        let mut rng = example_rng(config.seed, index);
+        for _ in 0..config.max_attempts_invalid {
+            let site = match self.sites.choose(&mut rng) {
+                Some(site) => site,
+                None => break,
+            };
+            let original = node(&input_data.grammar.rules[&site.rule].expr, site.position);
+            let replacement = match mutation(site, original, &self.terminals, &mut rng) {
+                Some(replacement) => replacement,
+                None => continue,
+            };
+
+            // La gramática limpia y las derivaciones mínimas originales se conservan, la cobertura no se modifica
+            let mutated = InputData {
+                grammar: self.mutated_grammar(input_data, site, &replacement),
+                coverage: None,
+                ..input_data.clone()
+            };
+
+            let text = match traverse(&mutated, &self.start_rule, &mut rng, config) {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            if input_data.parser.parse(&self.start_rule, &text).is_err() {
+                return Ok(InvalidExample {
+                    text,
+                    mutation: Mutation {
+                        kind: site.kind,
+                        rule: site.rule.clone(),
+                        original: original.to_string(),
+                        replacement: replacement.to_string(),
+                    },
+                });
+            }
+        }
+
+        Err(GenerationError::InvalidAttemptsExhausted(
+            self.start_rule.clone(),
+        ))
+    }
+
+    /// Copia de la gramática con la expresión de `site` reemplazada por `replacement`, cada mutación se construye
+    /// una sola vez
+    fn mutated_grammar(
+        &self,
+        input_data: &InputData,
+        site: &Site,
+        replacement: &Expr,
+    ) -> Arc<Grammar> {
+        let key = (site.rule.clone(), site.position, replacement.to_string());
+        let mut grammars = self.grammars.lock().unwrap();
+        grammars
+            .entry(key)
+            .or_insert_with(|| {
+                let mut grammar = (*input_data.grammar).clone();
+                let rule = grammar.rules.get_mut(&site.rule).unwrap();
+                rule.expr = replace(&rule.expr, &mut 0, site.position, replacement);
+                Arc::new(grammar)
+            })
+            .clone()
+    }
+}
+
+/// Expresiones que se pueden mutar en las reglas alcanzables desde `start_rule`
+///
+/// WHITESPACE, COMMENT y los predicados no se mutan
+fn mutation_sites(input_data: &InputData, start_rule: &str) -> Vec<Site> {
+    let mut sites = vec![];
+    for name in reachable_rules(input_data, start_rule) {
+        if name == "WHITESPACE" || name == "COMMENT" {
+            continue;
+        }
+        let mut nodes = vec![];
+        preorder(&input_data.grammar.rules[&name].expr, false, &mut nodes);
+
+        for (position, (expr, in_sequence)) in nodes.into_iter().enumerate() {
+            let mut add = |kind| {
+                sites.push(Site {
+                    rule: name.clone(),
+                    position,
+                    kind,
+                })
+            };
+            let token = match expr {
+                Expr::Str(string) => !string.is_empty() && !is_marker(string),
+                Expr::Insens(string) => !string.is_empty(),
+                Expr::Range(_, _) | Expr::Ident(_) => true,
+                _ => false,
+            };
+            if in_sequence && token {
+                add(MutationKind::DropToken);
+            }
+            match expr {
+                Expr::Str(_) | Expr::Insens(_) if token => add(MutationKind::SwapTerminal),
+                Expr::Range(_, _) => add(MutationKind::BreakRange),
+                Expr::RepOnce(_)
+                | Expr::RepExact(_, _)
+                | Expr::RepMin(_, _)
+                | Expr::RepMax(_, _)
+                | Expr::RepMinMax(_, _, _) => add(MutationKind::BreakRepetition),
+                _ => {}
+            }
+        }
+    }
+    sites
+}
+
+/// Expresiones de `expr` en preorden, junto con un indicador de si son un elemento de una secuencia
+///
+/// No se recorre el interior de los predicados
+fn preorder<'a>(expr: &'a Expr, in_sequence: bool, nodes: &mut Vec<(&'a Expr, bool)>) {
+    nodes.push((expr, in_sequence));
+    match expr {
+        Expr::Seq(lhs, rhs) => {
+            preorder(lhs, true, nodes);
+            preorder(rhs, true, nodes);
+        }
+        Expr::Choice(lhs, rhs) => {
+            preorder(lhs, false, nodes);
+            preorder(rhs, false, nodes);
+        }
+        Expr::Opt(inner)
+        | Expr::Rep(inner)
+        | Expr::RepOnce(inner)
+        | Expr::RepExact(inner, _)
+        | Expr::RepMin(inner, _)
+        | Expr::RepMax(inner, _)
+        | Expr::RepMinMax(inner, _, _)
+        | Expr::Push(inner) => preorder(inner, false, nodes),
+        _ => {}
+    }
+}
+
+/// Expresión en la posición `position` del recorrido en preorden
+fn node(expr: &Expr, position: usize) -> &Expr {
+    let mut nodes = vec![];
+    preorder(expr, false, &mut nodes);
+    nodes[position].0
+}
+
+/// Copia de `expr` donde la expresión en la posición `target` del preorden se reemplaza por `replacement`
+fn replace(expr: &Expr, position: &mut usize, target: usize, replacement: &Expr) -> Expr {
+    let actual = *position;
+    *position += 1;
+    if actual == target {
+        return replacement.clone();
+    }
+
+    let mut replace = |inner: &Expr| Box::new(replace(inner, position, target, replacement));
+    match expr {
+        Expr::Seq(lhs, rhs) => {
+            let lhs = replace(lhs);
+            Expr::Seq(lhs, replace(rhs))
+        }
+        Expr::Choice(lhs, rhs) => {
+            let lhs = replace(lhs);
+            Expr::Choice(lhs, replace(rhs))
+        }
+        Expr::Opt(inner) => Expr::Opt(replace(inner)),
+        Expr::Rep(inner) => Expr::Rep(replace(inner)),
+        Expr::RepOnce(inner) => Expr::RepOnce(replace(inner)),
+        Expr::RepExact(inner, n) => Expr::RepExact(replace(inner), *n),
+        Expr::RepMin(inner, n) => Expr::RepMin(replace(inner), *n),
+        Expr::RepMax(inner, n) => Expr::RepMax(replace(inner), *n),
+        Expr::RepMinMax(inner, min, max) => Expr::RepMinMax(replace(inner), *min, *max),
+        Expr::Push(inner) => Expr::Push(replace(inner)),
+        _ => expr.clone(),
+    }
+}
+
+/// Expresión que reemplaza a `original`, None si la mutación no es posible
+fn mutation(
+    site: &Site,
+    original: &Expr,
+    terminals: &[(String, Expr)],
+    rng: &mut StdRng,
+) -> Option<Expr> {
+    match (site.kind, original) {
+        (MutationKind::DropToken, _) => Some(Expr::Str(String::new())),
+        (MutationKind::SwapTerminal, _) => {
+            let candidates: Vec<&Expr> = terminals
+                .iter()
+                .filter(|(rule, terminal)| *rule != site.rule && terminal != original)
+                .map(|(_, terminal)| terminal)
+                .collect();
+            candidates.choose(rng).map(|terminal| (*terminal).clone())
+        }
+        (MutationKind::BreakRange, Expr::Range(start, end)) => {
+            let start = start.chars().next()?;
+            let end = end.chars().next()?;
+            // El caracter siguiente al final del rango o el anterior al inicio
+            let outside = [
+                std::char::from_u32(end as u32 + 1),
+                (start as u32).checked_sub(1).and_then(std::char::from_u32),
+            ];
+            let outside: Vec<char> = outside.iter().flatten().copied().collect();
+            outside
+                .choose(rng)
+                .map(|outside| Expr::Str(outside.to_string()))
+        }
+        (MutationKind::BreakRepetition, repetition) => {
+            let (inner, num_reps) = match repetition {
+                Expr::RepOnce(inner) => (inner, 0),
+                Expr::RepExact(inner, n) | Expr::RepMax(inner, n) => (inner, n + 1),
+                Expr::RepMin(_, 0) => return None,
+                Expr::RepMin(inner, n) => (inner, n - 1),
+                Expr::RepMinMax(inner, min, max) => {
+                    // Por encima del máximo o por debajo del mínimo
+                    if *min > 0 && rng.gen_bool(0.5) {
+                        (inner, min - 1)
+                    } else {
+                        (inner, max + 1)
+                    }
+                }
+                _ => return None,
+            };
+            Some(Expr::RepExact(inner.clone(), num_reps))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,147 @@
+use rand::Rng;
+use std::convert::TryFrom;
+use std::ops::RangeInclusive;
+
+/// Source of the random decisions taken while an example is generated: the alternative of every choice, the number
+/// of repetitions, the optional elements and the characters of the ranges
+///
+/// It's implemented for every `rand::Rng` and for `ByteSource`, that takes the decisions from a slice of bytes
+/// (e.g. the input of a fuzzer), see `Generator::generate_with`
+pub trait RandomSource {
+    /// Number in `range`
+    fn gen_range(&mut self, range: RangeInclusive<u32>) -> u32;
+
+    /// Character in `range`
+    fn gen_char(&mut self, range: RangeInclusive<char>) -> char;
+
+    /// `true` with a probability of `probability`
+    fn gen_bool(&mut self, probability: f64) -> bool;
+
+    /// Position of the element chosen in a slice of `len` elements, `None` if `len` is 0
+    fn choose(&mut self, len: usize) -> Option<usize>;
+}
+
+/// Se usan las mismas llamadas de `rand` que antes de la abstracción, así una semilla genera los mismos ejemplos
+impl<R: Rng> RandomSource for R {
+    fn gen_range(&mut self, range: RangeInclusive<u32>) -> u32 {
+        Rng::gen_range(self, range)
+    }
+
+    fn gen_char(&mut self, range: RangeInclusive<char>) -> char {
+        Rng::gen_range(self, range)
+    }
+
+    fn gen_bool(&mut self, probability: f64) -> bool {
+        Rng::gen_bool(self, probability)
+    }
+
+    fn choose(&mut self, len: usize) -> Option<usize> {
+        // Igual que `SliceRandom::choose`
+        match len {
+            0 => None,
+            len if len <= u32::MAX as usize => Some(Rng::gen_range(self, 0..len as u32) as usize),
+            len => Some(Rng::gen_range(self, 0..len)),
+        }
+    }
+}
+
+/// Random source that takes every decision from the next bytes of a slice, in the style of
+/// `arbitrary::Unstructured`
+///
+/// A decision between `n` options consumes the bytes needed to represent `n - 1`. When the bytes run out every
+/// decision takes the lowest value (the minimum number of repetitions, no optional elements), so a short input
+/// generates a short example. Small changes in the bytes produce small changes in the example, which lets
+/// coverage-guided fuzzers (libFuzzer, AFL) steer the generation
+///
+/// ```
+/// use bulk_examples_generator::{ByteSource, Generator};
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go"}
+///         sentence = {"I like " ~ language}
+///     "#;
+///
+/// let generator = Generator::new(grammar.to_string()).unwrap();
+/// let mut source = ByteSource::new(&[2]);
+/// assert_eq!("I like Go", generator.generate_with("sentence", &mut source).unwrap());
+/// assert!(source.is_empty());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ByteSource<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ByteSource<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteSource { data }
+    }
+
+    /// Bytes not consumed yet
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// All the bytes have been consumed, the next decisions take the smallest option
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Consume el siguiente byte, None si ya no quedan bytes
+    fn next_byte(&mut self) -> Option<u8> {
+        let (byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(*byte)
+    }
+}
+
+impl RandomSource for ByteSource<'_> {
+    fn gen_range(&mut self, range: RangeInclusive<u32>) -> u32 {
+        let (start, end) = (*range.start(), *range.end());
+        if start >= end {
+            return start;
+        }
+
+        // Se consumen (big endian) los bytes necesarios para representar el tamaño del rango
+        let width = end - start;
+        let mut value: u32 = 0;
+        let mut consumed: u32 = 0;
+        while consumed < 4 && width >> (8 * consumed) > 0 {
+            match self.next_byte() {
+                Some(byte) => value = (value << 8) | u32::from(byte),
+                None => break,
+            }
+            consumed += 1;
+        }
+
+        match width.checked_add(1) {
+            Some(size) => start + value % size,
+            None => start + value,
+        }
+    }
+
+    fn gen_char(&mut self, range: RangeInclusive<char>) -> char {
+        let start = *range.start();
+        let codepoint = self.gen_range(u32::from(start)..=u32::from(*range.end()));
+        // Los surrogates no son caracteres válidos
+        std::char::from_u32(codepoint).unwrap_or(start)
+    }
+
+    fn gen_bool(&mut self, probability: f64) -> bool {
+        match self.next_byte() {
+            // Con el byte 255 siempre es true (si la probabilidad no es 0) y con el byte 0 siempre es false (si la
+            // probabilidad no es 1)
+            Some(byte) => f64::from(byte) >= 256.0 * (1.0 - probability),
+            None => false,
+        }
+    }
+
+    fn choose(&mut self, len: usize) -> Option<usize> {
+        match len {
+            0 => None,
+            len => {
+                let last = u32::try_from(len - 1).unwrap_or(u32::MAX);
+                Some(self.gen_range(0..=last) as usize)
+            }
+        }
+    }
+}
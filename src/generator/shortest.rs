@@ -0,0 +1,232 @@
+use super::unicode::UnicodeRanges;
+use super::{branch_weight, is_marker, peek_slice, process_stack_builtin, Grammar};
+
+use pest_meta::ast::Expr;
+use std::collections::HashMap;
+
+/// Minimal derivation of a rule, used to close the rules still open when a limit of the generation is reached
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MinimalDerivation {
+    /// Minimum number of nested rules expanded to derive the rule, counting the rule itself, e.g. in
+    /// `a = { "x" | b }` and `b = { "y" }` the depth of `a` is 1 and the depth of `b` is 1
+    pub depth: usize,
+    /// Shortest text derived from the rule, the alternatives with weight 0 aren't used
+    pub shortest: String,
+    /// Valores que la derivación más corta deja en el stack (PUSH sin POP)
+    pushes: Vec<String>,
+}
+
+/// Calcula la derivación mínima de cada regla, las reglas que no tienen una derivación finita no se incluyen
+/// (por ejemplo `a = { "x" ~ a }`)
+///
+/// Ambos valores se calculan como un punto fijo: en cada pasada se evalúan las reglas con los valores conocidos de
+/// las demás reglas hasta que ningún valor mejora
+pub(crate) fn minimal_derivations(
+    grammar: &Grammar,
+    unicode_ranges: &HashMap<String, UnicodeRanges>,
+) -> HashMap<String, MinimalDerivation> {
+    // Orden fijo para que el resultado no dependa del orden del HashMap
+    let mut names: Vec<&String> = grammar.rules.keys().collect();
+    names.sort();
+
+    let mut depths: HashMap<String, usize> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for name in &names {
+            let depth = match expr_depth(&grammar.rules[*name].expr, grammar, &depths) {
+                Some(depth) => depth + 1,
+                None => continue,
+            };
+            if depths.get(*name).is_none_or(|known| depth < *known) {
+                depths.insert(name.to_string(), depth);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // La profundidad se completa al final, toda regla con texto más corto tiene profundidad
+    let mut derivations: HashMap<String, MinimalDerivation> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for name in &names {
+            let shortest = Shortest {
+                grammar,
+                unicode_ranges,
+                rules: &derivations,
+            };
+            let mut stack = vec![];
+            let text = match shortest.text(&grammar.rules[*name].expr, &mut stack) {
+                Some(text) => text,
+                None => continue,
+            };
+            let better = derivations
+                .get(*name)
+                .is_none_or(|known| shorter(&text, &known.shortest));
+            if better {
+                let derivation = MinimalDerivation {
+                    depth: 0,
+                    shortest: text,
+                    pushes: stack,
+                };
+                derivations.insert(name.to_string(), derivation);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for (name, derivation) in derivations.iter_mut() {
+        derivation.depth = depths[name];
+    }
+    derivations
+}
+
+/// Texto más corto de `expr` partiendo del stack de valores `stack`, usado para cerrar las expresiones pendientes
+/// cuando se alcanza un límite. Retorna None si `expr` usa una regla sin derivación finita
+pub(crate) fn shortest_text(
+    expr: &Expr,
+    grammar: &Grammar,
+    unicode_ranges: &HashMap<String, UnicodeRanges>,
+    derivations: &HashMap<String, MinimalDerivation>,
+    stack: &mut Vec<String>,
+) -> Option<String> {
+    let shortest = Shortest {
+        grammar,
+        unicode_ranges,
+        rules: derivations,
+    };
+    shortest.text(expr, stack)
+}
+
+/// Retorna true si `a` es más corto que `b`, a igual longitud se usa el orden alfabético
+fn shorter(a: &str, b: &str) -> bool {
+    (a.chars().count(), a) < (b.chars().count(), b)
+}
+
+/// Alternativas de un choice de izquierda a derecha, sin las alternativas con peso 0 (salvo que todas tengan peso 0)
+fn alternatives(expr: &Expr) -> Vec<&Expr> {
+    let mut list = vec![];
+    let mut aux_expr = expr;
+    while let Expr::Choice(lhs, rhs) = aux_expr {
+        list.push(&**rhs);
+        aux_expr = lhs;
+    }
+    list.push(aux_expr);
+    list.reverse();
+
+    let weighted: Vec<&Expr> = list
+        .iter()
+        .copied()
+        .filter(|alternative| branch_weight(alternative) > 0)
+        .collect();
+    if weighted.is_empty() {
+        list
+    } else {
+        weighted
+    }
+}
+
+/// Profundidad mínima de las reglas usadas por `expr`, None si usa una regla sin profundidad conocida
+fn expr_depth(expr: &Expr, grammar: &Grammar, depths: &HashMap<String, usize>) -> Option<usize> {
+    match expr {
+        Expr::Ident(name) if grammar.rules.contains_key(name) => depths.get(name).copied(),
+        Expr::Seq(lhs, rhs) => {
+            let lhs = expr_depth(lhs, grammar, depths)?;
+            let rhs = expr_depth(rhs, grammar, depths)?;
+            Some(lhs.max(rhs))
+        }
+        Expr::Choice(_, _) => alternatives(expr)
+            .into_iter()
+            .filter_map(|alternative| expr_depth(alternative, grammar, depths))
+            .min(),
+        Expr::RepOnce(inner) | Expr::Push(inner) => expr_depth(inner, grammar, depths),
+        Expr::RepExact(inner, min_reps)
+        | Expr::RepMin(inner, min_reps)
+        | Expr::RepMinMax(inner, min_reps, _)
+            if *min_reps > 0 =>
+        {
+            expr_depth(inner, grammar, depths)
+        }
+        _ => Some(0),
+    }
+}
+
+/// Evaluación del texto más corto con los textos conocidos de cada regla
+struct Shortest<'a> {
+    grammar: &'a Grammar,
+    unicode_ranges: &'a HashMap<String, UnicodeRanges>,
+    /// Derivaciones conocidas de cada regla
+    rules: &'a HashMap<String, MinimalDerivation>,
+}
+
+impl<'a> Shortest<'a> {
+    fn text(&self, expr: &Expr, stack: &mut Vec<String>) -> Option<String> {
+        match expr {
+            Expr::Str(string) if is_marker(string) => Some(String::new()),
+            Expr::Str(string) => Some(string.to_string()),
+            Expr::Insens(string) => Some(string.to_lowercase()),
+            Expr::Range(initial_char, _) => Some(initial_char.to_string()),
+            Expr::Ident(name) => {
+                if let Some(text) = process_stack_builtin(name, stack) {
+                    return Some(text);
+                }
+                if let Some(ranges) = self.unicode_ranges.get(name) {
+                    return Some(ranges.chars().next().map(String::from).unwrap_or_default());
+                }
+                if !self.grammar.rules.contains_key(name) {
+                    // SOI y EOI no producen texto
+                    return Some(String::new());
+                }
+                let derivation = self.rules.get(name)?;
+                stack.extend(derivation.pushes.iter().cloned());
+                Some(derivation.shortest.to_string())
+            }
+            Expr::PeekSlice(start, end) => Some(peek_slice(stack, *start, *end)),
+            Expr::Seq(lhs, rhs) => {
+                let lhs = self.text(lhs, stack)?;
+                let rhs = self.text(rhs, stack)?;
+                Some(lhs + &rhs)
+            }
+            Expr::Choice(_, _) => {
+                let mut best: Option<(String, Vec<String>)> = None;
+                for alternative in alternatives(expr) {
+                    let mut alternative_stack = stack.clone();
+                    if let Some(text) = self.text(alternative, &mut alternative_stack) {
+                        if best.as_ref().is_none_or(|(known, _)| shorter(&text, known)) {
+                            best = Some((text, alternative_stack));
+                        }
+                    }
+                }
+                let (text, best_stack) = best?;
+                *stack = best_stack;
+                Some(text)
+            }
+            Expr::RepOnce(inner) => self.text(inner, stack),
+            Expr::RepExact(inner, min_reps)
+            | Expr::RepMin(inner, min_reps)
+            | Expr::RepMinMax(inner, min_reps, _) => {
+                let mut text = String::new();
+                for _ in 0..*min_reps {
+                    text.push_str(&self.text(inner, stack)?);
+                }
+                Some(text)
+            }
+            Expr::Push(inner) => {
+                let text = self.text(inner, stack)?;
+                stack.push(text.clone());
+                Some(text)
+            }
+            Expr::Opt(_)
+            | Expr::Rep(_)
+            | Expr::RepMax(_, _)
+            | Expr::Skip(_)
+            | Expr::PosPred(_)
+            | Expr::NegPred(_) => Some(String::new()),
+        }
+    }
+}
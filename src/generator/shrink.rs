@@ -0,0 +1,118 @@
+use super::InputData;
+use crate::error::Error;
+use crate::parse_with_grammar;
+
+use pest::iterators::Pair;
+use pest_meta::ast::{Expr, Rule as AstRule, RuleType};
+use pest_meta::optimizer;
+use pest_vm::Vm;
+use std::collections::HashSet;
+
+/// Regla temporal que parsea la regla inicial hasta el final del texto
+const COMPLETE_RULE: &str = "__shrink_complete";
+
+/// Reduce `example` al texto más pequeño que la gramática puede parsear (completo) y que sigue cumpliendo
+/// `is_failing`
+///
+/// En cada pasada se prueban los candidatos de la pasada (de mayor a menor reducción) hasta encontrar uno válido,
+/// todos los candidatos son más cortos que el texto actual así que el proceso termina
+pub(crate) fn shrink_example<F>(
+    input_data: &InputData,
+    start_rule: &str,
+    example: &str,
+    mut is_failing: F,
+) -> Result<String, Error>
+where
+    F: FnMut(&str) -> bool,
+{
+    let clean_grammar = &input_data.clean_grammar;
+    if !clean_grammar.rules.contains_key(start_rule) {
+        return Err(Error::UnknownStartRule(start_rule.to_string()));
+    }
+    parse_with_grammar(clean_grammar, start_rule, example)?;
+    if !is_failing(example) {
+        return Err(Error::ExampleNotFailing);
+    }
+
+    // pest acepta un prefijo del texto cuando la regla no termina en EOI, el resto del texto no haría parte del
+    // árbol, por eso los candidatos se parsean con `start_rule ~ EOI`
+    let mut rules: Vec<AstRule> = clean_grammar.rules.values().cloned().collect();
+    rules.push(AstRule {
+        name: COMPLETE_RULE.to_string(),
+        ty: RuleType::Normal,
+        expr: Expr::Seq(
+            Box::new(Expr::Ident(start_rule.to_string())),
+            Box::new(Expr::Ident("EOI".to_string())),
+        ),
+    });
+    let vm = Vm::new(optimizer::optimize(rules));
+    let mut text = example.to_string();
+    while let Ok(pairs) = vm.parse(start_rule, &text) {
+        let mut candidates = vec![];
+        for pair in pairs {
+            reductions(input_data, &text, pair, &mut candidates);
+        }
+
+        // Primero los candidatos más cortos, a igual longitud se conserva el orden del recorrido
+        candidates.sort_by_key(String::len);
+        let mut seen = HashSet::new();
+        let mut reduced = None;
+        for candidate in candidates {
+            if candidate.len() >= text.len() || !seen.insert(candidate.clone()) {
+                continue;
+            }
+            if vm.parse(COMPLETE_RULE, &candidate).is_ok() && is_failing(&candidate) {
+                reduced = Some(candidate);
+                break;
+            }
+        }
+
+        match reduced {
+            Some(candidate) => text = candidate,
+            None => break,
+        }
+    }
+
+    Ok(text)
+}
+
+/// Candidatos de reducción del subárbol `pair` y de sus descendientes
+///
+/// - El subárbol se reemplaza con la derivación más corta de su regla
+/// - El subárbol se elimina (elementos opcionales o repetidos)
+/// - El subárbol se reemplaza con un subárbol interno de la misma regla
+/// - Se eliminan dos hermanos consecutivos junto con el texto entre ellos, o uno de ellos con el texto que los
+///   separa, así se quitan los elementos repetidos con sus separadores (`("," ~ item)*`)
+fn reductions(input_data: &InputData, text: &str, pair: Pair<&str>, candidates: &mut Vec<String>) {
+    let span = pair.as_span();
+    let (start, end) = (span.start(), span.end());
+    let replace = |from: usize, to: usize, replacement: &str| {
+        format!("{}{}{}", &text[..from], replacement, &text[to..])
+    };
+
+    if let Some(derivation) = input_data.minimal_derivations.get(pair.as_rule()) {
+        if derivation.shortest.len() < end - start {
+            candidates.push(replace(start, end, &derivation.shortest));
+        }
+    }
+    if start < end {
+        candidates.push(replace(start, end, ""));
+    }
+    // Un subárbol interno de la misma regla reemplaza al subárbol, e.g. `[[1]]` a `[1]`
+    for inner in pair.clone().into_inner().flatten() {
+        if inner.as_rule() == pair.as_rule() {
+            candidates.push(replace(start, end, inner.as_str()));
+        }
+    }
+
+    let children: Vec<Pair<&str>> = pair.into_inner().collect();
+    for siblings in children.windows(2) {
+        let (first, second) = (siblings[0].as_span(), siblings[1].as_span());
+        candidates.push(replace(first.start(), second.end(), ""));
+        candidates.push(replace(first.start(), second.start(), ""));
+        candidates.push(replace(first.end(), second.end(), ""));
+    }
+    for child in children {
+        reductions(input_data, text, child, candidates);
+    }
+}
@@ -0,0 +1,89 @@
+use super::RandomSource;
+
+use pest::unicode;
+
+/// Codepoints de una propiedad Unicode de pest (LETTER, XID_START, WHITE_SPACE, ...) agrupados en rangos
+///
+/// Se guarda el conteo acumulado de codepoints para elegir cualquier codepoint de la propiedad con la misma
+/// probabilidad, sin importar el tamaño de cada rango
+#[derive(Clone, Debug)]
+pub struct UnicodeRanges {
+    /// Rangos (inicio, fin) inclusivos
+    ranges: Vec<(u32, u32)>,
+    /// `cumulative[i]` es la cantidad de codepoints en `ranges[..=i]`
+    cumulative: Vec<u32>,
+}
+
+impl UnicodeRanges {
+    /// Calcula los rangos de la propiedad `name`, retorna None si no es una propiedad Unicode de pest
+    pub fn from_name(name: &str) -> Option<Self> {
+        let property = unicode::by_name(name)?;
+
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        let mut range_start: Option<u32> = None;
+        // Los surrogates no son char válidos, por eso nunca quedan dentro de un rango
+        for codepoint in 0..=char::MAX as u32 {
+            let inside = char::from_u32(codepoint).is_some_and(&property);
+            match (inside, range_start) {
+                (true, None) => range_start = Some(codepoint),
+                (false, Some(start)) => {
+                    ranges.push((start, codepoint - 1));
+                    range_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = range_start {
+            ranges.push((start, char::MAX as u32));
+        }
+
+        let mut total = 0;
+        let cumulative = ranges
+            .iter()
+            .map(|(start, end)| {
+                total += end - start + 1;
+                total
+            })
+            .collect();
+
+        Some(UnicodeRanges { ranges, cumulative })
+    }
+
+    /// Todos los codepoints de la propiedad en orden
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.ranges
+            .iter()
+            .flat_map(|(start, end)| (*start..=*end).filter_map(char::from_u32))
+    }
+
+    /// Elige un codepoint aleatorio de la propiedad, None si la propiedad no tiene codepoints
+    pub fn random_char(&self, rng: &mut dyn RandomSource) -> Option<char> {
+        let total = *self.cumulative.last()?;
+        let position = rng.gen_range(0..=total - 1);
+
+        // Primer rango cuyo conteo acumulado supera la posición elegida
+        let index = self.cumulative.partition_point(|&count| count <= position);
+        let previous = if index == 0 {
+            0
+        } else {
+            self.cumulative[index - 1]
+        };
+        char::from_u32(self.ranges[index].0 + position - previous)
+    }
+}
+
+#[test]
+fn test_unicode_ranges() {
+    let mut rng: rand::rngs::StdRng = rand::SeedableRng::seed_from_u64(1);
+
+    let letters = UnicodeRanges::from_name("LETTER").unwrap();
+    for _ in 0..100 {
+        let c = letters.random_char(&mut rng).unwrap();
+        assert!(unicode::LETTER(c));
+    }
+
+    let digits = UnicodeRanges::from_name("DECIMAL_NUMBER").unwrap();
+    assert!(digits.ranges.contains(&(48, 57)));
+
+    assert!(UnicodeRanges::from_name("ASCII_DIGIT").is_none());
+}
@@ -14,27 +14,44 @@
 //! Please see first the Readme. Well if you really want to see the code, go ahead
 //!
 use aho_corasick::AhoCorasick;
-use indicatif::{ProgressBar, ProgressStyle};
-use pest::error::{Error, ErrorVariant, InputLocation};
+use pest::error::InputLocation;
 use pest_meta::ast::Rule as AstRule;
 use pest_meta::parser::{self, Rule};
 use pest_meta::{optimizer, validator};
 use pest_vm::Vm;
 use rayon::prelude::*;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
 
 pub mod config;
+mod error;
 mod generator;
+mod output;
+mod report;
+mod runner;
+mod sink;
+mod stream;
 
 // Re-exports
 pub use pest;
 pub use pest_meta;
 
 use crate::config::*;
-use crate::generator::*;
+use crate::output::Record;
+
+pub use crate::error::{Error, GenerationError, GrammarError, ParseError};
+pub use crate::generator::{
+    ByteSource, Coverage, DerivationNode, DerivedExample, Enumeration, Generator, Grammar,
+    GrammarAnalysis, InvalidExample, MinimalDerivation, MutatedExample, Mutation, MutationKind,
+    PredicateIssue, RandomSource, SubtreeMutation, SubtreeMutationKind, UnboundedAlternative,
+    UnsupportedPredicate,
+};
+pub use crate::report::{AlternativeHits, CoverageReport};
+pub use crate::runner::{CommandOutput, Outcome, TargetCommand};
+pub use crate::sink::{
+    ExampleSink, FileSink, FolderSink, ProgressBarSink, StdoutSink, TextSink, VecSink,
+};
+pub use crate::stream::{ExampleStream, ParallelExampleStream};
 
 /// Compile a grammar string and creates a `HashMap` with rules found as keys and their components (AST) as entries
 ///
@@ -56,9 +73,9 @@ use crate::generator::*;
 ///
 /// println!("{:?}", grammar_ast);
 /// ```
-pub fn compile_grammar(grammar: String) -> Result<Grammar, Vec<HashMap<String, String>>> {
+pub fn compile_grammar(grammar: String) -> Result<Grammar, Error> {
     // Replace builtin pest rules for their equivalents
-    let grammar = replace_builtin_rules(&grammar).unwrap();
+    let grammar = replace_builtin_rules(&grammar)?;
 
     // Print grammar after replaces builtin rules
     // println!("{}", grammar.clone());
@@ -96,26 +113,30 @@ pub fn compile_grammar(grammar: String) -> Result<Grammar, Vec<HashMap<String, S
         Ok(pairs) => pairs,
         Err(error) => {
             // add_rules_to_select(vec![]);
-            return Err(vec![convert_error(error, &grammar)]);
+            return Err(Error::GrammarSyntax(convert_error(error, &grammar)));
         }
     };
 
     if let Err(errors) = validator::validate_pairs(pairs.clone()) {
         // add_rules_to_select(vec![]);
-        return Err(errors
-            .into_iter()
-            .map(|e| convert_error(e, &grammar))
-            .collect());
+        return Err(Error::GrammarValidation(
+            errors
+                .into_iter()
+                .map(|e| convert_error(e, &grammar))
+                .collect(),
+        ));
     }
 
     let ast = match parser::consume_rules(pairs) {
         Ok(ast) => ast,
         Err(errors) => {
             // add_rules_to_select(vec![]);
-            return Err(errors
-                .into_iter()
-                .map(|e| convert_error(e, &grammar))
-                .collect());
+            return Err(Error::GrammarValidation(
+                errors
+                    .into_iter()
+                    .map(|e| convert_error(e, &grammar))
+                    .collect(),
+            ));
         }
     };
 
@@ -203,154 +224,447 @@ pub fn generate_examples(
     start: String,
     generator_config: &GeneratorConfig,
     executor_config: &ExecutorConfig,
-) -> Vec<Result<String, String>> {
-    let input_data = InputData::new(grammar_string);
-    if executor_config.parallel_mode {
-        return parallel_generate_examples(
-            input_data,
+) -> Result<Vec<Result<String, GenerationError>>, Error> {
+    let mut vec = VecSink::new();
+    let mut sinks: Vec<Box<dyn ExampleSink + Send + '_>> = vec![];
+    if executor_config.return_vec {
+        sinks.push(Box::new(&mut vec));
+    }
+    generate_to_sinks(
+        grammar_string,
+        quantity,
+        start,
+        generator_config,
+        executor_config,
+        sinks,
+        false,
+    )?;
+    Ok(vec.into_examples())
+}
+
+/// Generate a number of examples like `generate_examples` and send every example to the `sinks`, see `ExampleSink`
+///
+/// The outputs of `executor_config` (stdout, file, folder, progress bar and text) are still written, disable them to
+/// only use the sinks. `return_vec` is ignored, use a `VecSink` instead. With `coverage_guided` in `generator_config`
+/// the final `Coverage` is returned
+///
+/// `finish` is called on every sink even when a sink fails, then the first error is returned
+///
+/// ```
+/// use bulk_examples_generator::config::*;
+/// use bulk_examples_generator::{generate_examples_with_sinks, VecSink};
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go" | "Java" | "PHP" | "Haskell"}
+///         sentence = {"I like " ~ language}
+///     "#;
+///
+/// let mut exe_config: ExecutorConfig = Default::default();
+/// exe_config.print_stdout = false;
+///
+/// let mut sink = VecSink::new();
+/// generate_examples_with_sinks(
+///             grammar.to_string(),
+///             5,
+///             "sentence".to_string(),
+///             &Default::default(),
+///             &exe_config,
+///             vec![Box::new(&mut sink)],
+///         ).unwrap();
+///
+/// assert_eq!(5, sink.into_examples().len());
+/// ```
+pub fn generate_examples_with_sinks(
+    grammar_string: String,
+    quantity: u32,
+    start: String,
+    generator_config: &GeneratorConfig,
+    executor_config: &ExecutorConfig,
+    sinks: Vec<Box<dyn ExampleSink + Send + '_>>,
+) -> Result<Option<Coverage>, Error> {
+    // Los sinks del usuario reciben los conteos de las reglas con cualquier formato
+    let sinks_use_hits = !sinks.is_empty();
+    generate_to_sinks(
+        grammar_string,
+        quantity,
+        start,
+        generator_config,
+        executor_config,
+        sinks,
+        sinks_use_hits,
+    )
+}
+
+// Genera los ejemplos y los envía a las salidas de la configuración y a los `sinks`, los conteos de las reglas solo
+// se calculan si alguien los usa: los registros JSON Lines y CSV o los `sinks` con `sinks_use_hits`
+fn generate_to_sinks(
+    grammar_string: String,
+    quantity: u32,
+    start: String,
+    generator_config: &GeneratorConfig,
+    executor_config: &ExecutorConfig,
+    sinks: Vec<Box<dyn ExampleSink + Send + '_>>,
+    sinks_use_hits: bool,
+) -> Result<Option<Coverage>, Error> {
+    let generator = Generator::new(grammar_string)?.with_config(generator_config.clone());
+    if !generator.grammar().rules.contains_key(&start) {
+        return Err(Error::UnknownStartRule(start));
+    }
+
+    // Las salidas de la configuración van antes que los sinks del usuario
+    let mut all_sinks: Vec<Box<dyn ExampleSink + Send + '_>> = vec![];
+    if executor_config.print_progress_bar {
+        all_sinks.push(Box::new(ProgressBarSink::new(quantity.into())));
+    }
+    if executor_config.print_progress_text {
+        all_sinks.push(Box::new(TextSink));
+    }
+    if let Some((name_format, folder_path)) = &executor_config.print_folder {
+        all_sinks.push(Box::new(FolderSink::new(
+            name_format,
+            folder_path,
+            executor_config.compression,
+            executor_config.tar_folder,
+        )?));
+    }
+    if executor_config.print_stdout {
+        all_sinks.push(Box::new(StdoutSink::new(
+            &generator,
+            &start,
+            executor_config,
+        )?));
+    }
+    if let Some(path) = &executor_config.print_file {
+        all_sinks.push(Box::new(FileSink::new(
+            &generator,
+            &start,
+            path,
+            executor_config,
+        )?));
+    }
+    all_sinks.extend(sinks);
+
+    let writes_hits = executor_config.output_format != OutputFormat::Text
+        && (executor_config.print_stdout || executor_config.print_file.is_some());
+    let with_hits = executor_config.rule_hits && (writes_hits || sinks_use_hits);
+
+    // En modo cobertura con semilla se genera en orden para que los ejemplos sean siempre los mismos
+    if executor_config.parallel_mode && !generator.is_sequential() {
+        parallel_generate_examples(
+            &generator,
             quantity,
             start,
-            generator_config,
             executor_config,
-        );
+            with_hits,
+            all_sinks,
+        )?;
     } else {
-        return sequential_generate_examples(
-            input_data,
+        sequential_generate_examples(
+            &generator,
             quantity,
             start,
-            generator_config,
             executor_config,
-        );
+            with_hits,
+            all_sinks,
+        )?;
+    }
+
+    if executor_config.print_coverage {
+        if let Some(coverage) = generator.coverage() {
+            eprintln!("{}", coverage);
+        }
     }
+
+    Ok(generator.coverage().cloned())
 }
 
-fn parallel_generate_examples(
-    input_grammar: InputData,
+// Retorna true si se debe detener la generación porque ya se generaron todas las alternativas
+fn full_coverage_reached(generator: &Generator, executor_config: &ExecutorConfig) -> bool {
+    executor_config.stop_on_full_coverage
+        && generator
+            .coverage()
+            .is_some_and(|coverage| coverage.is_complete())
+}
+
+/// Enumerate every distinct example of the start rule within `max_depth` rule expansions, in order of increasing
+/// size, see `Enumeration`
+///
+/// ```
+/// use bulk_examples_generator::enumerate_examples;
+///
+/// let grammar = r#"
+///         bit = { "0" | "1" }
+///         byte = { bit{2} }
+///     "#;
+///
+/// let examples: Vec<String> = enumerate_examples(
+///             grammar.to_string(),
+///             "byte".to_string(),
+///             2,
+///             &Default::default(),
+///         ).unwrap().collect();
+///
+/// assert_eq!(vec!["00", "01", "10", "11"], examples);
+/// ```
+pub fn enumerate_examples(
+    grammar_string: String,
+    start: String,
+    max_depth: usize,
+    generator_config: &GeneratorConfig,
+) -> Result<Enumeration, Error> {
+    Generator::new(grammar_string)?
+        .with_config(generator_config.clone())
+        .enumerate(&start, max_depth)
+}
+
+/// Generate `quantity` examples that the grammar rejects, every example is generated with a mutated copy of the grammar
+/// and labelled with the mutation used to create it, see `InvalidExample`
+///
+/// ```
+/// use bulk_examples_generator::generate_invalid_examples;
+///
+/// let grammar = r#"
+///         digit = { '0'..'9' }
+///         number = { "[" ~ digit{3} ~ "]" }
+///     "#;
+///
+/// let examples = generate_invalid_examples(
+///             grammar.to_string(),
+///             10,
+///             "number".to_string(),
+///             &Default::default(),
+///         ).unwrap();
+///
+/// for example in examples {
+///     let example = example.unwrap();
+///     println!("{}: {}", example.mutation, example.text);
+/// }
+/// ```
+pub fn generate_invalid_examples(
+    grammar_string: String,
     quantity: u32,
     start: String,
     generator_config: &GeneratorConfig,
-    executor_config: &ExecutorConfig,
-) -> Vec<Result<String, String>> {
-    let vec = Arc::new(Mutex::new(vec![]));
+) -> Result<Vec<Result<InvalidExample, GenerationError>>, Error> {
+    let generator = Generator::new(grammar_string)?.with_config(generator_config.clone());
+    Ok(generator.generate_invalid_n(&start, quantity.into()))
+}
 
-    // Create the progress bar
-    let progress_bar = ProgressBar::new(quantity.into());
-    if executor_config.print_progress_bar {
-        progress_bar.set_style(
-            ProgressStyle::default_bar()
-                .template(
-                    "[{elapsed_precise}] {wide_bar} {pos:>3}/{len:3} {msg} {percent}% {eta_precise}",
-                )
-                .progress_chars("██░"),
-        );
-
-        // Force the initial paint
-        progress_bar.tick();
-    }
+/// Generate `quantity` examples together with the derivation tree that produced each one, see `DerivedExample`
+///
+/// ```
+/// use bulk_examples_generator::generate_derived_examples;
+///
+/// let grammar = r#"
+///         digit = { '0'..'9' }
+///         number = { "[" ~ digit{3} ~ "]" }
+///     "#;
+///
+/// let examples = generate_derived_examples(
+///             grammar.to_string(),
+///             10,
+///             "number".to_string(),
+///             &Default::default(),
+///         ).unwrap();
+///
+/// for example in examples {
+///     let example = example.unwrap();
+///     assert_eq!(3, example.tree.children.len());
+///     println!("{}", example.to_json());
+/// }
+/// ```
+pub fn generate_derived_examples(
+    grammar_string: String,
+    quantity: u32,
+    start: String,
+    generator_config: &GeneratorConfig,
+) -> Result<Vec<Result<DerivedExample, GenerationError>>, Error> {
+    let generator = Generator::new(grammar_string)?.with_config(generator_config.clone());
+    Ok(generator.generate_derived_n(&start, quantity.into()))
+}
 
-    (1..quantity + 1).into_par_iter().for_each(|i| {
-        let r = generator::generate_example(input_grammar.clone(), start.clone(), generator_config);
-        if executor_config.print_progress_bar {
-            progress_bar.inc(1);
-        }
-        if executor_config.print_progress_text {
-            println!("Example #{} generated:\r\n{}", i, r.as_ref().unwrap());
-        }
-        if executor_config.print_stdout {
-            println!("{}", r.as_ref().unwrap());
-        }
+/// Create `quantity` variants of the seeds by generating again random subtrees, the variant #n is created from the
+/// seed `(n - 1) % seeds.len()`, see `MutatedExample`. Use `Generator::splice_n` to swap subtrees between seeds
+///
+/// ```
+/// use bulk_examples_generator::mutate_examples;
+///
+/// let grammar = r#"
+///         digit = { '0'..'9' }
+///         number = { digit ~ ("," ~ digit)* }
+///     "#;
+///
+/// let seeds = vec!["1,2,3".to_string(), "4".to_string()];
+/// let examples = mutate_examples(
+///             grammar.to_string(),
+///             &seeds,
+///             10,
+///             "number".to_string(),
+///             &Default::default(),
+///         ).unwrap();
+///
+/// for example in examples {
+///     let example = example.unwrap();
+///     println!("{}: {}", example.mutations[0], example.text);
+/// }
+/// ```
+pub fn mutate_examples(
+    grammar_string: String,
+    seeds: &[String],
+    quantity: u32,
+    start: String,
+    generator_config: &GeneratorConfig,
+) -> Result<Vec<Result<MutatedExample, Error>>, Error> {
+    let generator = Generator::new(grammar_string)?.with_config(generator_config.clone());
+    Ok(generator.mutate_n(&start, seeds, quantity.into()))
+}
 
-        if executor_config.return_vec {
-            vec.lock().unwrap().push(r.clone())
-        }
+/// Shrink `example` toward the smallest input that the start rule can parse and `is_failing` still returns true for,
+/// see `Generator::shrink`
+///
+/// ```
+/// use bulk_examples_generator::shrink_example;
+///
+/// let grammar = r#"
+///         word = @{ ('a'..'z')+ }
+///         sentence = { word ~ (" " ~ word)* }
+///     "#;
+///
+/// let shrunk = shrink_example(
+///             grammar.to_string(),
+///             "sentence".to_string(),
+///             "the parser fails with zebra words",
+///             |example| example.contains('z'),
+///         ).unwrap();
+///
+/// assert_eq!("zebra", shrunk);
+/// ```
+pub fn shrink_example<F>(
+    grammar_string: String,
+    start: String,
+    example: &str,
+    is_failing: F,
+) -> Result<String, Error>
+where
+    F: FnMut(&str) -> bool,
+{
+    Generator::new(grammar_string)?.shrink(&start, example, is_failing)
+}
 
-        if let Some((name_format, folder_path)) = &executor_config.print_folder {
-            match r {
-                Ok(example) => {
-                    let new_path = folder_path.join(name_format.replace("{}", &i.to_string()));
-                    // println!("for {:?}", new_path);
-                    // Save the file
-                    let mut f = File::create(new_path).expect("Unable to create file");
-                    f.write_all(example.as_bytes())
-                        .expect(&format!("Unable to write data, example {}", i));
-                }
-                Err(error) => {
-                    println!("{}", error);
-                }
-            }
+/// Generate an example of `start` taking the random decisions from `data`, for use inside the `fuzz_target!` of
+/// cargo-fuzz (libFuzzer), see `ByteSource`
+///
+/// The same bytes always generate the same example and the fuzzer steers the generation by mutating the bytes. An
+/// empty string is returned when the example can't be generated
+///
+/// ```
+/// use bulk_examples_generator::{generate_from_bytes, Generator};
+///
+/// let grammar = r#"
+///         digit = {'0'..'9'}
+///         number = {digit+}
+///     "#;
+///
+/// // Compiled once, outside of the fuzz target
+/// let generator = Generator::new(grammar.to_string()).unwrap();
+///
+/// let data: &[u8] = &[3, 7, 1];
+/// let example = generate_from_bytes(&generator, "number", data);
+/// assert_eq!(example, generate_from_bytes(&generator, "number", data));
+/// assert!(generator.parse("number", &example).is_ok());
+/// ```
+pub fn generate_from_bytes(generator: &Generator, start: &str, data: &[u8]) -> String {
+    let mut source = ByteSource::new(data);
+    generator
+        .generate_with(start, &mut source)
+        .unwrap_or_default()
+}
+
+/// Índices generados a la vez en modo paralelo con `ordered_output`, ver `ExecutorConfig::ordered_output`
+const ORDERED_OUTPUT_WINDOW: u32 = 4096;
+
+fn parallel_generate_examples(
+    generator: &Generator,
+    quantity: u32,
+    start: String,
+    executor_config: &ExecutorConfig,
+    with_hits: bool,
+    sinks: Vec<Box<dyn ExampleSink + Send + '_>>,
+) -> Result<(), Error> {
+    // Cada sink recibe un ejemplo a la vez, sin bloquear a los demás sinks
+    let sinks: Vec<_> = sinks.into_iter().map(Mutex::new).collect();
+
+    let send = |i: u32| {
+        // Los ejemplos que faltan se omiten cuando ya se alcanzó la cobertura completa
+        if full_coverage_reached(generator, executor_config) {
+            return Ok(());
+        }
+        let record = Record::generate(generator, &start, i, with_hits);
+        for sink in &sinks {
+            record.send_to(&mut **sink.lock().unwrap())?;
         }
-    });
 
-    if executor_config.print_progress_bar {
-        progress_bar.finish();
-    }
+        Ok::<(), Error>(())
+    };
 
-    Arc::try_unwrap(vec).unwrap().into_inner().unwrap()
+    // Con `ordered_output` se genera por ventanas de índices, así los ejemplos que esperan a los anteriores nunca
+    // son más que una ventana
+    let window = if executor_config.ordered_output {
+        ORDERED_OUTPUT_WINDOW
+    } else {
+        quantity
+    };
+    let result = (1..quantity + 1)
+        .step_by(window.max(1) as usize)
+        .try_for_each(|first| {
+            let last = first.saturating_add(window).min(quantity + 1);
+            (first..last).into_par_iter().try_for_each(send)
+        });
+
+    let finished = finish_sinks(sinks.into_iter().map(|sink| sink.into_inner().unwrap()));
+    result.and(finished)
 }
 
 fn sequential_generate_examples(
-    input_grammar: InputData,
+    generator: &Generator,
     quantity: u32,
     start: String,
-    generator_config: &GeneratorConfig,
     executor_config: &ExecutorConfig,
-) -> Vec<Result<String, String>> {
-    let mut vec = vec![];
-
-    // Create progress bar
-    let progress_bar = ProgressBar::new(quantity.into());
-    if executor_config.print_progress_bar {
-        progress_bar.set_style(
-            ProgressStyle::default_bar()
-                .template(
-                    "[{elapsed_precise}] {wide_bar} {pos:>3}/{len:3} {msg} {percent}% {eta_precise}",
-                )
-                .progress_chars("██░"),
-        );
-
-        // Force the initial paint
-        progress_bar.tick();
-    }
+    with_hits: bool,
+    mut sinks: Vec<Box<dyn ExampleSink + Send + '_>>,
+) -> Result<(), Error> {
+    let mut send_all = || {
+        for i in 1..quantity + 1 {
+            if full_coverage_reached(generator, executor_config) {
+                break;
+            }
 
-    for i in 1..quantity + 1 {
-        // Generate example
-        let r = generator::generate_example(input_grammar.clone(), start.clone(), generator_config);
-        if executor_config.print_progress_bar {
-            progress_bar.inc(1);
-        }
-        if executor_config.print_progress_text {
-            println!("Example #{} generated:\r\n{}", i, r.as_ref().unwrap());
-        }
-        if executor_config.print_stdout {
-            println!("{}", r.as_ref().unwrap());
+            // Generate example
+            let record = Record::generate(generator, &start, i, with_hits);
+            for sink in &mut sinks {
+                record.send_to(&mut **sink)?;
+            }
         }
+        Ok(())
+    };
+    let result = send_all();
 
-        if executor_config.return_vec {
-            vec.push(r.clone())
-        }
+    let finished = finish_sinks(sinks);
+    result.and(finished)
+}
 
-        if let Some((name_format, folder_path)) = &executor_config.print_folder {
-            match r {
-                Ok(example) => {
-                    let new_path = folder_path.join(name_format.replace("{}", &i.to_string()));
-                    // println!("for {:?}", new_path);
-                    // Save the file
-                    let mut f = File::create(new_path).expect("Unable to create file");
-                    f.write_all(example.as_bytes())
-                        .expect(&format!("Unable to write data, example {}", i));
-                }
-                Err(error) => {
-                    println!("{}", error);
-                }
-            }
+// Termina todos los sinks aunque alguno falle (o haya fallado la generación), retorna el primer error
+fn finish_sinks<'a>(
+    sinks: impl IntoIterator<Item = Box<dyn ExampleSink + Send + 'a>>,
+) -> Result<(), Error> {
+    let mut result = Ok(());
+    for mut sink in sinks {
+        let finished = sink.finish();
+        if result.is_ok() {
+            result = finished;
         }
     }
-
-    if executor_config.print_progress_bar {
-        progress_bar.finish();
-    }
-
-    vec
+    result
 }
 
 // Parsea `input` usando la gramática `grammar`, iniciando el parseo desde `rule`
@@ -384,10 +698,10 @@ fn sequential_generate_examples(
 /// let results = generate_examples(
 ///             grammar.to_string(),        // The grammar
 ///             1,                          // Quantity of examples
-///             "sentences".to_string(),    // Start rule
+///             "sentence".to_string(),     // Start rule
 ///             &gen_config,                    // Config of the generator
 ///             &exe_config,                    // Config of the executor
-///         );
+///         ).unwrap();
 ///
 /// let one_example = results[0].as_ref().unwrap();
 ///
@@ -397,19 +711,41 @@ fn sequential_generate_examples(
 /// println!("{:?}", validate);
 /// ```
 ///
-pub fn parse_input<'a>(grammar: Grammar, rule: String, input: String) -> Result<(), String> {
+pub fn parse_input(grammar: Grammar, rule: String, input: String) -> Result<(), Error> {
+    parse_with_grammar(&grammar, &rule, &input)
+}
+
+/// Parse every example with the provided grammar and start rule, and count the hits of every rule and every
+/// alternative of the choices, see `CoverageReport`
+pub fn coverage_report(
+    grammar: Grammar,
+    rule: String,
+    examples: &[String],
+) -> Result<CoverageReport, Error> {
+    CoverageReport::new(&grammar, &rule, examples)
+}
+
+/// Find the rules unreachable from `start`, the rules that can't derive a finite string, the rules and alternatives
+/// with unbounded expected size and the predicates the generator can't honor, with the default config. See
+/// `GrammarAnalysis`, use `Generator::analyze` for other repetition bounds or weights
+pub fn analyze_grammar(grammar: &Grammar, start: &str) -> Result<GrammarAnalysis, Error> {
+    Generator::from_grammar(grammar.clone()).analyze(start)
+}
+
+/// Igual que `parse_input` pero sin tomar la gramática, usado por el generador para no copiar la gramática
+pub(crate) fn parse_with_grammar(grammar: &Grammar, rule: &str, input: &str) -> Result<(), Error> {
     // Es necesario entregar una copia entera de las reglas al vm
     let vm = Vm::new(optimizer::optimize(
-        grammar.rules.values().map(|r| r.clone()).collect(),
+        grammar.rules.values().cloned().collect(),
     ));
 
-    parse_input_with_vm(vm, rule, input)
+    parse_input_with_vm(&vm, rule, input)
 }
 
 /// Parsea `input` usando la máquina `Vm`, iniciando el parseo desde `rule`
 /// retorna Ok si es exitoso el parseo, Err si no es posible parsear
-fn parse_input_with_vm<'a>(vm: Vm, rule: String, input: String) -> Result<(), String> {
-    match vm.parse(&rule, &input) {
+pub(crate) fn parse_input_with_vm(vm: &Vm, rule: &str, input: &str) -> Result<(), Error> {
+    match vm.parse(rule, input) {
         Ok(_pairs) => {
             // let lines: Vec<_> = pairs.map(|pair| format_pair(pair, 0, true)).collect();
             // let lines = lines.join("\n");
@@ -419,42 +755,39 @@ fn parse_input_with_vm<'a>(vm: Vm, rule: String, input: String) -> Result<(), St
         }
         Err(error) => {
             // output.set_value(&format!("{}", error.renamed_rules(|r| r.to_string())))
-            // FIXME: Eliminar el string para usar un tipo de error más "Rustacean"
-            Err(format!("{}", error.renamed_rules(|r| r.to_string())))
+            let error = error.renamed_rules(|r| r.to_string());
+            let (from, to) = match error.location {
+                InputLocation::Pos(pos) => (pos, pos),
+                InputLocation::Span((start, end)) => (start, end),
+            };
+
+            Err(Error::Parse(ParseError {
+                rule: rule.to_string(),
+                from: line_col(from, input),
+                to: line_col(to, input),
+                message: error.to_string(),
+            }))
         }
     }
     // }
 }
 
-fn convert_error(error: Error<Rule>, grammar: &str) -> HashMap<String, String> {
-    let message = match error.variant {
-        ErrorVariant::CustomError { message } => message,
-        _ => unreachable!(),
-    };
-
-    match error.location {
-        InputLocation::Pos(pos) => {
-            let mut map = HashMap::new();
+fn convert_error<R: pest::RuleType>(error: pest::error::Error<R>, grammar: &str) -> GrammarError {
+    let message = error.variant.message().to_string();
 
-            map.insert("from".to_owned(), line_col(pos, grammar));
-            map.insert("to".to_owned(), line_col(pos, grammar));
-            map.insert("message".to_owned(), format!("{}", message));
-
-            map
-        }
-        InputLocation::Span((start, end)) => {
-            let mut map = HashMap::new();
-
-            map.insert("from".to_owned(), line_col(start, grammar));
-            map.insert("to".to_owned(), line_col(end, grammar));
-            map.insert("message".to_owned(), format!("{}", message));
+    let (from, to) = match error.location {
+        InputLocation::Pos(pos) => (pos, pos),
+        InputLocation::Span((start, end)) => (start, end),
+    };
 
-            map
-        }
+    GrammarError {
+        from: line_col(from, grammar),
+        to: line_col(to, grammar),
+        message,
     }
 }
 
-fn line_col(pos: usize, input: &str) -> String {
+fn line_col(pos: usize, input: &str) -> (usize, usize) {
     let (line, col) = {
         let mut pos = pos;
         // Position's pos is always a UTF-8 border.
@@ -496,7 +829,7 @@ fn line_col(pos: usize, input: &str) -> String {
         line_col
     };
 
-    format!("({}, {})", line - 1, col - 1)
+    (line - 1, col - 1)
 }
 
 /// Replace builtin pest rules for their equivalents
@@ -516,7 +849,8 @@ fn line_col(pos: usize, input: &str) -> String {
 ///
 /// For the list of equivalent rules see https://pest.rs/book/grammars/built-ins.html
 fn replace_builtin_rules(grammar: &String) -> Result<String, std::io::Error> {
-    //TODO: Add the Unicode rules from https://pest.rs/book/grammars/built-ins.html
+    // The Unicode rules (LETTER, XID_START, ...) have hundreds of ranges, they aren't replaced here, the generator
+    // samples their codepoints directly, see generator/unicode.rs
 
     let patterns = &[
         "ANY",
@@ -1,11 +1,18 @@
 use bulk_examples_generator::compile_grammar;
-use bulk_examples_generator::config::{ExecutorConfig, GeneratorConfig};
-use bulk_examples_generator::generate_examples;
+use bulk_examples_generator::config::{
+    Compression, ExecutorConfig, GeneratorConfig, OutputFormat, Sharding,
+};
+use bulk_examples_generator::{enumerate_examples, generate_examples};
+use bulk_examples_generator::{Error, Generator, Outcome, TargetCommand};
 
-use std::fs::File;
+use rayon::prelude::*;
+use serde_derive::Serialize;
+use std::fs::{self, File};
 use std::io::prelude::*;
-use std::io::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+use structopt::clap;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -18,37 +25,183 @@ use structopt::StructOpt;
 /// Shortened
 ///
 /// bulk-examples-generator -g my-grammar.pest -q 5 -s myrule -o stdout
+///
+/// Coverage of a folder of examples
+///
+/// bulk-examples-generator -g my-grammar.pest -s myrule --mode coverage --corpus my-examples
+///
+/// All the examples up to a depth of 4 rules
+///
+/// bulk-examples-generator -g my-grammar.pest -s myrule --mode enumerate --max-depth 4
+///
+/// Examples that the grammar must reject, labelled with the mutation used to create them
+///
+/// bulk-examples-generator -g my-grammar.pest -q 5 -s myrule --mode invalid
+///
+/// Variants of the seeds of a folder, generating again random subtrees (or swapping subtrees between seeds with
+/// --splice)
+///
+/// bulk-examples-generator -g my-grammar.pest -q 20 -s myrule --mode mutate --corpus my-seeds
+///
+/// Smallest version of an example that still makes a command fail, {} is replaced with the path of a file with the
+/// example (without {} the example is written to the stdin of the command)
+///
+/// bulk-examples-generator -g my-grammar.pest -s myrule --mode shrink --input crash.txt --command "my-parser {}"
+///
+/// Feed examples to a command and save the ones that make it crash, fail or time out (or, with --differential,
+/// the ones where two commands disagree) in a folder
+///
+/// bulk-examples-generator -g my-grammar.pest -q 1000 -s myrule --mode run --command "my-parser {}" --timeout 2000
+/// --output-folder failures
+///
+/// Examples with the derivation tree that produced them, in the pair format of pest
+///
+/// bulk-examples-generator -g my-grammar.pest -q 5 -s myrule --derivation-tree pairs
+///
+/// Problems of the grammar that make the generation hang (unreachable rules, infinite rules, ...)
+///
+/// bulk-examples-generator -g my-grammar.pest -s myrule --mode analyze
 #[structopt(name = "bulk-examples-generator")]
 pub struct Opt {
     /// Path of grammar for generate examples
     #[structopt(short, long, parse(from_os_str))]
     pub grammar: PathBuf,
 
-    /// Quantity of examples to generate
+    /// Quantity of examples to generate (not used in analyze mode)
     #[structopt(short, long)]
-    pub quantity: u32,
+    pub quantity: Option<u32>,
 
     /// Rule to start generation of examples
     #[structopt(short, long)]
     pub start_rule: String,
 
-    /// Where to write the examples (multiples values can be used) debug, stdout, text, bar, folder
+    /// What to do with the grammar: generate, coverage, enumerate, analyze, invalid, mutate, shrink, run
+    ///
+    /// generate: Generate examples
+    /// coverage: Print the hits of every rule and alternative in the examples of --corpus (or in --quantity examples
+    /// generated)
+    /// enumerate: Print every distinct example up to --max-depth rule expansions, from the shortest to the longest
+    /// (at most --quantity examples)
+    /// analyze: Print the rules unreachable from --start-rule, the rules that can't derive a finite string, the rules
+    /// and alternatives with unbounded expected size and the predicates the generator can't honor
+    /// invalid: Print --quantity examples that the grammar rejects, generated with a mutated copy of the grammar,
+    /// every example after the mutation used to create it
+    /// mutate: Print --quantity variants of the seeds of --corpus, every variant after the subtrees replaced
+    /// shrink: Print the smallest version of --input that the grammar can parse and still makes --command fail
+    /// run: Run --command with --quantity examples and save the failing ones in --output-folder
+    #[structopt(
+        long,
+        default_value = "generate",
+        possible_values = &[
+            "generate", "coverage", "enumerate", "analyze", "invalid", "mutate", "shrink", "run",
+        ],
+        verbatim_doc_comment
+    )]
+    pub mode: String,
+
+    /// Max rules expanded in enumerate mode, with 1 only the start rule is expanded
+    #[structopt(long, required_if("mode", "enumerate"))]
+    pub max_depth: Option<usize>,
+
+    /// Examples used in coverage mode (or seeds in mutate mode), a folder with one example per file or a file with
+    /// one example per line
+    #[structopt(long, required_if("mode", "mutate"), parse(from_os_str))]
+    pub corpus: Option<PathBuf>,
+
+    /// Example to shrink in shrink mode
+    #[structopt(long, required_if("mode", "shrink"), parse(from_os_str))]
+    pub input: Option<PathBuf>,
+
+    /// Command used in shrink and run modes, an example fails when the command exits with an error status, crashes
+    /// or times out. {} is replaced with the path of a file with the example, without {} the example is written to
+    /// the stdin of the command. The arguments are split like in sh (with quotes and \), but the command isn't run
+    /// by a shell, use "sh -c '...'" for pipes or redirections
+    #[structopt(long, required_if("mode", "shrink"), required_if("mode", "run"))]
+    pub command: Option<String>,
+
+    /// Second command used in run mode for differential testing, an example also fails when the two commands finish
+    /// in a different way or print a different output
+    #[structopt(long)]
+    pub differential: Option<String>,
+
+    /// Max milliseconds of every execution of the commands (shrink and run modes)
+    #[structopt(long)]
+    pub timeout: Option<u64>,
+
+    /// In mutate mode, replace the subtrees with subtrees of the same rule of the next seed instead of generating
+    /// them again
+    #[structopt(long)]
+    pub splice: bool,
+
+    /// Format of the coverage report, the analysis, the invalid examples and the variants: table, json
+    #[structopt(long, default_value = "table", possible_values = &["table", "json"])]
+    pub report_format: String,
+
+    /// Print every example with the derivation tree that produced it (generate mode): json, pairs
+    ///
+    /// json: Print the examples and their trees (rules, chosen alternatives and byte spans) as a JSON array
+    /// pairs: Print the pairs that pest produces when parsing the example before every example
+    #[structopt(long, possible_values = &["json", "pairs"], verbatim_doc_comment)]
+    pub derivation_tree: Option<String>,
+
+    /// Where to write the examples (multiples values can be used) debug, stdout, text, bar, file, jsonl, csv, folder
     ///
     /// debug: Print results in stdout (vec form) for debugging purposes
     /// stdout: Print results in stdout
     /// text: Print "Example #n generated:" before print the example
     /// bar: Print progress bar
+    /// file: Save all the examples in --output-file, every example followed by --separator
+    /// jsonl: Write one JSON record per line (index, start_rule, example, length, ok, error, seed) in --output-file,
+    /// or in stdout without --output-file
+    /// csv: Write the same records as jsonl in CSV, with a header
     /// folder: Create one file for each example (use template_name for personalize the filename and output_folder)
     ///
     #[structopt(short, long, verbatim_doc_comment)]
     pub out_type: Vec<String>,
 
-    // TODO: is necessary implement this?
-    // /// file: Save all examples in a single file
-    // #[structopt(required_if("out_type", "file"), parse(from_os_str))]
-    // pub output_file: Option<PathBuf>,
-    /// Output folder to save the examples
+    /// File to save all the examples (file, jsonl and csv out types)
+    #[structopt(long, parse(from_os_str))]
+    pub output_file: Option<PathBuf>,
+
+    /// Text written after every example in stdout and file out types: newline, nul or any other text (\n, \t, \0 and
+    /// \\ are replaced), use nul when the examples can contain newlines
+    #[structopt(long, default_value = "newline")]
+    pub separator: String,
+
+    /// Write the examples in stdout, file, jsonl and csv out types in order (example #1, #2, ...) in parallel mode
+    #[structopt(long)]
+    pub ordered: bool,
+
+    /// Add to the jsonl and csv records how many times every rule was expanded in the example
+    #[structopt(long)]
+    pub rule_hits: bool,
+
+    /// Split --output-file in shards of at most this number of examples, {shard} in --output-file is replaced with
+    /// the number of the shard, e.g. corpus-{shard}.jsonl
+    #[structopt(long)]
+    pub shard_examples: Option<u64>,
+
+    /// Split --output-file in shards of at most this size, e.g. 500K, 64M or 2G
+    #[structopt(long, parse(try_from_str = parse_size))]
+    pub shard_bytes: Option<u64>,
+
+    /// Manifest with the files, examples and bytes of the shards [default: in the folder of the shards, named after
+    /// --output-file, e.g. corpus-manifest.json for corpus-{shard}.jsonl]
     #[structopt(long, parse(from_os_str))]
+    pub manifest: Option<PathBuf>,
+
+    /// Compress --output-file (every shard) and the files of the folder out type [default: gzip for the files ending
+    /// in .gz, zstd for the files ending in .zst], the extension is added to the files without it
+    #[structopt(long, possible_values = &["gzip", "zstd"])]
+    pub compression: Option<String>,
+
+    /// Pack the files of the folder out type in one tar archive, --output-folder is the archive (.tar is added if
+    /// it's missing). An --output-folder ending in .tar, .tar.gz, .tgz or .tar.zst is always an archive
+    #[structopt(long)]
+    pub tar: bool,
+
+    /// Output folder to save the examples (or the failing examples in run mode)
+    #[structopt(long, required_if("mode", "run"), parse(from_os_str))]
     pub output_folder: Option<PathBuf>,
 
     /// Name of the files, e.g. html-test-{}.html, {} will be used for enumerating the example
@@ -63,19 +216,60 @@ pub struct Opt {
     #[structopt(long)]
     /// Disable parallel mode
     pub sequential: bool,
+
+    /// Seed for the random generation, the same seed always generates the same examples
+    /// (in parallel or sequential mode)
+    #[structopt(long)]
+    pub seed: Option<u64>,
+
+    /// TOML file with the weights of the alternatives of the rules, e.g. keyword = [10, 5, 1]
+    #[structopt(long, parse(from_os_str))]
+    pub weights: Option<PathBuf>,
+
+    /// Prefer the alternatives and repetition bounds not generated yet, the final coverage is printed in stderr
+    #[structopt(long)]
+    pub coverage_guided: bool,
+
+    /// Stop when all the alternatives of the grammar have been generated (implies --coverage-guided)
+    #[structopt(long)]
+    pub stop_on_full_coverage: bool,
 }
 
-fn main() -> Result<(), Error> {
+fn main() {
     let opt = Opt::from_args();
 
+    if let Err(error) = run(opt) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run(opt: Opt) -> Result<(), Error> {
     let mut gen_config: GeneratorConfig = Default::default();
     if let Some(config_file) = &opt.config_file {
-        gen_config = GeneratorConfig::new(config_file.to_str().unwrap()).unwrap();
+        gen_config = GeneratorConfig::new(config_file.to_str().unwrap())?;
+    }
+    if opt.seed.is_some() {
+        gen_config.seed = opt.seed;
+    }
+    if let Some(weights_file) = &opt.weights {
+        gen_config.load_weights(weights_file.to_str().unwrap())?;
+    }
+
+    if opt.coverage_guided || opt.stop_on_full_coverage {
+        gen_config.coverage_guided = true;
     }
 
-    let mut exe_config: ExecutorConfig = Default::default();
-    exe_config.print_stdout = false;
-    exe_config.parallel_mode = !opt.sequential;
+    let mut exe_config = ExecutorConfig {
+        print_stdout: false,
+        parallel_mode: !opt.sequential,
+        stop_on_full_coverage: opt.stop_on_full_coverage,
+        print_coverage: gen_config.coverage_guided,
+        separator: separator(&opt.separator),
+        ordered_output: opt.ordered,
+        rule_hits: opt.rule_hits,
+        ..Default::default()
+    };
     // if let Some(config_file) = &opt.config_file {
     //     config = GeneratorConfig::new(config_file.to_str().unwrap()).unwrap();
     // }
@@ -85,6 +279,57 @@ fn main() -> Result<(), Error> {
     let mut f = File::open(&opt.grammar)?;
     f.read_to_string(&mut grammar_string)?;
 
+    if opt.derivation_tree.is_some() && opt.mode != "generate" {
+        clap::Error::with_description(
+            "The argument '--derivation-tree <derivation-tree>' can only be used in generate mode",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit()
+    }
+    if opt.mode == "coverage" {
+        return coverage(opt, grammar_string, &gen_config, exe_config);
+    }
+    if opt.mode == "analyze" {
+        let generator = Generator::new(grammar_string)?.with_config(gen_config);
+        let analysis = generator.analyze(&opt.start_rule)?;
+        if opt.report_format == "json" {
+            println!("{}", analysis.to_json());
+        } else {
+            print!("{}", analysis);
+        }
+        return Ok(());
+    }
+    if opt.mode == "invalid" {
+        return invalid(opt, grammar_string, gen_config);
+    }
+    if opt.mode == "mutate" {
+        return mutate(opt, grammar_string, gen_config);
+    }
+    if opt.mode == "shrink" {
+        return shrink(opt, grammar_string);
+    }
+    if opt.mode == "run" {
+        return run_examples(opt, grammar_string, gen_config);
+    }
+    if opt.derivation_tree.is_some() {
+        return derived(opt, grammar_string, gen_config);
+    }
+    if opt.mode == "enumerate" {
+        let examples = enumerate_examples(
+            grammar_string,
+            opt.start_rule,
+            opt.max_depth.unwrap(),
+            &gen_config,
+        )?;
+        let quantity = opt
+            .quantity
+            .map_or(usize::MAX, |quantity| quantity as usize);
+        for example in examples.take(quantity) {
+            println!("{}", example);
+        }
+        return Ok(());
+    }
+
     if opt.out_type.contains(&"debug".to_string()) {
         // Print input parameters
         println!("{:?}", &opt);
@@ -108,19 +353,59 @@ fn main() -> Result<(), Error> {
     if opt.out_type.contains(&"bar".to_string()) {
         exe_config.print_progress_bar = true;
     }
+    let quantity = required_quantity(&opt);
+    if opt.out_type.contains(&"file".to_string()) {
+        match &opt.output_file {
+            Some(output_file) => exe_config.print_file = Some(output_file.clone()),
+            None => clap::Error::with_description(
+                "The argument '--output-file <output-file>' is required with the file out type",
+                clap::ErrorKind::MissingRequiredArgument,
+            )
+            .exit(),
+        }
+    }
+    for (out_type, format) in &[("jsonl", OutputFormat::Jsonl), ("csv", OutputFormat::Csv)] {
+        if opt.out_type.contains(&out_type.to_string()) {
+            exe_config.output_format = *format;
+            match &opt.output_file {
+                Some(output_file) => exe_config.print_file = Some(output_file.clone()),
+                None => exe_config.print_stdout = true,
+            }
+        }
+    }
+    if opt.shard_examples.is_some() || opt.shard_bytes.is_some() {
+        exe_config.sharding = Some(Sharding {
+            max_examples: opt.shard_examples,
+            max_bytes: opt.shard_bytes,
+            manifest: opt.manifest.clone(),
+        });
+    }
+    exe_config.compression = match opt.compression.as_deref() {
+        Some("gzip") => Some(Compression::Gzip),
+        Some("zstd") => Some(Compression::Zstd),
+        _ => None,
+    };
+    exe_config.tar_folder = opt.tar;
     if opt.out_type.contains(&"folder".to_string()) {
         // Output folder
-        exe_config.print_folder = Some((opt.template_name, opt.output_folder.unwrap()));
+        match opt.output_folder {
+            Some(output_folder) => exe_config.print_folder = Some((opt.template_name, output_folder)),
+            None => clap::Error::with_description(
+                "The argument '--output-folder <output-folder>' is required with the folder out type",
+                clap::ErrorKind::MissingRequiredArgument,
+            )
+            .exit(),
+        }
     }
 
     // Generating examples
     let results = generate_examples(
         grammar_string,
-        opt.quantity,
+        quantity,
         opt.start_rule,
         &gen_config,
         &exe_config,
-    );
+    )?;
 
     if opt.out_type.contains(&"debug".to_string()) {
         // Print vec
@@ -129,3 +414,320 @@ fn main() -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Imprime el reporte de cobertura de los ejemplos de `--corpus` o de `--quantity` ejemplos generados
+fn coverage(
+    opt: Opt,
+    grammar_string: String,
+    gen_config: &GeneratorConfig,
+    mut exe_config: ExecutorConfig,
+) -> Result<(), Error> {
+    let examples = match &opt.corpus {
+        Some(corpus) => read_corpus(corpus)?,
+        None => {
+            exe_config.return_vec = true;
+            generate_examples(
+                grammar_string.clone(),
+                required_quantity(&opt),
+                opt.start_rule.clone(),
+                gen_config,
+                &exe_config,
+            )?
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect()
+        }
+    };
+
+    let generator = Generator::new(grammar_string)?;
+    let report = generator.coverage_report(&opt.start_rule, &examples)?;
+    if opt.report_format == "json" {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}", report);
+    }
+    Ok(())
+}
+
+/// Imprime `--quantity` ejemplos inválidos, cada uno después de su mutación
+fn invalid(opt: Opt, grammar_string: String, gen_config: GeneratorConfig) -> Result<(), Error> {
+    let generator = Generator::new(grammar_string)?.with_config(gen_config);
+    let results = generator.generate_invalid_n(&opt.start_rule, required_quantity(&opt).into());
+
+    let mut examples = vec![];
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(example) => examples.push(example),
+            Err(error) => eprintln!("Invalid example #{}: {}", index + 1, error),
+        }
+    }
+
+    if opt.report_format == "json" {
+        println!("{}", serde_json::to_string_pretty(&examples).unwrap());
+    } else {
+        for example in examples {
+            println!("# {}\n{}", example.mutation, example.text);
+        }
+    }
+    Ok(())
+}
+
+/// Imprime `--quantity` variantes de las semillas de `--corpus`, cada una después de sus mutaciones
+fn mutate(opt: Opt, grammar_string: String, gen_config: GeneratorConfig) -> Result<(), Error> {
+    let seeds = read_corpus(opt.corpus.as_ref().unwrap())?;
+    let generator = Generator::new(grammar_string)?.with_config(gen_config);
+    let quantity = required_quantity(&opt).into();
+    let results = if opt.splice {
+        generator.splice_n(&opt.start_rule, &seeds, quantity)
+    } else {
+        generator.mutate_n(&opt.start_rule, &seeds, quantity)
+    };
+
+    let mut examples = vec![];
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(example) => examples.push(example),
+            Err(error) => eprintln!("Variant #{}: {}", index + 1, error),
+        }
+    }
+
+    if opt.report_format == "json" {
+        println!("{}", serde_json::to_string_pretty(&examples).unwrap());
+    } else {
+        for example in examples {
+            for mutation in &example.mutations {
+                println!("# {}", mutation);
+            }
+            println!("{}", example.text);
+        }
+    }
+    Ok(())
+}
+
+/// Imprime la versión más pequeña de `--input` con la que `--command` sigue fallando
+fn shrink(opt: Opt, grammar_string: String) -> Result<(), Error> {
+    let example = fs::read_to_string(opt.input.as_ref().unwrap())?;
+    let command = target_command(&opt, opt.command.as_ref().unwrap());
+
+    let generator = Generator::new(grammar_string)?;
+    // Un comando que no se puede ejecutar no cuenta como fallo
+    let shrunk = generator.shrink(&opt.start_rule, &example, |candidate| {
+        command
+            .run(candidate)
+            .is_ok_and(|output| output.outcome.is_failure())
+    })?;
+    eprintln!("Shrunk from {} to {} bytes", example.len(), shrunk.len());
+    print!("{}", shrunk);
+    Ok(())
+}
+
+/// Ejemplo que hizo fallar a `--command` (o en el que los comandos no coinciden), guardado en `--output-folder`
+#[derive(Serialize)]
+struct Failure {
+    /// Número del ejemplo, con `seed` se puede generar de nuevo
+    example: u64,
+    seed: u64,
+    file: String,
+    outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    differential: Option<Outcome>,
+    /// Las salidas de los comandos son diferentes
+    mismatch: bool,
+}
+
+/// Ejecuta `--command` con `--quantity` ejemplos y guarda los que fallan en `--output-folder`, con un resumen en
+/// failures.json
+fn run_examples(
+    opt: Opt,
+    grammar_string: String,
+    mut gen_config: GeneratorConfig,
+) -> Result<(), Error> {
+    // Sin semilla se elige una al azar, así los ejemplos que fallan se pueden generar de nuevo
+    let seed = gen_config.seed.unwrap_or_else(rand::random);
+    gen_config.seed = Some(seed);
+    let command = target_command(&opt, opt.command.as_ref().unwrap());
+    let differential = opt
+        .differential
+        .as_ref()
+        .map(|differential| target_command(&opt, differential));
+    let folder = opt.output_folder.as_ref().unwrap();
+    fs::create_dir_all(folder)?;
+
+    let generator = Generator::new(grammar_string)?.with_config(gen_config);
+    let check = |index: u64| -> Result<Option<Failure>, Error> {
+        let example = match generator.generate_nth(&opt.start_rule, index) {
+            Ok(example) => example,
+            Err(error) => {
+                eprintln!("Example #{}: {}", index, error);
+                return Ok(None);
+            }
+        };
+        let output = command.run(&example)?;
+        let differential_output = match &differential {
+            Some(differential) => Some(differential.run(&example)?),
+            None => None,
+        };
+        let mismatch = differential_output
+            .as_ref()
+            .is_some_and(|other| output.differs_from(other));
+        if !output.outcome.is_failure() && !mismatch {
+            return Ok(None);
+        }
+
+        let file = opt.template_name.replace("{}", &index.to_string());
+        fs::write(folder.join(&file), &example)?;
+        Ok(Some(Failure {
+            example: index,
+            seed,
+            file,
+            outcome: output.outcome,
+            differential: differential_output.map(|other| other.outcome),
+            mismatch,
+        }))
+    };
+
+    let quantity = u64::from(required_quantity(&opt));
+    let results: Result<Vec<Option<Failure>>, Error> = if opt.sequential {
+        (1..quantity + 1).map(check).collect()
+    } else {
+        (1..quantity + 1).into_par_iter().map(check).collect()
+    };
+    let failures: Vec<Failure> = results?.into_iter().flatten().collect();
+
+    let summary = serde_json::to_string_pretty(&failures).unwrap();
+    fs::write(folder.join("failures.json"), summary)?;
+    for failure in &failures {
+        let mut line = format!(
+            "{} (example #{}, seed {}): {}",
+            failure.file, failure.example, failure.seed, failure.outcome
+        );
+        if let Some(differential) = failure.differential {
+            line.push_str(&format!(", differential: {}", differential));
+        }
+        if failure.mismatch {
+            line.push_str(", outputs differ");
+        }
+        println!("{}", line);
+    }
+    println!("{} examples, {} failing", quantity, failures.len());
+    Ok(())
+}
+
+/// Comando con el `--timeout` de las opciones, termina el programa si el comando está vacío
+fn target_command(opt: &Opt, command: &str) -> TargetCommand {
+    let target = match TargetCommand::new(command) {
+        Some(target) => target,
+        None => clap::Error::with_description(
+            "The command can't be empty or have unclosed quotes",
+            clap::ErrorKind::InvalidValue,
+        )
+        .exit(),
+    };
+    match opt.timeout {
+        Some(timeout) => target.with_timeout(Duration::from_millis(timeout)),
+        None => target,
+    }
+}
+
+/// Imprime `--quantity` ejemplos con su árbol de derivación, en JSON o después de los pares de pest
+fn derived(opt: Opt, grammar_string: String, gen_config: GeneratorConfig) -> Result<(), Error> {
+    let generator = Generator::new(grammar_string)?.with_config(gen_config);
+    let results = generator.generate_derived_n(&opt.start_rule, required_quantity(&opt).into());
+
+    let mut examples = vec![];
+    for (index, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(example) => examples.push(example),
+            Err(error) => eprintln!("Example #{}: {}", index + 1, error),
+        }
+    }
+
+    if opt.derivation_tree.as_deref() == Some("json") {
+        println!("{}", serde_json::to_string_pretty(&examples).unwrap());
+    } else {
+        for example in examples {
+            println!("# {}\n{}", example.to_pairs_string(), example.text);
+        }
+    }
+    Ok(())
+}
+
+/// Lee los ejemplos de una carpeta (un ejemplo por archivo) o de un archivo (un ejemplo por línea)
+fn read_corpus(corpus: &Path) -> Result<Vec<String>, Error> {
+    if corpus.is_dir() {
+        let mut paths = fs::read_dir(corpus)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+        paths
+            .into_iter()
+            .filter(|path| path.is_file())
+            .map(|path| Ok(fs::read_to_string(path)?))
+            .collect()
+    } else {
+        Ok(fs::read_to_string(corpus)?
+            .lines()
+            .map(|line| line.to_string())
+            .collect())
+    }
+}
+
+/// Separador de `--separator`: newline, nul o el texto con los escapes \n, \t, \0 y \\ reemplazados
+fn separator(separator: &str) -> String {
+    match separator {
+        "newline" => "\n".to_string(),
+        "nul" => "\0".to_string(),
+        separator => {
+            let mut result = String::new();
+            let mut chars = separator.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    result.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    Some('0') => result.push('\0'),
+                    Some('\\') => result.push('\\'),
+                    Some(other) => {
+                        result.push('\\');
+                        result.push(other);
+                    }
+                    None => result.push('\\'),
+                }
+            }
+            result
+        }
+    }
+}
+
+/// Tamaño en bytes de `--shard-bytes`, con los sufijos K, M y G (potencias de 1024)
+fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    // Los tamaños que no caben en u64 también son inválidos
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid size: {}", size))
+}
+
+/// `--quantity` solo es opcional en los modos enumerate y analyze, o cuando se usa `--corpus`
+fn required_quantity(opt: &Opt) -> u32 {
+    match opt.quantity {
+        Some(quantity) => quantity,
+        None => clap::Error::with_description(
+            "The argument '--quantity <quantity>' is required",
+            clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    }
+}
@@ -0,0 +1,535 @@
+use crate::config::{Compression, ExecutorConfig, OutputFormat, Sharding};
+use crate::error::{Error, GenerationError};
+use crate::generator::{DerivationNode, Generator};
+use crate::sink::ExampleSink;
+
+use flate2::write::GzEncoder;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Ejemplo generado con su número y, con `rule_hits`, las veces que se expandió cada regla
+pub(crate) struct Record {
+    pub(crate) index: u32,
+    pub(crate) result: Result<String, GenerationError>,
+    pub(crate) hits: Option<BTreeMap<String, usize>>,
+}
+
+impl Record {
+    /// Genera el ejemplo número `index`, con `with_hits` se genera con su árbol de derivación para contar las reglas
+    pub(crate) fn generate(
+        generator: &Generator,
+        start: &str,
+        index: u32,
+        with_hits: bool,
+    ) -> Self {
+        if !with_hits {
+            return Record {
+                index,
+                result: generator.generate_nth(start, index.into()),
+                hits: None,
+            };
+        }
+        match generator.generate_derived_nth(start, index.into()) {
+            Ok(derived) => {
+                let mut hits = BTreeMap::new();
+                count_rules(&derived.tree, &mut hits);
+                Record {
+                    index,
+                    result: Ok(derived.text),
+                    hits: Some(hits),
+                }
+            }
+            Err(error) => Record {
+                index,
+                result: Err(error),
+                hits: None,
+            },
+        }
+    }
+
+    /// Envía el ejemplo al sink, con los conteos de las reglas si se calcularon
+    pub(crate) fn send_to(&self, sink: &mut dyn ExampleSink) -> Result<(), Error> {
+        match &self.hits {
+            Some(hits) => sink.on_example_with_hits(self.index, &self.result, hits),
+            None => sink.on_example(self.index, &self.result),
+        }
+    }
+}
+
+fn count_rules(node: &DerivationNode, hits: &mut BTreeMap<String, usize>) {
+    *hits.entry(node.rule.clone()).or_insert(0) += 1;
+    for child in &node.children {
+        count_rules(child, hits);
+    }
+}
+
+/// Registro de un ejemplo en formato JSON Lines
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    index: u32,
+    start_rule: &'a str,
+    example: Option<&'a str>,
+    length: Option<usize>,
+    ok: bool,
+    error: Option<String>,
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule_hits: Option<&'a BTreeMap<String, usize>>,
+}
+
+/// Ejemplos que esperan a que se escriban los anteriores, por índice
+type Pending = BTreeMap<u32, Record>;
+
+/// Escribe los ejemplos en stdout o en un archivo, como texto seguido del separador o como registros JSON Lines o CSV,
+/// es la base de `StdoutSink` y `FileSink`
+///
+/// Con `ordered_output` los ejemplos que terminan antes que los anteriores (modo paralelo) se guardan hasta que se
+/// pueden escribir en orden
+pub(crate) struct ExampleWriter {
+    stdout: Option<BufWriter<io::Stdout>>,
+    file: Option<FileOutput>,
+    format: OutputFormat,
+    separator: Vec<u8>,
+    start_rule: String,
+    seed: Option<u64>,
+    /// Reglas de la gramática, son las columnas de conteos en CSV
+    rules: Vec<String>,
+    /// Siguiente índice a escribir y ejemplos pendientes, solo con `ordered_output`
+    order: Option<(u32, Pending)>,
+}
+
+impl ExampleWriter {
+    /// Escribe en stdout si `stdout` es true y en el archivo `file`, con el formato de `executor_config`
+    pub(crate) fn new(
+        generator: &Generator,
+        start: &str,
+        executor_config: &ExecutorConfig,
+        stdout: bool,
+        file: Option<&Path>,
+    ) -> Result<Self, Error> {
+        let file = file.map(|path| {
+            FileOutput::new(
+                path,
+                executor_config.sharding.clone(),
+                executor_config.compression,
+            )
+        });
+        let mut rules: Vec<String> = if executor_config.rule_hits {
+            generator.grammar().rules.keys().cloned().collect()
+        } else {
+            vec![]
+        };
+        rules.sort();
+
+        let mut writer = ExampleWriter {
+            stdout: if stdout {
+                Some(BufWriter::new(io::stdout()))
+            } else {
+                None
+            },
+            file,
+            format: executor_config.output_format,
+            separator: executor_config.separator.as_bytes().to_vec(),
+            start_rule: start.to_string(),
+            seed: generator.config().seed,
+            rules,
+            order: if executor_config.ordered_output {
+                Some((1, BTreeMap::new()))
+            } else {
+                None
+            },
+        };
+        if writer.format == OutputFormat::Csv {
+            let mut header: Vec<String> = [
+                "index",
+                "start_rule",
+                "example",
+                "length",
+                "ok",
+                "error",
+                "seed",
+            ]
+            .iter()
+            .map(|column| column.to_string())
+            .collect();
+            header.extend(writer.rules.iter().map(|rule| format!("hits_{}", rule)));
+            let header = csv_line(&header)?;
+            if let Some(stdout) = &mut writer.stdout {
+                stdout.write_all(&header)?;
+            }
+            if let Some(file) = &mut writer.file {
+                file.header = header;
+            }
+        }
+        if let Some(file) = &mut writer.file {
+            file.open_shard()?;
+        }
+        Ok(writer)
+    }
+
+    /// Escribe el ejemplo (o guarda una copia hasta que se escriban los anteriores)
+    fn write(
+        &mut self,
+        index: u32,
+        result: &Result<String, GenerationError>,
+        hits: Option<&BTreeMap<String, usize>>,
+    ) -> Result<(), Error> {
+        let ready = match &mut self.order {
+            Some((next, pending)) => {
+                pending.insert(
+                    index,
+                    Record {
+                        index,
+                        result: result.clone(),
+                        hits: hits.cloned(),
+                    },
+                );
+                let mut ready = vec![];
+                while let Some(record) = pending.remove(next) {
+                    ready.push(record);
+                    *next += 1;
+                }
+                ready
+            }
+            None => return self.write_record(index, result, hits),
+        };
+        for record in &ready {
+            self.write_record(record.index, &record.result, record.hits.as_ref())?;
+        }
+        Ok(())
+    }
+
+    fn write_record(
+        &mut self,
+        index: u32,
+        result: &Result<String, GenerationError>,
+        hits: Option<&BTreeMap<String, usize>>,
+    ) -> Result<(), Error> {
+        let bytes = match self.format {
+            OutputFormat::Text => match result {
+                Ok(example) => {
+                    let mut bytes = example.as_bytes().to_vec();
+                    bytes.extend_from_slice(&self.separator);
+                    bytes
+                }
+                Err(error) => {
+                    eprintln!("{}", error);
+                    return Ok(());
+                }
+            },
+            OutputFormat::Jsonl => {
+                let json = JsonRecord {
+                    index,
+                    start_rule: &self.start_rule,
+                    example: result.as_deref().ok(),
+                    length: result.as_ref().ok().map(String::len),
+                    ok: result.is_ok(),
+                    error: result.as_ref().err().map(ToString::to_string),
+                    seed: self.seed,
+                    rule_hits: hits,
+                };
+                let mut bytes = serde_json::to_vec(&json).map_err(io::Error::from)?;
+                bytes.push(b'\n');
+                bytes
+            }
+            OutputFormat::Csv => {
+                let (example, length, error) = match result {
+                    Ok(example) => (example.clone(), example.len().to_string(), String::new()),
+                    Err(error) => (String::new(), String::new(), error.to_string()),
+                };
+                let mut fields = vec![
+                    index.to_string(),
+                    self.start_rule.clone(),
+                    example,
+                    length,
+                    result.is_ok().to_string(),
+                    error,
+                    self.seed.map(|seed| seed.to_string()).unwrap_or_default(),
+                ];
+                for rule in &self.rules {
+                    let hits = hits.and_then(|hits| hits.get(rule)).copied().unwrap_or(0);
+                    fields.push(hits.to_string());
+                }
+                csv_line(&fields)?
+            }
+        };
+        self.write_bytes(&bytes)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if let Some(stdout) = &mut self.stdout {
+            stdout.write_all(bytes)?;
+        }
+        if let Some(file) = &mut self.file {
+            file.write(bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl ExampleSink for ExampleWriter {
+    fn on_example(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+    ) -> Result<(), Error> {
+        self.write(index, example, None)
+    }
+
+    fn on_example_with_hits(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+        rule_hits: &BTreeMap<String, usize>,
+    ) -> Result<(), Error> {
+        self.write(index, example, Some(rule_hits))
+    }
+
+    /// Escribe los ejemplos pendientes (los ejemplos omitidos dejan huecos en los índices) y termina el archivo
+    fn finish(&mut self) -> Result<(), Error> {
+        if let Some((_, pending)) = self.order.take() {
+            for record in pending.values() {
+                self.write_record(record.index, &record.result, record.hits.as_ref())?;
+            }
+        }
+        if let Some(stdout) = &mut self.stdout {
+            stdout.flush()?;
+        }
+        if let Some(file) = self.file.take() {
+            file.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Archivo de `print_file`, dividido en shards con `sharding`
+struct FileOutput {
+    /// Ruta del archivo, o plantilla de los shards
+    path: PathBuf,
+    sharding: Option<Sharding>,
+    compression: Option<Compression>,
+    /// Encabezado escrito al inicio de cada archivo (CSV)
+    header: Vec<u8>,
+    writer: Option<Encoder<BufWriter<File>>>,
+    /// Shards creados, el último es el shard actual
+    shards: Vec<Shard>,
+}
+
+/// Shard del manifiesto, los shards están en la carpeta de la plantilla
+#[derive(Serialize)]
+struct Shard {
+    file: String,
+    examples: u64,
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    examples: u64,
+    bytes: u64,
+    shards: &'a [Shard],
+}
+
+impl FileOutput {
+    fn new(path: &Path, sharding: Option<Sharding>, compression: Option<Compression>) -> Self {
+        FileOutput {
+            path: path.to_path_buf(),
+            sharding,
+            compression,
+            header: vec![],
+            writer: None,
+            shards: vec![],
+        }
+    }
+
+    /// Crea el siguiente archivo (el único archivo sin `sharding`) y escribe el encabezado
+    fn open_shard(&mut self) -> Result<(), Error> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        let path = match &self.sharding {
+            Some(_) => shard_path(&self.path, self.shards.len() + 1),
+            None => self.path.clone(),
+        };
+        let (path, compression) = compressed_path(&path, self.compression);
+        let mut writer = Encoder::new(BufWriter::new(File::create(&path)?), compression)?;
+        writer.write_all(&self.header)?;
+        self.writer = Some(writer);
+        self.shards.push(Shard {
+            file: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            examples: 0,
+            bytes: self.header.len() as u64,
+        });
+        Ok(())
+    }
+
+    /// Escribe un ejemplo, antes se cambia de shard si el shard actual está lleno
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if let (Some(sharding), Some(shard)) = (&self.sharding, self.shards.last()) {
+            let full_examples = sharding
+                .max_examples
+                .is_some_and(|max_examples| shard.examples >= max_examples);
+            let full_bytes = sharding.max_bytes.is_some_and(|max_bytes| {
+                shard.examples > 0 && shard.bytes + bytes.len() as u64 > max_bytes
+            });
+            if full_examples || full_bytes {
+                self.open_shard()?;
+            }
+        }
+
+        if let Some(writer) = &mut self.writer {
+            writer.write_all(bytes)?;
+        }
+        if let Some(shard) = self.shards.last_mut() {
+            shard.examples += 1;
+            shard.bytes += bytes.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Termina el último archivo y, con `sharding`, escribe el manifiesto
+    fn finish(mut self) -> Result<(), Error> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        if let Some(sharding) = &self.sharding {
+            let manifest = Manifest {
+                examples: self.shards.iter().map(|shard| shard.examples).sum(),
+                bytes: self.shards.iter().map(|shard| shard.bytes).sum(),
+                shards: &self.shards,
+            };
+            let path = match &sharding.manifest {
+                Some(path) => path.clone(),
+                None => manifest_path(&self.path),
+            };
+            let manifest = serde_json::to_string_pretty(&manifest).map_err(io::Error::from)?;
+            std::fs::write(path, manifest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Ruta del shard número `shard`, `{shard}` se reemplaza con el número o el número se añade antes de la extensión
+fn shard_path(template: &Path, shard: usize) -> PathBuf {
+    template.with_file_name(shard_name(template, &shard.to_string()))
+}
+
+/// Ruta del manifiesto por defecto, en la carpeta de los shards y con el nombre de la plantilla, e.g. el manifiesto de
+/// `corpus-{shard}.jsonl` es `corpus-manifest.json`
+fn manifest_path(template: &Path) -> PathBuf {
+    let name = shard_name(template, "manifest");
+    let stem = name.split('.').next().unwrap_or_default();
+    template.with_file_name(format!("{}.json", stem))
+}
+
+/// Nombre de la plantilla con `{shard}` reemplazado por `shard`, sin `{shard}` se añade antes de la extensión
+fn shard_name(template: &Path, shard: &str) -> String {
+    let name = template
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if name.contains("{shard}") {
+        name.replace("{shard}", shard)
+    } else {
+        match name.find('.') {
+            Some(dot) => format!("{}-{}{}", &name[..dot], shard, &name[dot..]),
+            None => format!("{}-{}", name, shard),
+        }
+    }
+}
+
+/// Escritor de un archivo, sin comprimir o comprimido con gzip o zstd
+pub(crate) enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub(crate) fn new(writer: W, compression: Option<Compression>) -> io::Result<Self> {
+        Ok(match compression {
+            None => Encoder::Plain(writer),
+            Some(Compression::Gzip) => {
+                Encoder::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            Some(Compression::Zstd) => Encoder::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    /// Escribe el final del stream comprimido y vacía el buffer, sin esto el archivo comprimido queda truncado
+    pub(crate) fn finish(self) -> io::Result<()> {
+        let mut writer = match self {
+            Encoder::Plain(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Ruta y compresión de un archivo: con una compresión explícita se añade su extensión si falta, sin compresión
+/// explícita se usa la de la extensión
+pub(crate) fn compressed_path(
+    path: &Path,
+    compression: Option<Compression>,
+) -> (PathBuf, Option<Compression>) {
+    match compression {
+        Some(compression) if Compression::from_path(path) != Some(compression) => (
+            append_extension(path, compression.extension()),
+            Some(compression),
+        ),
+        Some(compression) => (path.to_path_buf(), Some(compression)),
+        None => (path.to_path_buf(), Compression::from_path(path)),
+    }
+}
+
+/// Añade `.extension` al nombre del archivo, manteniendo las extensiones que ya tiene
+pub(crate) fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// La ruta es un archivo tar, comprimido o no
+pub(crate) fn is_tar(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    [".tar", ".tar.gz", ".tgz", ".tar.zst"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// Línea CSV con los campos, los campos con comas, comillas o saltos de línea se escriben entre comillas
+fn csv_line(fields: &[String]) -> Result<Vec<u8>, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer
+        .write_record(fields)
+        .map_err(|error| Error::Io(error.into()))?;
+    writer
+        .into_inner()
+        .map_err(|error| Error::Io(error.into_error()))
+}
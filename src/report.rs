@@ -0,0 +1,314 @@
+use crate::error::Error;
+use crate::generator::Grammar;
+
+use pest_meta::ast::{Expr, Rule as AstRule, RuleType};
+use pest_meta::optimizer;
+use pest_vm::Vm;
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Prefijo de las reglas temporales que envuelven cada alternativa de un choice
+const ALTERNATIVE_RULE: &str = "__bulk_examples_generator_alternative_";
+
+/// How much of a grammar is exercised by a corpus of examples
+///
+/// Every example is parsed with `pest_vm` and the pairs produced are counted, a rule is hit when a pair of the rule
+/// is produced (the silent and atomic rules are counted too) and an alternative of `a | b | c` is hit when it's the
+/// alternative matched by the choice. The examples that can't be parsed are counted as rejected and don't add hits.
+///
+/// ```
+/// use bulk_examples_generator::{compile_grammar, coverage_report};
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go"}
+///         sentence = {"I like " ~ language}
+///     "#;
+///
+/// let examples = vec!["I like Rust".to_string(), "I like Go".to_string()];
+/// let report = coverage_report(
+///             compile_grammar(grammar.to_string()).unwrap(),
+///             "sentence".to_string(),
+///             &examples,
+///         ).unwrap();
+///
+/// assert_eq!(1, report.unhit_alternatives().len());
+/// println!("{}", report);
+/// ```
+#[derive(Clone, Debug, Serialize)]
+pub struct CoverageReport {
+    /// Number of examples parsed
+    pub examples: usize,
+    /// Number of examples that can't be parsed with the grammar
+    pub rejected: usize,
+    /// Hits of every rule of the grammar
+    pub rules: BTreeMap<String, usize>,
+    /// Hits of every alternative of the choices, sorted by rule
+    pub alternatives: Vec<AlternativeHits>,
+}
+
+/// Hits of an alternative of a choice
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AlternativeHits {
+    /// Rule that contains the choice
+    pub rule: String,
+    /// Position of the alternative in the rule, starting in 0, the alternatives of a nested choice are numbered after
+    /// the alternative that contains it, e.g. in `(a | (b | c)) ~ (d | e)` the positions are a: 0, b | c: 1, b: 2,
+    /// c: 3, d: 4 and e: 5
+    pub index: usize,
+    /// Expression of the alternative
+    pub alternative: String,
+    pub hits: usize,
+}
+
+impl CoverageReport {
+    /// Parsea los ejemplos con la gramática instrumentada y cuenta los pares de cada regla y alternativa
+    pub(crate) fn new(grammar: &Grammar, rule: &str, examples: &[String]) -> Result<Self, Error> {
+        if !grammar.rules.contains_key(rule) {
+            return Err(Error::UnknownStartRule(rule.to_string()));
+        }
+
+        let (rules, alternative_rules) = instrument(grammar);
+        let mut alternative_rules: Vec<(String, AlternativeHits)> =
+            alternative_rules.into_iter().collect();
+        alternative_rules.sort_by(|(_, a), (_, b)| (&a.rule, a.index).cmp(&(&b.rule, b.index)));
+        // Posición en el reporte de la alternativa de cada regla temporal
+        let positions: HashMap<String, usize> = alternative_rules
+            .iter()
+            .enumerate()
+            .map(|(position, (name, _))| (name.to_string(), position))
+            .collect();
+        let alternatives = alternative_rules
+            .into_iter()
+            .map(|(_, hits)| hits)
+            .collect();
+
+        let mut report = CoverageReport {
+            examples: examples.len(),
+            rejected: 0,
+            rules: grammar
+                .rules
+                .keys()
+                .map(|name| (name.to_string(), 0))
+                .collect(),
+            alternatives,
+        };
+
+        let vm = Vm::new(optimizer::optimize(rules));
+        for example in examples {
+            let pairs = match vm.parse(rule, example) {
+                Ok(pairs) => pairs,
+                Err(_) => {
+                    report.rejected += 1;
+                    continue;
+                }
+            };
+
+            for pair in pairs.flatten() {
+                let name = pair.as_rule();
+                if let Some(position) = positions.get(name) {
+                    report.alternatives[*position].hits += 1;
+                } else if let Some(hits) = report.rules.get_mut(name) {
+                    *hits += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Rules never hit by the examples
+    pub fn unhit_rules(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .filter(|(_, hits)| **hits == 0)
+            .map(|(rule, _)| rule.as_str())
+            .collect()
+    }
+
+    /// Alternatives never hit by the examples
+    pub fn unhit_alternatives(&self) -> Vec<&AlternativeHits> {
+        self.alternatives
+            .iter()
+            .filter(|alternative| alternative.hits == 0)
+            .collect()
+    }
+
+    /// Report in JSON format
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// Text table with the hits of the rules and alternatives, followed by the rules and alternatives never hit
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Examples: {} ({} rejected)",
+            self.examples, self.rejected
+        )?;
+
+        let rule_width = self
+            .rules
+            .keys()
+            .map(|rule| rule.len())
+            .chain(std::iter::once("Rule".len()))
+            .max()
+            .unwrap();
+
+        writeln!(f)?;
+        writeln!(f, "{:<width$}  {:>6}", "Rule", "Hits", width = rule_width)?;
+        for (rule, hits) in &self.rules {
+            writeln!(f, "{:<width$}  {:>6}", rule, hits, width = rule_width)?;
+        }
+
+        if !self.alternatives.is_empty() {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "{:<width$}  {:>3}  {:>6}  Alternative",
+                "Rule",
+                "#",
+                "Hits",
+                width = rule_width
+            )?;
+            for alternative in &self.alternatives {
+                writeln!(
+                    f,
+                    "{:<width$}  {:>3}  {:>6}  {}",
+                    alternative.rule,
+                    alternative.index,
+                    alternative.hits,
+                    alternative.alternative,
+                    width = rule_width
+                )?;
+            }
+        }
+
+        let unhit_rules = self.unhit_rules();
+        if !unhit_rules.is_empty() {
+            write!(f, "\nRules never hit:")?;
+            for rule in unhit_rules {
+                write!(f, "\n  {}", rule)?;
+            }
+            writeln!(f)?;
+        }
+
+        let unhit_alternatives = self.unhit_alternatives();
+        if !unhit_alternatives.is_empty() {
+            write!(f, "\nAlternatives never hit:")?;
+            for alternative in unhit_alternatives {
+                write!(
+                    f,
+                    "\n  {} #{}: {}",
+                    alternative.rule, alternative.index, alternative.alternative
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Crea una copia de la gramática donde cada alternativa de un choice es una regla temporal, así el parser produce
+/// un par por cada alternativa elegida
+///
+/// Las reglas silenciosas se vuelven normales y las atómicas compuestas, de esta forma todas las reglas producen pares
+/// sin cambiar lo que la gramática acepta. Las reglas temporales son normales, heredan la atomicidad de su regla.
+/// Retorna las reglas y las alternativas indexadas por el nombre de su regla temporal
+fn instrument(grammar: &Grammar) -> (Vec<AstRule>, HashMap<String, AlternativeHits>) {
+    let mut rules = vec![];
+    let mut alternatives = HashMap::new();
+
+    for rule in grammar.rules.values() {
+        let ty = match rule.ty {
+            RuleType::Silent => RuleType::Normal,
+            RuleType::Atomic => RuleType::CompoundAtomic,
+            ty => ty,
+        };
+        let mut index = 0;
+        let expr = instrument_expr(
+            &rule.name,
+            &rule.expr,
+            &mut index,
+            &mut rules,
+            &mut alternatives,
+        );
+        rules.push(AstRule {
+            name: rule.name.clone(),
+            ty,
+            expr,
+        });
+    }
+
+    (rules, alternatives)
+}
+
+fn instrument_expr(
+    rule: &str,
+    expr: &Expr,
+    index: &mut usize,
+    rules: &mut Vec<AstRule>,
+    alternatives: &mut HashMap<String, AlternativeHits>,
+) -> Expr {
+    let mut instrument =
+        |expr: &Expr| Box::new(instrument_expr(rule, expr, index, rules, alternatives));
+
+    match expr {
+        Expr::Choice(_, _) => {
+            // `a | b | c` es `(a | b) | c`, las alternativas se recorren de izquierda a derecha
+            let mut list = vec![];
+            let mut aux_expr = expr;
+            while let Expr::Choice(lhs, rhs) = aux_expr {
+                list.push(&**rhs);
+                aux_expr = lhs;
+            }
+            list.push(aux_expr);
+            list.reverse();
+
+            let mut choice: Option<Expr> = None;
+            for alternative in list {
+                let position = *index;
+                *index += 1;
+                let name = format!("{}{}_{}", ALTERNATIVE_RULE, rule, position);
+                let alternative_expr =
+                    instrument_expr(rule, alternative, index, rules, alternatives);
+
+                alternatives.insert(
+                    name.clone(),
+                    AlternativeHits {
+                        rule: rule.to_string(),
+                        index: position,
+                        alternative: alternative.to_string(),
+                        hits: 0,
+                    },
+                );
+                rules.push(AstRule {
+                    name: name.clone(),
+                    ty: RuleType::Normal,
+                    expr: alternative_expr,
+                });
+
+                let ident = Expr::Ident(name);
+                choice = Some(match choice {
+                    Some(lhs) => Expr::Choice(Box::new(lhs), Box::new(ident)),
+                    None => ident,
+                });
+            }
+            choice.unwrap()
+        }
+        Expr::Seq(lhs, rhs) => Expr::Seq(instrument(lhs), instrument(rhs)),
+        Expr::PosPred(inner) => Expr::PosPred(instrument(inner)),
+        Expr::NegPred(inner) => Expr::NegPred(instrument(inner)),
+        Expr::Opt(inner) => Expr::Opt(instrument(inner)),
+        Expr::Rep(inner) => Expr::Rep(instrument(inner)),
+        Expr::RepOnce(inner) => Expr::RepOnce(instrument(inner)),
+        Expr::RepExact(inner, n) => Expr::RepExact(instrument(inner), *n),
+        Expr::RepMin(inner, n) => Expr::RepMin(instrument(inner), *n),
+        Expr::RepMax(inner, n) => Expr::RepMax(instrument(inner), *n),
+        Expr::RepMinMax(inner, min, max) => Expr::RepMinMax(instrument(inner), *min, *max),
+        Expr::Push(inner) => Expr::Push(instrument(inner)),
+        _ => expr.clone(),
+    }
+}
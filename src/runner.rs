@@ -0,0 +1,292 @@
+use crate::error::Error;
+
+use serde_derive::Serialize;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use wait_timeout::ChildExt;
+
+/// Contador usado para que cada ejecución tenga su propio archivo temporal (los comandos se ejecutan en paralelo)
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// Command that receives the examples, e.g. a parser under test
+///
+/// Every `{}` in the arguments is replaced with the path of a temporary file with the example, without `{}` the
+/// example is written to the stdin of the command
+///
+/// ```
+/// use bulk_examples_generator::{Outcome, TargetCommand};
+/// use std::time::Duration;
+///
+/// let command = TargetCommand::new("cat").unwrap().with_timeout(Duration::from_secs(5));
+/// let output = command.run("I like Rust").unwrap();
+/// assert_eq!(Outcome::Success, output.outcome);
+/// assert_eq!(b"I like Rust".to_vec(), output.stdout);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Max time of every execution, the command is killed when it's reached
+    pub timeout: Option<Duration>,
+}
+
+/// Result of running a command with an example
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandOutput {
+    pub outcome: Outcome,
+    /// Output of the command, empty when the command is killed by the timeout
+    pub stdout: Vec<u8>,
+    /// Error output of the command, empty when the command is killed by the timeout
+    pub stderr: Vec<u8>,
+}
+
+/// How a command finished
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The command finished with exit status 0
+    Success,
+    /// The command finished with a nonzero exit status
+    ExitCode(i32),
+    /// The command was terminated by a signal (a crash, e.g. SIGSEGV or SIGABRT), only in Unix
+    Signal(i32),
+    /// The command was killed because it didn't finish before the timeout
+    Timeout,
+}
+
+impl TargetCommand {
+    /// Split `command` in the program and its arguments like a Unix shell: the words are separated by whitespace,
+    /// `'...'` and `"..."` group words and `\` escapes the next char, e.g. `my-parser --name "a b" {}`. `None` if
+    /// `command` is empty or has an unclosed quote
+    ///
+    /// The command isn't run by a shell, so pipes, redirections and variables aren't supported, use
+    /// `sh -c '...'` for them
+    pub fn new(command: &str) -> Option<Self> {
+        let mut words = split_words(command)?.into_iter();
+        Some(TargetCommand {
+            program: words.next()?,
+            args: words.collect(),
+            timeout: None,
+        })
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The command receives the example in a file instead of stdin
+    pub fn uses_file(&self) -> bool {
+        self.args.iter().any(|arg| arg.contains("{}"))
+    }
+
+    /// Run the command with `example`, it returns an error if the command can't be started
+    pub fn run(&self, example: &str) -> Result<CommandOutput, Error> {
+        let path = if self.uses_file() {
+            let path = std::env::temp_dir().join(format!(
+                "bulk-examples-run-{}-{}.txt",
+                std::process::id(),
+                NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::write(&path, example)?;
+            Some(path)
+        } else {
+            None
+        };
+
+        let output = self.run_with(example, path.as_ref());
+        if let Some(path) = &path {
+            let _ = fs::remove_file(path);
+        }
+        output
+    }
+
+    fn run_with(&self, example: &str, path: Option<&PathBuf>) -> Result<CommandOutput, Error> {
+        let args: Vec<String> = match path {
+            Some(path) => self
+                .args
+                .iter()
+                .map(|arg| arg.replace("{}", &path.to_string_lossy()))
+                .collect(),
+            None => self.args.clone(),
+        };
+        let mut child = Command::new(&self.program)
+            .args(&args)
+            .stdin(if path.is_some() {
+                Stdio::null()
+            } else {
+                Stdio::piped()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // La entrada y las salidas se procesan en otros hilos, así el comando no se bloquea con un pipe lleno
+        if let Some(mut stdin) = child.stdin.take() {
+            let example = example.as_bytes().to_vec();
+            // El comando puede terminar sin leer todo el ejemplo
+            thread::spawn(move || stdin.write_all(&example));
+        }
+        let stdout = read_in_thread(child.stdout.take());
+        let stderr = read_in_thread(child.stderr.take());
+
+        let status = match self.timeout {
+            Some(timeout) => child.wait_timeout(timeout)?,
+            None => Some(child.wait()?),
+        };
+        match status {
+            Some(status) => Ok(CommandOutput {
+                outcome: Outcome::from(status),
+                stdout: stdout.join().unwrap_or_default(),
+                stderr: stderr.join().unwrap_or_default(),
+            }),
+            None => {
+                // Los procesos hijos del comando pueden mantener abiertas las salidas, por eso no se espera a los
+                // hilos de lectura
+                let _ = child.kill();
+                let _ = child.wait();
+                Ok(CommandOutput {
+                    outcome: Outcome::Timeout,
+                    stdout: vec![],
+                    stderr: vec![],
+                })
+            }
+        }
+    }
+}
+
+/// Separa `command` en palabras con las reglas de comillas y escapes de sh, None si una comilla no se cierra
+///
+/// Dentro de comillas dobles `\` solo escapa `"`, `\`, `$` y `` ` ``, dentro de comillas simples no escapa nada
+fn split_words(command: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    // Una palabra puede estar vacía si viene de comillas, e.g. ""
+    let mut in_word = false;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            '\n' => {}
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // Un salto de línea escapado une las líneas
+                Some('\n') => {}
+                Some(c) => {
+                    in_word = true;
+                    word.push(c);
+                }
+                None => {
+                    in_word = true;
+                    word.push('\\');
+                }
+            },
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Some(words)
+}
+
+/// Lee una salida del comando hasta el final
+fn read_in_thread<R: Read + Send + 'static>(output: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut output) = output {
+            let _ = output.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+impl CommandOutput {
+    /// The outputs of two commands differ when they finish in a different way or print a different stdout, used for
+    /// differential testing
+    pub fn differs_from(&self, other: &CommandOutput) -> bool {
+        self.outcome != other.outcome || self.stdout != other.stdout
+    }
+}
+
+impl Outcome {
+    /// Every outcome except `Success`
+    pub fn is_failure(&self) -> bool {
+        *self != Outcome::Success
+    }
+}
+
+impl From<ExitStatus> for Outcome {
+    fn from(status: ExitStatus) -> Self {
+        if status.success() {
+            return Outcome::Success;
+        }
+        match status.code() {
+            Some(code) => Outcome::ExitCode(code),
+            None => signal(status),
+        }
+    }
+}
+
+/// Sin código de salida el proceso terminó por una señal
+#[cfg(unix)]
+fn signal(status: ExitStatus) -> Outcome {
+    use std::os::unix::process::ExitStatusExt;
+    Outcome::Signal(status.signal().unwrap_or_default())
+}
+
+#[cfg(not(unix))]
+fn signal(_status: ExitStatus) -> Outcome {
+    Outcome::ExitCode(-1)
+}
+
+/// e.g. `exit code 1` or `crash (signal 11)`
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Success => write!(f, "success"),
+            Outcome::ExitCode(code) => write!(f, "exit code {}", code),
+            Outcome::Signal(signal) => write!(f, "crash (signal {})", signal),
+            Outcome::Timeout => write!(f, "timeout"),
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use crate::config::{Compression, ExecutorConfig};
+use crate::error::{Error, GenerationError};
+use crate::generator::Generator;
+use crate::output::{append_extension, compressed_path, is_tar, Encoder, ExampleWriter};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Destination of the examples generated by `generate_examples_with_sinks`, e.g. a queue or a database
+///
+/// `on_example` is called once for every example with its number (starting in 1) and `finish` once after the last
+/// example. In parallel mode the examples arrive in any order, but a sink receives one example at a time
+///
+/// ```
+/// use bulk_examples_generator::config::*;
+/// use bulk_examples_generator::{generate_examples_with_sinks, Error, ExampleSink, GenerationError};
+///
+/// #[derive(Default)]
+/// struct LengthSink {
+///     total: usize,
+/// }
+///
+/// impl ExampleSink for LengthSink {
+///     fn on_example(&mut self, _index: u32, example: &Result<String, GenerationError>) -> Result<(), Error> {
+///         if let Ok(example) = example {
+///             self.total += example.len();
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go"}
+///         sentence = {"I like " ~ language}
+///     "#;
+///
+/// let mut exe_config: ExecutorConfig = Default::default();
+/// exe_config.print_stdout = false;
+///
+/// let mut sink = LengthSink::default();
+/// generate_examples_with_sinks(
+///             grammar.to_string(),
+///             5,
+///             "sentence".to_string(),
+///             &Default::default(),
+///             &exe_config,
+///             vec![Box::new(&mut sink)],
+///         ).unwrap();
+///
+/// assert!(sink.total >= 5 * "I like Go".len());
+/// ```
+pub trait ExampleSink {
+    /// Receive the example number `index`, or the error that prevented its generation
+    fn on_example(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+    ) -> Result<(), Error>;
+
+    /// Called instead of `on_example` when `rule_hits` is enabled in `ExecutorConfig`, with how many times every
+    /// rule was expanded in the example
+    fn on_example_with_hits(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+        rule_hits: &BTreeMap<String, usize>,
+    ) -> Result<(), Error> {
+        let _ = rule_hits;
+        self.on_example(index, example)
+    }
+
+    /// Called after the last example, to flush buffers or close connections
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Así se puede pasar un sink prestado y usarlo después de la generación
+impl<S: ExampleSink + ?Sized> ExampleSink for &mut S {
+    fn on_example(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+    ) -> Result<(), Error> {
+        (**self).on_example(index, example)
+    }
+
+    fn on_example_with_hits(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+        rule_hits: &BTreeMap<String, usize>,
+    ) -> Result<(), Error> {
+        (**self).on_example_with_hits(index, example, rule_hits)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        (**self).finish()
+    }
+}
+
+/// Print every example in stdout after "Example #n generated:", and the errors in stderr (`print_progress_text`
+/// in `ExecutorConfig`)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextSink;
+
+impl ExampleSink for TextSink {
+    fn on_example(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+    ) -> Result<(), Error> {
+        match example {
+            Ok(example) => println!("Example #{} generated:\r\n{}", index, example),
+            Err(error) => eprintln!("Example #{} failed:\r\n{}", index, error),
+        }
+        Ok(())
+    }
+}
+
+/// Write every example in stdout like `print_stdout` in `ExecutorConfig`, with its `output_format`, `separator`,
+/// `ordered_output` and `rule_hits`
+pub struct StdoutSink(ExampleWriter);
+
+impl StdoutSink {
+    /// The CSV header is written here, the CSV columns of the rule hits are the rules of `generator`
+    pub fn new(
+        generator: &Generator,
+        start: &str,
+        executor_config: &ExecutorConfig,
+    ) -> Result<Self, Error> {
+        Ok(StdoutSink(ExampleWriter::new(
+            generator,
+            start,
+            executor_config,
+            true,
+            None,
+        )?))
+    }
+}
+
+impl ExampleSink for StdoutSink {
+    fn on_example(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+    ) -> Result<(), Error> {
+        self.0.on_example(index, example)
+    }
+
+    fn on_example_with_hits(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+        rule_hits: &BTreeMap<String, usize>,
+    ) -> Result<(), Error> {
+        self.0.on_example_with_hits(index, example, rule_hits)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.0.finish()
+    }
+}
+
+/// Write all the examples in the file `path` like `print_file` in `ExecutorConfig`, with its `output_format`,
+/// `separator`, `ordered_output`, `rule_hits`, `sharding` and `compression`
+///
+/// The file isn't complete until `finish` is called
+pub struct FileSink(ExampleWriter);
+
+impl FileSink {
+    /// The file (or the first shard) is created here, the CSV columns of the rule hits are the rules of `generator`
+    pub fn new(
+        generator: &Generator,
+        start: &str,
+        path: &Path,
+        executor_config: &ExecutorConfig,
+    ) -> Result<Self, Error> {
+        Ok(FileSink(ExampleWriter::new(
+            generator,
+            start,
+            executor_config,
+            false,
+            Some(path),
+        )?))
+    }
+}
+
+impl ExampleSink for FileSink {
+    fn on_example(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+    ) -> Result<(), Error> {
+        self.0.on_example(index, example)
+    }
+
+    fn on_example_with_hits(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+        rule_hits: &BTreeMap<String, usize>,
+    ) -> Result<(), Error> {
+        self.0.on_example_with_hits(index, example, rule_hits)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.0.finish()
+    }
+}
+
+/// Show a progress bar in stderr that advances with every example (`print_progress_bar` in `ExecutorConfig`)
+pub struct ProgressBarSink {
+    progress_bar: ProgressBar,
+}
+
+impl ProgressBarSink {
+    /// Progress bar of `quantity` examples, it's painted here
+    pub fn new(quantity: u64) -> Self {
+        let progress_bar = ProgressBar::new(quantity);
+        progress_bar.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "[{elapsed_precise}] {wide_bar} {pos:>3}/{len:3} {msg} {percent}% {eta_precise}",
+                )
+                .progress_chars("██░"),
+        );
+
+        // Force the initial paint
+        progress_bar.tick();
+        ProgressBarSink { progress_bar }
+    }
+}
+
+impl ExampleSink for ProgressBarSink {
+    fn on_example(
+        &mut self,
+        _index: u32,
+        _example: &Result<String, GenerationError>,
+    ) -> Result<(), Error> {
+        self.progress_bar.inc(1);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.progress_bar.finish();
+        Ok(())
+    }
+}
+
+/// Collect the examples in memory (`return_vec` in `ExecutorConfig`)
+#[derive(Clone, Debug, Default)]
+pub struct VecSink {
+    examples: Vec<(u32, Result<String, GenerationError>)>,
+}
+
+impl VecSink {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The examples received, in order of their number
+    pub fn into_examples(mut self) -> Vec<Result<String, GenerationError>> {
+        self.examples.sort_unstable_by_key(|(index, _)| *index);
+        self.examples
+            .into_iter()
+            .map(|(_, example)| example)
+            .collect()
+    }
+}
+
+impl ExampleSink for VecSink {
+    fn on_example(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+    ) -> Result<(), Error> {
+        self.examples.push((index, example.clone()));
+        Ok(())
+    }
+}
+
+/// Save every example in its own file of a folder, or in one tar archive (`print_folder` in `ExecutorConfig`)
+///
+/// The errors of the examples that couldn't be generated are printed in stderr instead
+pub struct FolderSink {
+    /// Nombre de los archivos, {} es el número del ejemplo
+    template: String,
+    target: FolderTarget,
+}
+
+/// Carpeta con un archivo por ejemplo o archivo tar, el tar se quita al terminarlo
+enum FolderTarget {
+    Files {
+        folder: PathBuf,
+        compression: Option<Compression>,
+    },
+    Tar(Option<tar::Builder<Encoder<BufWriter<File>>>>),
+}
+
+impl FolderSink {
+    /// Files named `template` (`{}` is replaced with the number of the example) in the folder `path`, compressed
+    /// like in `ExecutorConfig::compression`. With `tar` (or a `path` ending in `.tar`, `.tar.gz`, `.tgz` or
+    /// `.tar.zst`) the files are written in the tar archive `path` instead, see `ExecutorConfig::tar_folder`
+    pub fn new(
+        template: &str,
+        path: &Path,
+        compression: Option<Compression>,
+        tar: bool,
+    ) -> Result<Self, Error> {
+        let target = if tar || is_tar(path) {
+            let path = if is_tar(path) {
+                path.to_path_buf()
+            } else {
+                append_extension(path, "tar")
+            };
+            let (path, compression) = compressed_path(&path, compression);
+            let encoder = Encoder::new(BufWriter::new(File::create(path)?), compression)?;
+            FolderTarget::Tar(Some(tar::Builder::new(encoder)))
+        } else {
+            FolderTarget::Files {
+                folder: path.to_path_buf(),
+                compression,
+            }
+        };
+        Ok(FolderSink {
+            template: template.to_string(),
+            target,
+        })
+    }
+}
+
+impl ExampleSink for FolderSink {
+    fn on_example(
+        &mut self,
+        index: u32,
+        example: &Result<String, GenerationError>,
+    ) -> Result<(), Error> {
+        let example = match example {
+            Ok(example) => example,
+            Err(error) => {
+                eprintln!("{}", error);
+                return Ok(());
+            }
+        };
+        let name = self.template.replace("{}", &index.to_string());
+        match &mut self.target {
+            FolderTarget::Files {
+                folder,
+                compression,
+            } => {
+                let (path, compression) = compressed_path(&folder.join(name), *compression);
+                let mut writer = Encoder::new(BufWriter::new(File::create(path)?), compression)?;
+                writer.write_all(example.as_bytes())?;
+                writer.finish()?;
+            }
+            FolderTarget::Tar(Some(builder)) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(example.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|time| time.as_secs())
+                        .unwrap_or(0),
+                );
+                builder.append_data(&mut header, name, example.as_bytes())?;
+            }
+            // El tar ya se terminó
+            FolderTarget::Tar(None) => {}
+        }
+        Ok(())
+    }
+
+    /// Termina el tar (los archivos sueltos ya están completos)
+    fn finish(&mut self) -> Result<(), Error> {
+        if let FolderTarget::Tar(builder) = &mut self.target {
+            if let Some(builder) = builder.take() {
+                builder.into_inner()?.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
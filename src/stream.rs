@@ -0,0 +1,145 @@
+use crate::config::GeneratorConfig;
+use crate::error::{Error, GenerationError};
+use crate::generator::Generator;
+
+use rayon::prelude::*;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+
+/// Lazy and unbounded sequence of examples, every example is generated when `next` is called
+///
+/// The examples are enumerated from 1 like in `generate_examples`, then with the same seed the example #n of the
+/// stream is the same example #n returned by `generate_examples`
+///
+/// ```
+/// use bulk_examples_generator::config::*;
+/// use bulk_examples_generator::ExampleStream;
+///
+/// let grammar = r#"
+///         language = {"Rust" | "Python" | "Go" | "Java" | "PHP" | "Haskell"}
+///         sentence = {"I like " ~ language}
+///     "#;
+///
+/// let stream = ExampleStream::new(
+///             grammar.to_string(),
+///             "sentence".to_string(),
+///             Default::default(),
+///         ).unwrap();
+///
+/// for example in stream.take(5) {
+///     println!("{}", example.unwrap());
+/// }
+/// ```
+pub struct ExampleStream {
+    generator: Generator,
+    start: String,
+    /// Number of the next example
+    index: u64,
+}
+
+impl ExampleStream {
+    /// Compile the grammar and create a stream of examples that begins in the start rule, `Error::UnknownStartRule`
+    /// is returned if the grammar doesn't have the start rule
+    pub fn new(
+        grammar_string: String,
+        start: String,
+        config: GeneratorConfig,
+    ) -> Result<Self, Error> {
+        Ok(ExampleStream::from_generator(
+            stream_generator(grammar_string, &start, config)?,
+            start,
+        ))
+    }
+
+    pub(crate) fn from_generator(generator: Generator, start: String) -> Self {
+        ExampleStream {
+            generator,
+            start,
+            index: 1,
+        }
+    }
+
+    /// Create a stream that generates the examples in parallel with rayon
+    ///
+    /// The examples are generated in batches of `buffer_size` elements and sent through a channel with the same
+    /// capacity, so no more than `2 * buffer_size` examples are kept in memory. The order of the examples is
+    /// the same of the sequential stream. Like in `new`, the grammar must have the start rule
+    pub fn parallel(
+        grammar_string: String,
+        start: String,
+        config: GeneratorConfig,
+        buffer_size: usize,
+    ) -> Result<ParallelExampleStream, Error> {
+        Ok(ParallelExampleStream::from_generator(
+            stream_generator(grammar_string, &start, config)?,
+            start,
+            buffer_size,
+        ))
+    }
+}
+
+// Compila la gramática y revisa que tenga la regla inicial, sin ella el stream solo produciría errores
+fn stream_generator(
+    grammar_string: String,
+    start: &str,
+    config: GeneratorConfig,
+) -> Result<Generator, Error> {
+    let generator = Generator::new(grammar_string)?.with_config(config);
+    if !generator.grammar().rules.contains_key(start) {
+        return Err(Error::UnknownStartRule(start.to_string()));
+    }
+    Ok(generator)
+}
+
+impl Iterator for ExampleStream {
+    type Item = Result<String, GenerationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let example = self.generator.generate_nth(&self.start, self.index);
+        self.index += 1;
+        Some(example)
+    }
+}
+
+/// Unbounded sequence of examples generated in parallel by a background thread
+///
+/// The background thread stops when the stream is dropped
+pub struct ParallelExampleStream {
+    receiver: Receiver<Result<String, GenerationError>>,
+}
+
+impl ParallelExampleStream {
+    pub(crate) fn from_generator(generator: Generator, start: String, buffer_size: usize) -> Self {
+        let buffer_size = buffer_size.max(1);
+        let (sender, receiver) = sync_channel(buffer_size);
+
+        thread::spawn(move || {
+            let mut first = 1;
+            loop {
+                let last = first + buffer_size as u64;
+                let batch: Vec<_> = (first..last)
+                    .into_par_iter()
+                    .map(|i| generator.generate_nth(&start, i))
+                    .collect();
+                first = last;
+
+                for example in batch {
+                    // The receiver was dropped, nobody wants more examples
+                    if sender.send(example).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        ParallelExampleStream { receiver }
+    }
+}
+
+impl Iterator for ParallelExampleStream {
+    type Item = Result<String, GenerationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
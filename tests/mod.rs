@@ -6,6 +6,7 @@ macro_rules! boilerplate_test_grammar {
         let gen_config: GeneratorConfig = Default::default();
         let mut exe_config: ExecutorConfig = Default::default();
         exe_config.print_stdout = false;
+        exe_config.return_vec = true;
 
         let grammar_string = $grammar_string.to_string();
 
@@ -20,7 +21,8 @@ macro_rules! boilerplate_test_grammar {
             $initial_rule.to_string(),
             &gen_config,
             &exe_config,
-        );
+        )
+        .unwrap();
 
         // println!("{:?}", results);
 
@@ -31,7 +33,7 @@ macro_rules! boilerplate_test_grammar {
                 result.clone().unwrap(),
             );
             // println!("{}", result.unwrap());
-            assert_eq!(Ok(()), parsing);
+            assert!(parsing.is_ok(), "{}", parsing.unwrap_err());
         }
     };
 }
@@ -72,6 +74,1744 @@ mod test {
         );
     }
 
+    /// Con la misma semilla el ejemplo #n debe ser idéntico en modo paralelo y secuencial
+    #[test]
+    fn seed_reproducible_examples() {
+        let grammar = r#"
+            language = {"Rust" | "Python" | "Go" | "Java" | "PHP" | "Haskell"}
+            one = {"1"}
+            daysNumber = {one ~ " day" | !one ~ ASCII_NONZERO_DIGIT ~ " days"}
+            sentence = {"I have been programming in " ~ language ~ " for " ~ daysNumber ~ "."}
+        "#;
+
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(42);
+        let mut exe_config = ExecutorConfig {
+            print_stdout: false,
+            return_vec: true,
+            ..Default::default()
+        };
+
+        let parallel = generate_examples(
+            grammar.to_string(),
+            100,
+            "sentence".to_string(),
+            &gen_config,
+            &exe_config,
+        )
+        .unwrap();
+
+        exe_config.parallel_mode = false;
+        let sequential = generate_examples(
+            grammar.to_string(),
+            100,
+            "sentence".to_string(),
+            &gen_config,
+            &exe_config,
+        )
+        .unwrap();
+
+        assert_eq!(parallel, sequential);
+
+        // Una semilla diferente debe producir otros ejemplos
+        let mut other_config: GeneratorConfig = Default::default();
+        other_config.seed = Some(43);
+        let other = generate_examples(
+            grammar.to_string(),
+            100,
+            "sentence".to_string(),
+            &other_config,
+            &exe_config,
+        )
+        .unwrap();
+        assert_ne!(sequential, other);
+    }
+
+    /// El stream debe producir los mismos ejemplos que `generate_examples` con la misma semilla
+    #[test]
+    fn stream_matches_generate_examples() {
+        let grammar = r#"
+            language = {"Rust" | "Python" | "Go" | "Java" | "PHP" | "Haskell"}
+            one = {"1"}
+            daysNumber = {one ~ " day" | !one ~ ASCII_NONZERO_DIGIT ~ " days"}
+            sentence = {"I have been programming in " ~ language ~ " for " ~ daysNumber ~ "."}
+        "#;
+
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(7);
+        let exe_config = ExecutorConfig {
+            print_stdout: false,
+            return_vec: true,
+            ..Default::default()
+        };
+
+        let expected: Vec<String> = generate_examples(
+            grammar.to_string(),
+            50,
+            "sentence".to_string(),
+            &gen_config,
+            &exe_config,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|r| r.unwrap())
+        .collect();
+
+        let sequential: Vec<String> = ExampleStream::new(
+            grammar.to_string(),
+            "sentence".to_string(),
+            gen_config.clone(),
+        )
+        .unwrap()
+        .take(50)
+        .map(|r| r.unwrap())
+        .collect();
+
+        let parallel: Vec<String> =
+            ExampleStream::parallel(grammar.to_string(), "sentence".to_string(), gen_config, 8)
+                .unwrap()
+                .take(50)
+                .map(|r| r.unwrap())
+                .collect();
+
+        assert_eq!(expected, sequential);
+        assert_eq!(expected, parallel);
+    }
+
+    /// Las alternativas se eligen de forma proporcional a su peso
+    #[test]
+    fn weighted_choices() {
+        let grammar = r#"
+            keyword = { "|WEIGHT|20|" ~ "if" | "else" | "|WEIGHT|0|" ~ "never" }
+            boolean = { "true" | "false" }
+        "#;
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(7);
+        let generator = Generator::new(grammar.to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+
+        // Los marcadores no hacen parte de los ejemplos, se parsean con la gramática limpia
+        for example in generator.generate_n("keyword", 100) {
+            assert!(generator.parse("keyword", &example.unwrap()).is_ok());
+        }
+
+        let count = |generator: &Generator, rule: &str, text: &str| {
+            generator
+                .generate_n(rule, 500)
+                .into_iter()
+                .filter(|example| example.as_ref().unwrap() == text)
+                .count()
+        };
+        assert!(count(&generator, "keyword", "if") > 5 * count(&generator, "keyword", "else"));
+        assert_eq!(0, count(&generator, "keyword", "never"));
+
+        // Pesos en la configuración
+        gen_config.weights.insert("boolean".to_string(), vec![0, 1]);
+        let generator = generator.with_config(gen_config.clone());
+        assert_eq!(500, count(&generator, "boolean", "false"));
+
+        // Pesos en un archivo TOML, reemplazan los de la configuración
+        let weights_file = std::env::temp_dir().join("bulk_examples_generator_weights.toml");
+        std::fs::write(&weights_file, "boolean = [1, 0]\n").unwrap();
+        gen_config
+            .load_weights(weights_file.to_str().unwrap())
+            .unwrap();
+        std::fs::remove_file(&weights_file).unwrap();
+        assert_eq!(vec![1, 0], gen_config.weights["boolean"]);
+        let generator = generator.with_config(gen_config.clone());
+        assert_eq!(500, count(&generator, "boolean", "true"));
+
+        // Una configuración sin pesos vuelve a la gramática original, no conserva los pesos anteriores
+        gen_config.weights.clear();
+        let generator = generator.with_config(gen_config);
+        assert!(count(&generator, "boolean", "false") > 0);
+        assert!(count(&generator, "keyword", "if") > 5 * count(&generator, "keyword", "else"));
+    }
+
+    #[test]
+    fn coverage_guided() {
+        let grammar = r#"
+            keyword = { "|WEIGHT|1000|" ~ "if" | "else" | "for" | "while" | "loop" | "match" }
+            digits = { ASCII_DIGIT{1, 4} ~ "x"? }
+            sentence = { keyword ~ " " ~ digits }
+        "#;
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(3);
+        gen_config.coverage_guided = true;
+        let generator = Generator::new(grammar.to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+
+        // Las alternativas raras se generan a pesar del peso de "if"
+        let coverage = generator.coverage().unwrap();
+        assert_eq!(6, coverage.alternatives_total());
+        assert!(!coverage.is_complete());
+        for example in generator.generate_n("sentence", 100) {
+            assert!(generator.parse("sentence", &example.unwrap()).is_ok());
+        }
+        let coverage = generator.coverage().unwrap();
+        assert!(coverage.is_complete());
+        assert_eq!(100.0, coverage.alternatives_percentage());
+        // {1, 4} y ? tienen dos límites cada uno
+        assert_eq!(4, coverage.repetitions_total());
+        assert!(coverage.uncovered_repetitions().is_empty());
+
+        // Sin modo cobertura no hay cobertura
+        assert!(Generator::new(grammar.to_string())
+            .unwrap()
+            .coverage()
+            .is_none());
+
+        // La generación se detiene al alcanzar la cobertura completa
+        let exe_config = ExecutorConfig {
+            print_stdout: false,
+            return_vec: true,
+            parallel_mode: false,
+            stop_on_full_coverage: true,
+            ..Default::default()
+        };
+        let results = generate_examples(
+            grammar.to_string(),
+            1000,
+            "keyword".to_string(),
+            &gen_config,
+            &exe_config,
+        )
+        .unwrap();
+        assert!(results.len() >= 6 && results.len() < 1000);
+
+        // Con semilla los ejemplos son los mismos en paralelo, y la cobertura final se retorna
+        let exe_config = ExecutorConfig {
+            print_stdout: false,
+            ..Default::default()
+        };
+        let generate = || {
+            let mut sink = VecSink::new();
+            let coverage = generate_examples_with_sinks(
+                grammar.to_string(),
+                100,
+                "sentence".to_string(),
+                &gen_config,
+                &exe_config,
+                vec![Box::new(&mut sink)],
+            )
+            .unwrap();
+            (sink.into_examples(), coverage.unwrap())
+        };
+        let (examples, coverage) = generate();
+        assert_eq!(examples, generate().0);
+        assert!(coverage.is_complete());
+        let generator = Generator::new(grammar.to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+        assert_eq!(generator.generate_n("sentence", 100), generate().0);
+    }
+
+    #[test]
+    fn corpus_coverage_report() {
+        let grammar = r#"
+            language = @{ "Rust" | "Go" | "Ja" ~ ("va" | "nus") }
+            _sp = { " " }
+            sentence = { "I like" ~ _sp ~ language ~ unused? }
+            unused = { "!" }
+        "#;
+        let examples: Vec<String> = ["I like Rust", "I like Java", "I like Java", "I like C"]
+            .iter()
+            .map(|example| example.to_string())
+            .collect();
+
+        let report = coverage_report(
+            compile_grammar(grammar.to_string()).unwrap(),
+            "sentence".to_string(),
+            &examples,
+        )
+        .unwrap();
+
+        assert_eq!(4, report.examples);
+        assert_eq!(1, report.rejected);
+        // Las reglas silenciosas también se cuentan
+        assert_eq!(3, report.rules["_sp"]);
+        assert_eq!(3, report.rules["language"]);
+        assert_eq!(vec!["unused"], report.unhit_rules());
+
+        // Las alternativas de una regla atómica, incluidas las de choices anidados
+        let hits: Vec<(usize, usize)> = report
+            .alternatives
+            .iter()
+            .map(|alternative| (alternative.index, alternative.hits))
+            .collect();
+        assert_eq!(vec![(0, 1), (1, 0), (2, 2), (3, 2), (4, 0)], hits);
+        let unhit: Vec<&str> = report
+            .unhit_alternatives()
+            .iter()
+            .map(|alternative| alternative.alternative.as_str())
+            .collect();
+        assert_eq!(vec!["\"Go\"", "\"nus\""], unhit);
+
+        assert!(report.to_json().contains("\"rejected\": 1"));
+        assert!(report.to_string().contains("Alternatives never hit:"));
+
+        assert!(matches!(
+            coverage_report(
+                compile_grammar(grammar.to_string()).unwrap(),
+                "nope".to_string(),
+                &examples
+            ),
+            Err(Error::UnknownStartRule(_))
+        ));
+    }
+
+    /// La enumeración produce todos los ejemplos hasta una profundidad, del más corto al más largo
+    #[test]
+    fn exhaustive_enumeration() {
+        let grammar = r#"
+            expr = { "x" | "(" ~ expr ~ ")" | tag }
+            tag = { PUSH("a" | "b") ~ "-" ~ POP }
+            word = { !"if" ~ ('f'..'i'){2} }
+            list = { "[" ~ ^"n"* ~ "]" }
+        "#;
+        let generator = Generator::new(grammar.to_string()).unwrap();
+
+        let examples: Vec<String> = generator.enumerate("expr", 3).unwrap().collect();
+        assert_eq!(
+            vec!["x", "(x)", "a-a", "b-b", "((x))", "(a-a)", "(b-b)"],
+            examples
+        );
+
+        // La profundidad cuenta la regla inicial
+        assert_eq!(0, generator.enumerate("expr", 0).unwrap().count());
+        assert_eq!(
+            vec!["x", "(x)"],
+            generator
+                .enumerate("expr", 2)
+                .unwrap()
+                .take(2)
+                .collect::<Vec<_>>()
+        );
+
+        let words: Vec<String> = generator.enumerate("word", 1).unwrap().collect();
+        assert_eq!(15, words.len());
+        assert!(!words.contains(&"if".to_string()));
+
+        // Las repeticiones se limitan con max_enumeration_repetitions
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.max_enumeration_repetitions = 1;
+        let lists: Vec<String> =
+            enumerate_examples(grammar.to_string(), "list".to_string(), 1, &gen_config)
+                .unwrap()
+                .collect();
+        assert_eq!(vec!["[]", "[N]", "[n]"], lists);
+
+        // Los ejemplos se derivan por tamaño, los primeros no requieren derivar los 52^8 ejemplos más largos
+        gen_config.max_enumeration_repetitions = 8;
+        let letters = Generator::new("letters = { ASCII_ALPHA* }".to_string())
+            .unwrap()
+            .with_config(gen_config);
+        let first: Vec<String> = letters.enumerate("letters", 1).unwrap().take(3).collect();
+        assert_eq!(vec!["", "A", "B"], first);
+
+        for example in generator.enumerate("expr", 5).unwrap() {
+            assert!(generator.parse("expr", &example).is_ok());
+        }
+
+        assert!(matches!(
+            generator.enumerate("nope", 3),
+            Err(Error::UnknownStartRule(_))
+        ));
+    }
+
+    /// Al alcanzar un límite las reglas abiertas se cierran con su derivación más corta y los ejemplos se parsean
+    #[test]
+    fn shortest_completion_on_limit() {
+        let grammar = r#"
+            expr = { term ~ ("+" ~ term)* }
+            term = { "(" ~ expr ~ ")" | "[" ~ expr ~ "]" | number }
+            number = { ASCII_DIGIT{2} }
+            tag = { PUSH("a" | "bc") ~ "-" ~ POP }
+            loop = { "x" ~ loop }
+        "#;
+        let generator = Generator::new(grammar.to_string()).unwrap();
+
+        let derivations = generator.minimal_derivations();
+        assert_eq!(3, derivations["expr"].depth);
+        assert_eq!("00", derivations["expr"].shortest);
+        assert_eq!(2, derivations["term"].depth);
+        assert_eq!("a-a", derivations["tag"].shortest);
+        assert!(!derivations.contains_key("loop"));
+
+        for (hard_limit, limit_depth_level) in [(5, 100), (1000, 3), (30, 10)] {
+            let mut gen_config: GeneratorConfig = Default::default();
+            gen_config.hard_limit = hard_limit;
+            gen_config.limit_depth_level = limit_depth_level;
+            gen_config.upper_bound_zero_or_more_repetition = 10;
+            let generator = Generator::new(grammar.to_string())
+                .unwrap()
+                .with_config(gen_config);
+            for example in generator.generate_n("expr", 50) {
+                let example = example.unwrap();
+                assert!(generator.parse("expr", &example).is_ok(), "{}", example);
+            }
+            for example in generator.generate_n("tag", 10) {
+                assert!(generator.parse("tag", &example.unwrap()).is_ok());
+            }
+        }
+
+        // Sin la opción se usa text_expand_limit
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.hard_limit = 0;
+        gen_config.shortest_completion_on_limit = false;
+        gen_config.text_expand_limit = "LIMIT".to_string();
+        let generator = Generator::new("a = { \"a\"{20} }".to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+        assert_eq!(Ok("LIMIT".to_string()), generator.generate("a"));
+
+        gen_config.shortest_completion_on_limit = true;
+        let generator = generator.with_config(gen_config);
+        assert_eq!(Ok("a".repeat(20)), generator.generate("a"));
+    }
+
+    /// El análisis encuentra reglas inalcanzables, sin derivación finita, con tamaño esperado infinito y predicados
+    /// que el generador no cumple
+    #[test]
+    fn grammar_analysis() {
+        let grammar = r#"
+            WHITESPACE = _{ " " }
+            expr = { term ~ ("+" ~ term)* }
+            term = { "(" ~ expr ~ ")" | number }
+            number = @{ ASCII_DIGIT+ }
+            loop = { "x" ~ loop }
+            uses_loop = { "a" | loop }
+            unused = { "u" }
+            keyword = { "if" ~ !ASCII_ALPHA }
+            tag = { PUSH("a") ~ !PEEK ~ "b" }
+            word = { !("if" | "else") ~ ASCII_ALPHA+ }
+            start = { (expr | uses_loop) ~ keyword ~ tag ~ word }
+        "#;
+        let generator = Generator::new(grammar.to_string()).unwrap();
+
+        let analysis = generator.analyze("start").unwrap();
+        assert_eq!(vec!["unused"], analysis.unreachable_rules);
+        assert_eq!(vec!["loop"], analysis.non_productive_rules);
+        // Con 2 repeticiones en promedio cada expr usa 3 term y cada term 0.5 expr
+        assert_eq!(
+            vec!["expr", "loop", "start", "term", "uses_loop"],
+            analysis.unbounded_rules
+        );
+        let alternatives: Vec<(&str, usize)> = analysis
+            .unbounded_alternatives
+            .iter()
+            .map(|alternative| (alternative.rule.as_str(), alternative.index))
+            .collect();
+        assert_eq!(
+            vec![("start", 0), ("start", 1), ("term", 0), ("uses_loop", 1)],
+            alternatives
+        );
+        let predicates: Vec<(&str, PredicateIssue)> = analysis
+            .unsupported_predicates
+            .iter()
+            .map(|predicate| (predicate.rule.as_str(), predicate.reason))
+            .collect();
+        assert_eq!(
+            vec![
+                ("keyword", PredicateIssue::NotLeadingSequence),
+                ("tag", PredicateIssue::NotLeadingSequence),
+            ],
+            predicates
+        );
+        assert!(!analysis.is_clean());
+        assert!(analysis.to_json().contains("\"not_leading_sequence\""));
+
+        // Con menos repeticiones el tamaño esperado es finito
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.upper_bound_zero_or_more_repetition = 2;
+        let analysis = generator.with_config(gen_config).analyze("expr").unwrap();
+        assert!(analysis.unbounded_rules.is_empty(), "{}", analysis);
+        assert!(analysis.unbounded_alternatives.is_empty());
+
+        let compiled = compile_grammar(grammar.to_string()).unwrap();
+        assert_eq!(
+            vec!["loop"],
+            analyze_grammar(&compiled, "uses_loop")
+                .unwrap()
+                .non_productive_rules
+        );
+        assert!(matches!(
+            analyze_grammar(&compiled, "nope"),
+            Err(Error::UnknownStartRule(_))
+        ));
+    }
+
+    /// Los ejemplos inválidos no se pueden parsear y están etiquetados con su mutación
+    #[test]
+    fn invalid_examples() {
+        let grammar = r#"
+            language = { "Rust" | "Python" | "Go" }
+            digit = { '0'..'9' }
+            code = { "[" ~ digit{3} ~ "]" }
+            sentence = { "I like " ~ language ~ " " ~ code }
+        "#;
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(11);
+        let generator = Generator::new(grammar.to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+
+        let examples: Vec<InvalidExample> = generator
+            .generate_invalid_n("sentence", 200)
+            .into_iter()
+            .map(|example| example.unwrap())
+            .collect();
+        for example in &examples {
+            assert!(
+                generator.parse("sentence", &example.text).is_err(),
+                "{}",
+                example.text
+            );
+        }
+        for kind in &[
+            MutationKind::DropToken,
+            MutationKind::SwapTerminal,
+            MutationKind::BreakRange,
+            MutationKind::BreakRepetition,
+        ] {
+            assert!(examples
+                .iter()
+                .any(|example| example.mutation.kind == *kind));
+        }
+        // Más de 3 dígitos son aceptados por `digit{3}` pero no por el "]" que le sigue
+        assert!(examples.iter().any(|example| {
+            example.mutation.kind == MutationKind::BreakRepetition
+                && example.mutation.replacement.contains("{4}")
+        }));
+
+        // Con la misma semilla se obtienen los mismos ejemplos
+        let again =
+            generate_invalid_examples(grammar.to_string(), 20, "sentence".to_string(), &gen_config)
+                .unwrap()
+                .into_iter()
+                .map(|example| example.unwrap())
+                .collect::<Vec<_>>();
+        assert_eq!(examples[..20].to_vec(), again);
+
+        // Una gramática que acepta todo no tiene ejemplos inválidos
+        let generator = Generator::new("any = { ANY* }".to_string()).unwrap();
+        assert_eq!(
+            Err(GenerationError::InvalidAttemptsExhausted("any".to_string())),
+            generator.generate_invalid("any")
+        );
+    }
+
+    /// Pares de pest en el formato de `DerivedExample::to_pairs_string`
+    fn pest_pairs(pairs: pest::iterators::Pairs<&str>) -> String {
+        let pairs: Vec<String> = pairs
+            .map(|pair| {
+                let span = pair.as_span();
+                let inner = pest_pairs(pair.clone().into_inner());
+                if inner == "[]" {
+                    format!("{}({}, {})", pair.as_rule(), span.start(), span.end())
+                } else {
+                    format!(
+                        "{}({}, {}, {})",
+                        pair.as_rule(),
+                        span.start(),
+                        span.end(),
+                        inner
+                    )
+                }
+            })
+            .collect();
+        format!("[{}]", pairs.join(", "))
+    }
+
+    /// El árbol de derivación corresponde al texto generado y produce los mismos pares que pest
+    #[test]
+    fn derivation_tree() {
+        let grammar = r#"
+            WHITESPACE = _{ " " }
+            digit = { '0'..'9' }
+            number = @{ digit+ }
+            letter = { 'a'..'z' }
+            word = ${ letter ~ letter* }
+            add = { "+" }
+            sub = { "-" }
+            op = _{ add | sub }
+            term = { number | word | "(" ~ number ~ ")" }
+            expr = { term ~ (op ~ term)* }
+        "#;
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(5);
+        let generator = Generator::new(grammar.to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+        let vm = pest_vm::Vm::new(pest_meta::optimizer::optimize(
+            compile_grammar(grammar.to_string())
+                .unwrap()
+                .rules
+                .values()
+                .cloned()
+                .collect(),
+        ));
+
+        fn check_spans(text: &str, node: &DerivationNode) {
+            assert!(node.start <= node.end && node.end <= text.len());
+            let mut position = node.start;
+            for child in &node.children {
+                assert!(position <= child.start, "{:?}", node);
+                check_spans(text, child);
+                position = child.end;
+            }
+            assert!(position <= node.end);
+            if node.rule == "term" {
+                assert_eq!(1, node.alternatives.len());
+                let expected = match node.alternatives[0] {
+                    0 => "number",
+                    1 => "word",
+                    _ => "number",
+                };
+                // Los WHITESPACE implícitos también son nodos del árbol
+                let child = node
+                    .children
+                    .iter()
+                    .find(|child| child.rule != "WHITESPACE")
+                    .unwrap();
+                assert_eq!(expected, child.rule);
+                assert_eq!(
+                    node.alternatives[0] == 2,
+                    text[node.start..].starts_with('(')
+                );
+            }
+        }
+
+        for (index, example) in generator
+            .generate_derived_n("expr", 50)
+            .into_iter()
+            .enumerate()
+        {
+            let example = example.unwrap();
+            // El texto es el mismo que sin árbol
+            assert_eq!(
+                generator.generate_nth("expr", index as u64 + 1).unwrap(),
+                example.text
+            );
+            assert_eq!("expr", example.tree.rule);
+            assert_eq!(
+                (0, example.text.len()),
+                (example.tree.start, example.tree.end)
+            );
+            check_spans(&example.text, &example.tree);
+
+            let pairs = vm.parse("expr", &example.text).unwrap();
+            assert_eq!(pest_pairs(pairs), example.to_pairs_string());
+        }
+
+        let example = generate_derived_examples(
+            "sentence = { \"I like \" ~ language }\nlanguage = { \"Rust\" | \"Go\" }".to_string(),
+            1,
+            "sentence".to_string(),
+            &gen_config,
+        )
+        .unwrap()
+        .remove(0)
+        .unwrap();
+        let language = if example.text.ends_with("Rust") {
+            r#"{"pos":[7,11],"rule":"language","inner":"Rust"}"#
+        } else {
+            r#"{"pos":[7,9],"rule":"language","inner":"Go"}"#
+        };
+        assert_eq!(
+            format!(
+                r#"{{"pos":[0,{0}],"pairs":[{{"pos":[0,{0}],"rule":"sentence","inner":[{1}]}}]}}"#,
+                example.text.len(),
+                language
+            ),
+            example.to_pairs_json()
+        );
+    }
+
+    /// Las variantes de las semillas se pueden parsear y conservan el texto fuera del subárbol reemplazado
+    #[test]
+    fn mutate_seeds() {
+        let grammar = r#"
+            WHITESPACE = _{ " " }
+            number = @{ ('0'..'9')+ }
+            term = { number | "(" ~ expr ~ ")" }
+            expr = { term ~ (("+" | "*") ~ term){,1} }
+        "#;
+        let seeds = vec!["1 + (2 * 3)".to_string(), "(4)*5".to_string()];
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(3);
+        let generator = Generator::new(grammar.to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+
+        let examples: Vec<MutatedExample> = generator
+            .mutate_n("expr", &seeds, 20)
+            .into_iter()
+            .map(|example| example.unwrap())
+            .collect();
+        for (index, example) in examples.iter().enumerate() {
+            let seed = &seeds[index % seeds.len()];
+            assert!(generator.parse("expr", &example.text).is_ok());
+            assert_eq!(1, example.mutations.len());
+            let mutation = &example.mutations[0];
+            assert_eq!(SubtreeMutationKind::Regenerate, mutation.kind);
+            assert_eq!(
+                format!(
+                    "{}{}{}",
+                    &seed[..mutation.start],
+                    mutation.replacement,
+                    &seed[mutation.end..]
+                ),
+                example.text
+            );
+        }
+
+        // Con la misma semilla se obtienen las mismas variantes
+        let again = mutate_examples(
+            grammar.to_string(),
+            &seeds,
+            20,
+            "expr".to_string(),
+            &gen_config,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|example| example.unwrap())
+        .collect::<Vec<_>>();
+        assert_eq!(examples, again);
+
+        // En modo splice los subárboles se toman de la otra semilla
+        for example in generator.splice_n("expr", &seeds, 20) {
+            let example = example.unwrap();
+            assert!(generator.parse("expr", &example.text).is_ok());
+            let mutation = &example.mutations[0];
+            assert_eq!(SubtreeMutationKind::Splice, mutation.kind);
+            assert!(seeds
+                .iter()
+                .any(|seed| seed.contains(&mutation.replacement)));
+        }
+
+        gen_config.mutations_per_example = 3;
+        let generator = generator.with_config(gen_config);
+        let example = generator.mutate("expr", &seeds[0]).unwrap();
+        assert_eq!(3, example.mutations.len());
+        assert!(generator.parse("expr", &example.text).is_ok());
+
+        // Las semillas deben ser válidas
+        assert!(matches!(
+            generator.mutate("expr", "+ 1"),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    /// El ejemplo reducido sigue fallando y solo se prueban candidatos que la gramática puede parsear
+    #[test]
+    fn shrink_failing_example() {
+        let grammar = r#"
+            WHITESPACE = _{ " " }
+            number = @{ ('0'..'9')+ }
+            list = { "[" ~ (number | list) ~ ("," ~ (number | list))* ~ "]" }
+        "#;
+        let generator = Generator::new(grammar.to_string()).unwrap();
+
+        let mut calls = 0;
+        let shrunk = generator
+            .shrink("list", "[1, [22, 3, [843, 9]], 5, 6]", |candidate| {
+                calls += 1;
+                assert!(generator.parse("list", candidate).is_ok());
+                candidate.contains("843")
+            })
+            .unwrap();
+        assert_eq!("[843]", shrunk);
+        assert!(calls > 1);
+
+        // Los números se reducen a su derivación más corta, el texto entre los elementos se conserva
+        let shrunk = shrink_example(
+            grammar.to_string(),
+            "list".to_string(),
+            "[[12, 345], 6789]",
+            |candidate| candidate.matches(',').count() >= 2,
+        )
+        .unwrap();
+        assert_eq!("[[0, 0], 0]", shrunk);
+
+        assert!(matches!(
+            generator.shrink("list", "[1]", |_| false),
+            Err(Error::ExampleNotFailing)
+        ));
+        assert!(matches!(
+            generator.shrink("list", "1]", |_| true),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    /// Los comandos se clasifican por su forma de terminar y reciben el ejemplo por stdin o en un archivo
+    #[cfg(unix)]
+    #[test]
+    fn target_command_outcomes() {
+        use std::time::Duration;
+
+        let shell = |script: &str| TargetCommand {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            timeout: Some(Duration::from_secs(5)),
+        };
+
+        let output = shell("cat").run("I like Rust").unwrap();
+        assert_eq!(Outcome::Success, output.outcome);
+        assert_eq!(b"I like Rust".to_vec(), output.stdout);
+
+        // Con {} el ejemplo se recibe en un archivo temporal, que se elimina después
+        let output = TargetCommand::new("cat {}")
+            .unwrap()
+            .run("in a file")
+            .unwrap();
+        assert_eq!(b"in a file".to_vec(), output.stdout);
+
+        let output = shell("cat > /dev/null; echo error >&2; exit 3")
+            .run("x")
+            .unwrap();
+        assert_eq!(Outcome::ExitCode(3), output.outcome);
+        assert_eq!(b"error\n".to_vec(), output.stderr);
+        assert!(output.outcome.is_failure());
+
+        let output = shell("kill -SEGV $$").run("x").unwrap();
+        assert_eq!(Outcome::Signal(11), output.outcome);
+        assert_eq!("crash (signal 11)", output.outcome.to_string());
+
+        let command = shell("sleep 5").with_timeout(Duration::from_millis(100));
+        assert_eq!(Outcome::Timeout, command.run("x").unwrap().outcome);
+
+        // Pruebas diferenciales
+        let upper = shell("tr a-z A-Z").run("rust").unwrap();
+        let same = shell("tr a-z A-Z").run("rust").unwrap();
+        assert!(upper.differs_from(&shell("cat").run("rust").unwrap()));
+        assert!(!upper.differs_from(&same));
+
+        assert_eq!(None, TargetCommand::new("   "));
+
+        // Las comillas y los escapes se separan como en sh
+        let command = TargetCommand::new(r#"my-parser --name "a b" 'c "d"' e\ f "" {}"#).unwrap();
+        assert_eq!("my-parser", command.program);
+        assert_eq!(
+            vec!["--name", "a b", "c \"d\"", "e f", "", "{}"],
+            command.args
+        );
+        assert_eq!(None, TargetCommand::new("my-parser 'unclosed"));
+        let output = TargetCommand::new("sh -c 'tr a-z A-Z | rev'")
+            .unwrap()
+            .run("rust")
+            .unwrap();
+        assert_eq!(b"TSUR".to_vec(), output.stdout);
+        assert!(TargetCommand::new("this-command-does-not-exist")
+            .unwrap()
+            .run("x")
+            .is_err());
+    }
+
+    /// Los bytes deciden el ejemplo: los mismos bytes generan el mismo ejemplo y sin bytes se elige siempre la opción
+    /// más pequeña
+    #[test]
+    fn generate_from_byte_source() {
+        let grammar = r#"
+            digit = {'0'..'9'}
+            number = {digit+}
+            list = {"[" ~ number ~ ("," ~ number)* ~ "]"}
+            value = {list | number | "null"}
+        "#;
+        let generator = Generator::new(grammar.to_string()).unwrap();
+
+        // Sin bytes todas las decisiones toman el valor más bajo
+        assert_eq!("null", generate_from_bytes(&generator, "value", &[]));
+        assert_eq!("0", generate_from_bytes(&generator, "number", &[]));
+        assert_eq!("[0]", generate_from_bytes(&generator, "list", &[]));
+
+        // number: 1 + 1 repeticiones, digit: '5' y '9'
+        let mut source = ByteSource::new(&[1, 5, 9, 42]);
+        assert_eq!(
+            "59",
+            generator.generate_with("number", &mut source).unwrap()
+        );
+        assert_eq!(&[42], source.remaining());
+
+        for seed in 0..200u64 {
+            let data: Vec<u8> = (0..seed % 40)
+                .map(|i| (seed.wrapping_mul(31).wrapping_add(i * 17) % 256) as u8)
+                .collect();
+            let example = generate_from_bytes(&generator, "value", &data);
+            assert_eq!(example, generate_from_bytes(&generator, "value", &data));
+            assert!(generator.parse("value", &example).is_ok(), "{}", example);
+        }
+
+        // Las decisiones también se pueden tomar con cualquier generador de rand
+        let mut rng = rand::rngs::mock::StepRng::new(0, 1);
+        assert!(generator.generate_with("value", &mut rng).is_ok());
+        assert_eq!(None, ByteSource::new(&[7]).choose(0));
+        assert_eq!(2, ByteSource::new(&[7]).gen_range(2..=2));
+        assert!(!ByteSource::new(&[]).gen_bool(0.9));
+        assert!(ByteSource::new(&[255]).gen_bool(0.1));
+    }
+
+    /// Carpeta temporal única de un test, se elimina al terminar el test aunque falle
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+            let count = COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let path = std::env::temp_dir().join(format!(
+                "bulk-examples-{}-{}",
+                std::process::id(),
+                count
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        /// Ruta del archivo `name` en la carpeta
+        fn join(&self, name: &str) -> std::path::PathBuf {
+            self.0.join(name)
+        }
+
+        /// Contenido del archivo `name` de la carpeta
+        fn read(&self, name: &str) -> String {
+            std::fs::read_to_string(self.join(name)).unwrap()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Genera `quantity` ejemplos con la semilla `seed` y las salidas de `exe_config`, retorna los ejemplos generados
+    fn generate_outputs(
+        grammar: &str,
+        quantity: u32,
+        start: &str,
+        seed: u64,
+        exe_config: ExecutorConfig,
+    ) -> Vec<String> {
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(seed);
+        let exe_config = ExecutorConfig {
+            print_stdout: false,
+            return_vec: true,
+            ..exe_config
+        };
+        generate_examples(
+            grammar.to_string(),
+            quantity,
+            start.to_string(),
+            &gen_config,
+            &exe_config,
+        )
+        .unwrap()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect()
+    }
+
+    /// Ejemplos con saltos de línea, para ver que el separador los delimita
+    const LINES_GRAMMAR: &str = r#"
+        word = {"line" | "two\nlines" | "three\nshort\nlines"}
+        text = {word ~ (" " ~ word){0,3}}
+    "#;
+
+    /// En modo paralelo con `ordered_output` el archivo tiene los ejemplos en orden, cada uno seguido del separador
+    #[test]
+    fn file_output_ordered() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            LINES_GRAMMAR,
+            200,
+            "text",
+            7,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.txt")),
+                separator: "\0".to_string(),
+                ordered_output: true,
+                ..Default::default()
+            },
+        );
+
+        let content = dir.read("corpus.txt");
+        assert_eq!(expected, content.split_terminator('\0').collect::<Vec<_>>());
+        assert!(expected.iter().any(|example| example.contains('\n')));
+    }
+
+    /// Los ejemplos ordenados se generan por ventanas, el orden se mantiene entre una ventana y la siguiente
+    #[test]
+    fn file_output_ordered_across_windows() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            r#"number = { ASCII_DIGIT{1,5} }"#,
+            10000,
+            "number",
+            2,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.txt")),
+                ordered_output: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(expected.join("\n") + "\n", dir.read("corpus.txt"));
+    }
+
+    /// El separador puede tener varios caracteres y saltos de línea
+    #[test]
+    fn file_output_separator() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            LINES_GRAMMAR,
+            50,
+            "text",
+            7,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.txt")),
+                separator: "\n---\n".to_string(),
+                parallel_mode: false,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(expected.join("\n---\n") + "\n---\n", dir.read("corpus.txt"));
+    }
+
+    /// Ejemplos con comas, comillas y saltos de línea, que CSV escribe entre comillas
+    const RECORDS_GRAMMAR: &str = r#"
+        word = {"a" | "b,c" | "\"d\"\ne"}
+        text = {word ~ (" " ~ word){0,3}}
+    "#;
+
+    /// Un registro JSON Lines por ejemplo, en orden
+    #[test]
+    fn jsonl_output() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            RECORDS_GRAMMAR,
+            50,
+            "text",
+            3,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.jsonl")),
+                ordered_output: true,
+                output_format: OutputFormat::Jsonl,
+                ..Default::default()
+            },
+        );
+
+        let content = dir.read("corpus.jsonl");
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(50, lines.len());
+        for (i, (line, example)) in lines.iter().zip(&expected).enumerate() {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(i as u64 + 1, record["index"]);
+            assert_eq!("text", record["start_rule"]);
+            assert_eq!(example.as_str(), record["example"]);
+            assert_eq!(example.len() as u64, record["length"]);
+            assert_eq!(true, record["ok"]);
+            assert!(record["error"].is_null());
+            assert_eq!(3, record["seed"]);
+            assert!(record.get("rule_hits").is_none());
+        }
+    }
+
+    /// Con `rule_hits` cada registro JSON Lines tiene las veces que se expandió cada regla
+    #[test]
+    fn jsonl_output_rule_hits() {
+        let dir = TempDir::new();
+        generate_outputs(
+            RECORDS_GRAMMAR,
+            50,
+            "text",
+            3,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.jsonl")),
+                output_format: OutputFormat::Jsonl,
+                rule_hits: true,
+                ..Default::default()
+            },
+        );
+
+        for line in dir.read("corpus.jsonl").lines() {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(1, record["rule_hits"]["text"]);
+            let words = record["rule_hits"]["word"].as_u64().unwrap();
+            assert!((1..=4).contains(&words));
+        }
+    }
+
+    /// CSV con encabezado, los ejemplos con comas, comillas y saltos de línea se escriben entre comillas
+    #[test]
+    fn csv_output() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            RECORDS_GRAMMAR,
+            50,
+            "text",
+            3,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.csv")),
+                ordered_output: true,
+                output_format: OutputFormat::Csv,
+                ..Default::default()
+            },
+        );
+
+        let content = dir.read("corpus.csv");
+        let mut reader = csv::Reader::from_reader(content.as_bytes());
+        assert_eq!(
+            vec![
+                "index",
+                "start_rule",
+                "example",
+                "length",
+                "ok",
+                "error",
+                "seed"
+            ],
+            reader.headers().unwrap().iter().collect::<Vec<_>>()
+        );
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(50, records.len());
+        for (i, (record, example)) in records.iter().zip(&expected).enumerate() {
+            assert_eq!((i + 1).to_string(), record[0]);
+            assert_eq!(example, &record[2]);
+            assert_eq!(example.len().to_string(), record[3]);
+            assert_eq!("true", &record[4]);
+            assert_eq!("", &record[5]);
+            assert_eq!("3", &record[6]);
+        }
+        assert!(expected.iter().any(|example| example.contains('\n')));
+    }
+
+    /// Con `rule_hits` CSV tiene una columna de conteo por regla de la gramática
+    #[test]
+    fn csv_output_rule_hits() {
+        let dir = TempDir::new();
+        generate_outputs(
+            RECORDS_GRAMMAR,
+            50,
+            "text",
+            3,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.csv")),
+                output_format: OutputFormat::Csv,
+                rule_hits: true,
+                ..Default::default()
+            },
+        );
+
+        let content = dir.read("corpus.csv");
+        let mut reader = csv::Reader::from_reader(content.as_bytes());
+        assert_eq!(
+            vec!["hits_text", "hits_word"],
+            reader.headers().unwrap().iter().skip(7).collect::<Vec<_>>()
+        );
+        for record in reader.records() {
+            assert_eq!("1", &record.unwrap()[7]);
+        }
+    }
+
+    /// Números cortos, para contar los ejemplos y los bytes de los shards
+    const NUMBERS_GRAMMAR: &str = r#"
+        digit = {'0'..'9'}
+        number = {digit{1,5}}
+    "#;
+
+    /// Un shard nuevo empieza cada `max_examples` ejemplos y el manifiesto lista los shards con sus conteos
+    #[test]
+    fn shards_by_examples() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            NUMBERS_GRAMMAR,
+            25,
+            "number",
+            5,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus-{shard}.txt")),
+                ordered_output: true,
+                sharding: Some(Sharding {
+                    max_examples: Some(10),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&dir.read("corpus-manifest.json")).unwrap();
+        assert_eq!(25, manifest["examples"]);
+        let shards = manifest["shards"].as_array().unwrap();
+        assert_eq!(3, shards.len());
+        let mut content = String::new();
+        for (i, (shard, count)) in shards.iter().zip(&[10, 10, 5]).enumerate() {
+            let file = format!("corpus-{}.txt", i + 1);
+            assert_eq!(file.as_str(), shard["file"]);
+            assert_eq!(*count, shard["examples"]);
+            let shard_content = dir.read(&file);
+            assert_eq!(shard_content.len() as u64, shard["bytes"]);
+            content.push_str(&shard_content);
+        }
+        assert_eq!(expected.join("\n") + "\n", content);
+    }
+
+    /// Un shard nuevo empieza cuando el siguiente ejemplo pasaría de `max_bytes`, sin `{shard}` el número se añade
+    /// antes de la extensión
+    #[test]
+    fn shards_by_bytes() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            NUMBERS_GRAMMAR,
+            25,
+            "number",
+            5,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.txt")),
+                ordered_output: true,
+                sharding: Some(Sharding {
+                    max_bytes: Some(20),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&dir.read("corpus-manifest.json")).unwrap();
+        assert_eq!("corpus-1.txt", manifest["shards"][0]["file"]);
+        let mut content = String::new();
+        for shard in manifest["shards"].as_array().unwrap() {
+            assert!(shard["bytes"].as_u64().unwrap() <= 20);
+            content.push_str(&dir.read(shard["file"].as_str().unwrap()));
+        }
+        assert_eq!(expected.join("\n") + "\n", content);
+    }
+
+    /// Cada shard CSV tiene el encabezado
+    #[test]
+    fn csv_shards_header() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            NUMBERS_GRAMMAR,
+            25,
+            "number",
+            5,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus-{shard}.csv")),
+                ordered_output: true,
+                output_format: OutputFormat::Csv,
+                sharding: Some(Sharding {
+                    max_examples: Some(10),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let mut examples = vec![];
+        for shard in 1..=3 {
+            let content = dir.read(&format!("corpus-{}.csv", shard));
+            let mut reader = csv::Reader::from_reader(content.as_bytes());
+            assert_eq!("index", &reader.headers().unwrap()[0]);
+            examples.extend(
+                reader
+                    .records()
+                    .map(|record| record.unwrap()[2].to_string()),
+            );
+        }
+        assert_eq!(expected, examples);
+    }
+
+    /// El manifiesto por defecto se nombra según la plantilla, dos salidas en la misma carpeta no lo comparten, y
+    /// `manifest` lo escribe en otra ruta
+    #[test]
+    fn manifest_path() {
+        let dir = TempDir::new();
+        for template in &["first-{shard}.txt", "second.txt"] {
+            generate_outputs(
+                NUMBERS_GRAMMAR,
+                5,
+                "number",
+                5,
+                ExecutorConfig {
+                    print_file: Some(dir.join(template)),
+                    sharding: Some(Sharding {
+                        max_examples: Some(2),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            );
+        }
+        for (manifest, shard) in &[
+            ("first-manifest.json", "first-1.txt"),
+            ("second-manifest.json", "second-1.txt"),
+        ] {
+            let manifest: serde_json::Value = serde_json::from_str(&dir.read(manifest)).unwrap();
+            assert_eq!(*shard, manifest["shards"][0]["file"]);
+        }
+
+        generate_outputs(
+            NUMBERS_GRAMMAR,
+            5,
+            "number",
+            5,
+            ExecutorConfig {
+                print_file: Some(dir.join("third.txt")),
+                sharding: Some(Sharding {
+                    max_examples: Some(2),
+                    manifest: Some(dir.join("shards.json")),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        assert!(!dir.join("third-manifest.json").exists());
+        let manifest: serde_json::Value = serde_json::from_str(&dir.read("shards.json")).unwrap();
+        assert_eq!(5, manifest["examples"]);
+    }
+
+    /// Con la extensión `.gz` cada shard es un archivo gzip completo
+    #[test]
+    fn gzip_output_by_extension() {
+        use std::io::Read;
+
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            NUMBERS_GRAMMAR,
+            15,
+            "number",
+            9,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.txt.gz")),
+                ordered_output: true,
+                sharding: Some(Sharding {
+                    max_examples: Some(10),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        let mut content = String::new();
+        for shard in &["corpus-1.txt.gz", "corpus-2.txt.gz"] {
+            let file = std::fs::File::open(dir.join(shard)).unwrap();
+            flate2::read::GzDecoder::new(file)
+                .read_to_string(&mut content)
+                .unwrap();
+        }
+        assert_eq!(expected.join("\n") + "\n", content);
+    }
+
+    /// Con `compression` se comprime aunque el archivo no tenga la extensión, y la extensión se añade
+    #[test]
+    fn zstd_output_adds_extension() {
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            NUMBERS_GRAMMAR,
+            15,
+            "number",
+            9,
+            ExecutorConfig {
+                print_file: Some(dir.join("corpus.txt")),
+                ordered_output: true,
+                compression: Some(Compression::Zstd),
+                ..Default::default()
+            },
+        );
+
+        assert!(!dir.join("corpus.txt").exists());
+        let file = std::fs::File::open(dir.join("corpus.txt.zst")).unwrap();
+        let content = String::from_utf8(zstd::decode_all(file).unwrap()).unwrap();
+        assert_eq!(expected.join("\n") + "\n", content);
+    }
+
+    /// Con `tar_folder` los archivos de la carpeta se escriben en un tar, comprimido con `compression`
+    #[test]
+    fn tar_folder_output() {
+        use std::io::Read;
+
+        let dir = TempDir::new();
+        let expected = generate_outputs(
+            NUMBERS_GRAMMAR,
+            15,
+            "number",
+            9,
+            ExecutorConfig {
+                print_folder: Some(("example-{}.txt".to_string(), dir.join("examples"))),
+                tar_folder: true,
+                compression: Some(Compression::Gzip),
+                ..Default::default()
+            },
+        );
+
+        let file = std::fs::File::open(dir.join("examples.tar.gz")).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let mut files = std::collections::BTreeMap::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut example = String::new();
+            entry.read_to_string(&mut example).unwrap();
+            files.insert(name, example);
+        }
+        assert_eq!(15, files.len());
+        for (i, example) in expected.iter().enumerate() {
+            assert_eq!(Some(example), files.get(&format!("example-{}.txt", i + 1)));
+        }
+    }
+
+    /// Sink que guarda los ejemplos y los conteos de reglas recibidos, y cuántas veces se terminó
+    #[derive(Default)]
+    struct RecordingSink {
+        examples: Vec<(u32, String)>,
+        hits: Vec<std::collections::BTreeMap<String, usize>>,
+        finished: usize,
+    }
+
+    impl ExampleSink for RecordingSink {
+        fn on_example(
+            &mut self,
+            index: u32,
+            example: &Result<String, GenerationError>,
+        ) -> Result<(), Error> {
+            self.examples.push((index, example.clone().unwrap()));
+            Ok(())
+        }
+
+        fn on_example_with_hits(
+            &mut self,
+            index: u32,
+            example: &Result<String, GenerationError>,
+            rule_hits: &std::collections::BTreeMap<String, usize>,
+        ) -> Result<(), Error> {
+            self.hits.push(rule_hits.clone());
+            self.on_example(index, example)
+        }
+
+        fn finish(&mut self) -> Result<(), Error> {
+            self.finished += 1;
+            Ok(())
+        }
+    }
+
+    /// Sink que falla al recibir el ejemplo `fail_at`
+    struct FailingSink {
+        fail_at: u32,
+    }
+
+    impl ExampleSink for FailingSink {
+        fn on_example(
+            &mut self,
+            index: u32,
+            _example: &Result<String, GenerationError>,
+        ) -> Result<(), Error> {
+            if index == self.fail_at {
+                return Err(Error::Io(std::io::Error::other("sink failed")));
+            }
+            Ok(())
+        }
+    }
+
+    /// Envía 20 ejemplos de `NUMBERS_GRAMMAR` con la semilla 3 a los `sinks`
+    fn generate_to(
+        exe_config: &ExecutorConfig,
+        sinks: Vec<Box<dyn ExampleSink + Send + '_>>,
+    ) -> Result<Option<Coverage>, Error> {
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(3);
+        generate_examples_with_sinks(
+            NUMBERS_GRAMMAR.to_string(),
+            20,
+            "number".to_string(),
+            &gen_config,
+            exe_config,
+            sinks,
+        )
+    }
+
+    /// Los sinks del usuario reciben una vez cada ejemplo que retorna `generate_examples` y se terminan una vez
+    #[test]
+    fn custom_sinks() {
+        let expected = generate_outputs(NUMBERS_GRAMMAR, 20, "number", 3, Default::default());
+
+        for parallel_mode in &[true, false] {
+            let exe_config = ExecutorConfig {
+                print_stdout: false,
+                parallel_mode: *parallel_mode,
+                ..Default::default()
+            };
+            let mut recording = RecordingSink::default();
+            let mut vec = VecSink::new();
+            generate_to(
+                &exe_config,
+                vec![Box::new(&mut recording), Box::new(&mut vec)],
+            )
+            .unwrap();
+
+            assert_eq!(1, recording.finished);
+            assert!(recording.hits.is_empty());
+            recording.examples.sort();
+            let examples: Vec<String> = recording.examples.into_iter().map(|(_, e)| e).collect();
+            assert_eq!(expected, examples);
+            let examples: Vec<String> = vec
+                .into_examples()
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(expected, examples);
+        }
+    }
+
+    /// Las salidas de la configuración se escriben junto con los sinks del usuario
+    #[test]
+    fn custom_sinks_with_config_outputs() {
+        let dir = TempDir::new();
+        std::fs::create_dir(dir.join("examples")).unwrap();
+        let exe_config = ExecutorConfig {
+            print_stdout: false,
+            print_folder: Some(("example-{}.txt".to_string(), dir.join("examples"))),
+            ..Default::default()
+        };
+        let mut vec = VecSink::new();
+        generate_to(&exe_config, vec![Box::new(&mut vec)]).unwrap();
+
+        for (i, example) in vec.into_examples().into_iter().enumerate() {
+            let file = format!("examples/example-{}.txt", i + 1);
+            assert_eq!(example.unwrap(), dir.read(&file));
+        }
+    }
+
+    /// Con `rule_hits` los sinks reciben los conteos de las reglas en `on_example_with_hits`, aunque el formato sea
+    /// texto
+    #[test]
+    fn custom_sinks_rule_hits() {
+        let exe_config = ExecutorConfig {
+            print_stdout: false,
+            rule_hits: true,
+            ..Default::default()
+        };
+        let mut recording = RecordingSink::default();
+        generate_to(&exe_config, vec![Box::new(&mut recording)]).unwrap();
+
+        assert_eq!(20, recording.hits.len());
+        assert!(recording.hits.iter().all(|hits| hits["number"] == 1));
+        assert_eq!(20, recording.examples.len());
+    }
+
+    /// Si un sink falla el error se retorna, pero igual se terminan todos los sinks
+    #[test]
+    fn failing_sink_finishes_every_sink() {
+        for parallel_mode in &[true, false] {
+            let exe_config = ExecutorConfig {
+                print_stdout: false,
+                parallel_mode: *parallel_mode,
+                ..Default::default()
+            };
+            let mut before = RecordingSink::default();
+            let mut after = RecordingSink::default();
+            let result = generate_to(
+                &exe_config,
+                vec![
+                    Box::new(&mut before),
+                    Box::new(FailingSink { fail_at: 3 }),
+                    Box::new(&mut after),
+                ],
+            );
+
+            match result {
+                Err(Error::Io(e)) => assert_eq!("sink failed", e.to_string()),
+                other => panic!("Expected the error of the sink, got {:?}", other),
+            }
+            assert_eq!(1, before.finished);
+            assert_eq!(1, after.finished);
+            // El ejemplo 3 no llega a los sinks siguientes
+            assert!(after.examples.iter().all(|(i, _)| *i != 3));
+        }
+    }
+
+    /// Un `FileSink` usado directamente escribe el mismo archivo que `print_file`
+    #[test]
+    fn file_sink() {
+        let dir = TempDir::new();
+        let exe_config = ExecutorConfig {
+            print_stdout: false,
+            parallel_mode: false,
+            output_format: OutputFormat::Jsonl,
+            ..Default::default()
+        };
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(3);
+        let generator = Generator::new(NUMBERS_GRAMMAR.to_string())
+            .unwrap()
+            .with_config(gen_config);
+        let sink =
+            FileSink::new(&generator, "number", &dir.join("sink.jsonl"), &exe_config).unwrap();
+        generate_to(&exe_config, vec![Box::new(sink)]).unwrap();
+
+        let exe_config = ExecutorConfig {
+            print_file: Some(dir.join("config.jsonl")),
+            ..exe_config
+        };
+        generate_to(&exe_config, vec![]).unwrap();
+        assert_eq!(dir.read("config.jsonl"), dir.read("sink.jsonl"));
+    }
+
+    /// Un `Generator` compilado una vez debe generar ejemplos válidos para su gramática
+    #[test]
+    fn generator_reuse() {
+        let grammar = r#"
+            Text = {"Random text"}
+            FlowContent = {Header | Main | Form | Section | Text }
+            Header = {"<header>" ~ "|BLACKLIST|I|Main|" ~ FlowContent* ~ "|BLACKLIST|R|Main|" ~ "</header>"}
+            Main = {"<main>" ~  FlowContent+ ~ "</main>"}
+            Form = {"<form>" ~ "|BLACKLIST|I|Form|" ~ FlowContent* ~ "|BLACKLIST|R|Form|" ~ "</form>"}
+            Section = {"<section>" ~ FlowContent+ ~ "</section>"}
+        "#;
+
+        // Repeticiones acotadas para que los ejemplos no sean demasiado profundos para el parser
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(1);
+        gen_config.upper_bound_zero_or_more_repetition = 2;
+        gen_config.upper_bound_one_or_more_repetition = 2;
+
+        let generator = Generator::new(grammar.to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+        for example in generator.generate_n("FlowContent", 50) {
+            let example = example.unwrap();
+            assert!(!example.contains("BLACKLIST"));
+            assert!(generator.parse("FlowContent", &example).is_ok());
+        }
+
+        // Construido a partir de una gramática ya compilada genera los mismos ejemplos
+        let from_grammar = Generator::from_grammar(compile_grammar(grammar.to_string()).unwrap())
+            .with_config(gen_config);
+        assert_eq!(
+            generator.generate_n("FlowContent", 20),
+            from_grammar.generate_n("FlowContent", 20)
+        );
+
+        // `generate` devuelve los ejemplos en orden
+        assert_eq!(
+            generator.generate("FlowContent"),
+            generator.generate_nth("FlowContent", 1)
+        );
+        assert_eq!(
+            generator.generate("FlowContent"),
+            generator.generate_nth("FlowContent", 2)
+        );
+    }
+
+    /// Las gramáticas inválidas y las reglas inexistentes deben retornar un error en lugar de un panic
+    #[test]
+    fn typed_errors() {
+        let gen_config: GeneratorConfig = Default::default();
+        let exe_config = ExecutorConfig {
+            print_stdout: false,
+            ..Default::default()
+        };
+
+        // Error de sintaxis
+        match generate_examples(
+            "rule = { \"a\" ".to_string(),
+            1,
+            "rule".to_string(),
+            &gen_config,
+            &exe_config,
+        ) {
+            Err(Error::GrammarSyntax(error)) => assert_eq!((0, 13), error.from),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // Regla no definida
+        match compile_grammar("rule = { other }".to_string()) {
+            Err(Error::GrammarValidation(errors)) => assert_eq!(1, errors.len()),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // Regla inicial inexistente
+        match generate_examples(
+            "rule = { \"a\" }".to_string(),
+            1,
+            "cookies".to_string(),
+            &gen_config,
+            &exe_config,
+        ) {
+            Err(Error::UnknownStartRule(rule)) => assert_eq!("cookies", rule),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!(matches!(
+            ExampleStream::new(
+                "rule = { \"a\" }".to_string(),
+                "cookies".to_string(),
+                Default::default()
+            ),
+            Err(Error::UnknownStartRule(_))
+        ));
+        assert!(matches!(
+            ExampleStream::parallel(
+                "rule = { \"a\" }".to_string(),
+                "cookies".to_string(),
+                Default::default(),
+                8
+            ),
+            Err(Error::UnknownStartRule(_))
+        ));
+
+        // Error de parseo con la posición
+        let g =
+            compile_grammar("rule = { \"a\" ~ NEWLINE ~ b } b = { \"b\" }".to_string()).unwrap();
+        match parse_input(g, "rule".to_string(), "a\nc".to_string()) {
+            Err(Error::Parse(error)) => {
+                assert_eq!("rule", error.rule);
+                assert_eq!((1, 0), error.from);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // Negación imposible de cumplir
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.max_attempts_negation = 5;
+        let generator = Generator::new("a = { \"a\" } b = { !a ~ a }".to_string())
+            .unwrap()
+            .with_config(gen_config);
+        match generator.generate("b") {
+            Err(GenerationError::NegationAttemptsExhausted(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // Predicado positivo imposible de cumplir
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.max_attempts_negation = 5;
+        let generator = Generator::new("a = { \"a\" } b = { &a ~ \"b\" }".to_string())
+            .unwrap()
+            .with_config(gen_config);
+        match generator.generate("b") {
+            Err(GenerationError::LookaheadAttemptsExhausted(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // Limite alcanzado con error_on_limit
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.hard_limit = 10;
+        gen_config.error_on_limit = true;
+        let generator = Generator::new("a = { \"a\"{20} }".to_string())
+            .unwrap()
+            .with_config(gen_config);
+        assert_eq!(
+            Err(GenerationError::LimitReached("hard_limit".to_string())),
+            generator.generate("a")
+        );
+    }
+
     // TODO: Hacer test para probar la función init_grammar que tiene grammar y grammar_clean
 }
 
@@ -79,7 +1819,22 @@ mod test {
 mod pest_rules {
     use bulk_examples_generator::config::*;
     use bulk_examples_generator::parse_input;
-    use bulk_examples_generator::{compile_grammar, generate_examples};
+    use bulk_examples_generator::{compile_grammar, generate_examples, Generator};
+
+    /// Unicode built-in rules (XID_START, GREEK, ...) generate codepoints of their property
+    #[test]
+    fn char_range_unicode_builtin_rules() {
+        boilerplate_test_grammar!(
+            r#"
+            identifier = { XID_START ~ XID_CONTINUE{,8} }
+            number = { DECIMAL_NUMBER{1,3} }
+            greek = { GREEK{1,3} }
+            sentence = { identifier ~ WHITE_SPACE ~ (number | greek | UPPERCASE_LETTER | MATH_SYMBOL) }
+            "#,
+            "sentence",
+            200
+        );
+    }
 
     /// expr{n} exactly n repetitions
     #[test]
@@ -197,9 +1952,6 @@ mod pest_rules {
 
     #[test]
     fn negation_char() {
-        //TODO este test debería ser
-        // Line = { " "{5} ~ (!(" " | "0") ~ alphabet_numbers) ~ ASCII_ALPHA{,15} }
-        // sin embargo actualmente solo se procesan idents en la negación "!"
         boilerplate_test_grammar!(
             r#"
                 alphabet_numbers = { "a" | "b" | "c" | "d" | "e" | "f" | "g" | "h" 
@@ -207,8 +1959,7 @@ mod pest_rules {
                     | "r" | "s" | "t" | "u" | "v" | "w" | "x" | "y" | "z"
                     | "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8"
                     | "9" | " "}
-                forbidden_chars = {" " | "0"}
-                Line = { " "{5} ~ (!forbidden_chars ~ alphabet_numbers) ~ ASCII_ALPHA{,15} }
+                Line = { " "{5} ~ (!(" " | "0") ~ alphabet_numbers) ~ ASCII_ALPHA{,15} }
             "#,
             "Line",
             500
@@ -221,7 +1972,7 @@ mod pest_rules {
             r#"
                 Etiqueta = { Parrafo | Enlace | Texto }
                 Parrafo = { "<p>" ~ Texto ~ "</p>" ~ (Parrafo | "") }
-                Enlace = { "<a>" ~ (Texto | Parrafo) ~ "</a>" }
+                Enlace = { "<a>" ~ (Parrafo | Texto) ~ "</a>" }
                 Texto = { ASCII_ALPHA{,15} }
                 EtiquetaSinEnlace = { !Enlace ~ Etiqueta }
                 "#,
@@ -240,6 +1991,109 @@ mod pest_rules {
             500
         );
     }
+
+    #[test]
+    fn positive_predicate() {
+        boilerplate_test_grammar!(
+            r#"
+                vowel = { "a" | "e" | "i" | "o" | "u" }
+                word = { ASCII_ALPHA_LOWER{1,5} }
+                vowel_word = { &vowel ~ word }
+            "#,
+            "vowel_word",
+            200
+        );
+
+        boilerplate_test_grammar!(
+            r#"
+                word = { "if" | "else" | "for" | "while" }
+                condition = { &("if" | "else") ~ word ~ " " ~ word }
+            "#,
+            "condition",
+            200
+        );
+    }
+
+    #[test]
+    fn implicit_whitespace() {
+        let grammar = r##"
+            WHITESPACE = _{ " " | "\t" }
+            COMMENT = _{ "#" ~ ASCII_ALPHA_LOWER{,5} ~ "\n" }
+            number = @{ ASCII_DIGIT{1,4} }
+            list = { "[" ~ number ~ ("," ~ number)* ~ "]" }
+        "##;
+        boilerplate_test_grammar!(grammar, "list", 200);
+
+        let mut gen_config: GeneratorConfig = Default::default();
+        gen_config.seed = Some(1);
+        let generator = Generator::new(grammar.to_string())
+            .unwrap()
+            .with_config(gen_config.clone());
+        let examples: Vec<String> = generator
+            .generate_n("list", 100)
+            .into_iter()
+            .map(|example| example.unwrap())
+            .collect();
+        assert!(examples.iter().any(|example| example.contains(' ')));
+        assert!(examples.iter().any(|example| example.contains('#')));
+
+        // Dentro de la regla atómica `number` no se genera WHITESPACE ni COMMENT
+        for example in generator.generate_n("number", 100) {
+            assert!(example.unwrap().chars().all(|c| c.is_ascii_digit()));
+        }
+
+        // Con probabilidad 0 (o un valor que no es finito) no se genera nada implícito
+        for probability in &[0.0, f64::NAN] {
+            gen_config.implicit_whitespace_probability = *probability;
+            let generator = generator.clone().with_config(gen_config.clone());
+            for example in generator.generate_n("list", 100) {
+                let example = example.unwrap();
+                assert!(!example.contains(' ') && !example.contains('#'));
+            }
+        }
+    }
+
+    #[test]
+    fn stack_push_pop() {
+        boilerplate_test_grammar!(
+            r##"
+                raw_string = { "r" ~ PUSH("#"{,3}) ~ "\"" ~ ASCII_ALPHA{,10} ~ "\"" ~ POP }
+            "##,
+            "raw_string",
+            200
+        );
+
+        boilerplate_test_grammar!(
+            r#"
+                element = { "<" ~ PUSH(tag) ~ ">" ~ content ~ "</" ~ POP ~ ">" }
+                tag = { ASCII_ALPHA_LOWER{1,5} }
+                content = { ASCII_DIGIT{,3} ~ element? }
+            "#,
+            "element",
+            200
+        );
+    }
+
+    #[test]
+    fn stack_peek_and_drop() {
+        boilerplate_test_grammar!(
+            r#"
+                stack = {
+                    PUSH(ASCII_DIGIT) ~ PUSH(ASCII_ALPHA_LOWER{1,3}) ~ "|" ~ PEEK_ALL ~ "|" ~ PEEK[..1] ~ "|" ~ PEEK[-1..]
+                    ~ "|" ~ PEEK ~ DROP ~ "|" ~ POP_ALL
+                }
+            "#,
+            "stack",
+            200
+        );
+
+        let generator = Generator::new(
+            r#"stack = { PUSH("1") ~ PUSH("ab") ~ PEEK_ALL ~ PEEK[..1] ~ PEEK[-1..] ~ DROP ~ POP }"#
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!("1abab11ab1", generator.generate("stack").unwrap());
+    }
 }
 
 // #[cfg(test)]